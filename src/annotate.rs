@@ -0,0 +1,213 @@
+use crate::display::eval_text;
+use crate::eval::{calc_board_value, favour};
+use crate::game_state::GameState;
+use crate::piece_values::PAWN;
+use crate::search::{game_search_config, is_mate_score, search, SearchConfig, MATE_SCORE};
+use crate::{json, pgn};
+use chess::{BoardStatus, ChessMove};
+
+/// Scores past this many centipawns either way count as the same for the
+/// loss, so a mate doesn't dwarf every other miss; a move that lets a mate
+/// in or lets one go is judged by `changes_mate` instead.
+const LOSS_CLAMP: i64 = 10 * PAWN;
+
+/// How badly a move threw away the evaluation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    /// The judgement for a move that lost `loss` centipawns, if any.
+    fn of(loss: i64) -> Option<Judgement> {
+        match loss {
+            l if l >= 2 * PAWN => Some(Judgement::Blunder),
+            l if l >= PAWN => Some(Judgement::Mistake),
+            l if l >= PAWN / 2 => Some(Judgement::Inaccuracy),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "inaccuracy",
+            Judgement::Mistake => "mistake",
+            Judgement::Blunder => "blunder",
+        }
+    }
+
+    /// The PGN numeric annotation glyph: `?!`, `?` and `??`.
+    fn nag(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "$6",
+            Judgement::Mistake => "$2",
+            Judgement::Blunder => "$4",
+        }
+    }
+}
+
+/// Whether a move taking the score from `before` to `after`, both for the
+/// side that played it, let the other side force mate or gave up a forced
+/// mate of its own.
+fn changes_mate(before: i64, after: i64) -> bool {
+    let mating = |score: i64| is_mate_score(score) && score > 0;
+    let mated = |score: i64| is_mate_score(score) && score < 0;
+    mated(after) && !mated(before) || mating(before) && !mating(after)
+}
+
+/// What the engine made of one move of the game. Scores are in
+/// centipawns from White's point of view.
+pub struct MoveReport {
+    pub san: String,
+    /// The position after the move.
+    pub score: i64,
+    /// The engine's choice instead, and the position before the move.
+    pub best: Option<String>,
+    pub best_score: i64,
+    /// Centipawns the side that moved gave away, 0 for the best move.
+    pub loss: i64,
+    pub judgement: Option<Judgement>,
+}
+
+/// Score of the current position of `game`, positive for White. Finished
+/// games are scored as they stand, without a search.
+fn position_score(game: &GameState, depth: i8, config: &SearchConfig) -> (i64, Option<ChessMove>) {
+    let board = game.board();
+    if board.status() != BoardStatus::Ongoing {
+        return (calc_board_value(&board, &config.eval), None);
+    }
+    if game.draw_by_rule().is_some() {
+        return (0, None);
+    }
+    let result = search(&board, depth, &game_search_config(game, config));
+    (result.score, result.best_move)
+}
+
+/// Searches every position of `game` to `depth` and judges each move by
+/// how far the score fell for the side that played it.
+pub fn analyze_game(game: &GameState, depth: i8, config: &SearchConfig) -> Vec<MoveReport> {
+    let (start, clocks) = game.start();
+    let mut replay = GameState::new(start, clocks);
+    let (mut score, mut best) = position_score(&replay, depth, config);
+    let mut reports = Vec::new();
+    for &mv in game.moves() {
+        let board = replay.board();
+        let side = favour(board.side_to_move());
+        let san = pgn::san(&board, mv);
+        let best_san = best.filter(|&b| b != mv).map(|b| pgn::san(&board, b));
+        replay.make_move(mv);
+        let (after, next_best) = position_score(&replay, depth, config);
+        let clamp = |s: i64| s.clamp(-LOSS_CLAMP, LOSS_CLAMP);
+        let loss = match best_san {
+            Some(_) => (side * (clamp(score) - clamp(after))).max(0),
+            None => 0,
+        };
+        let judgement = match best_san {
+            Some(_) if changes_mate(side * score, side * after) => Some(Judgement::Blunder),
+            _ => Judgement::of(loss),
+        };
+        reports.push(MoveReport {
+            san,
+            score: after,
+            best: best_san,
+            best_score: score,
+            loss,
+            judgement,
+        });
+        (score, best) = (after, next_best);
+    }
+    reports
+}
+
+/// `eval_text` of a score, which for a side already mated has no moves
+/// left to count.
+fn score_text(score: i64) -> String {
+    if score.abs() == MATE_SCORE {
+        "Checkmate".to_string()
+    } else {
+        eval_text(score)
+    }
+}
+
+/// The PGN annotation of one move: the score after it, and for a poor move
+/// its glyph and the move the engine preferred.
+fn annotation(report: &MoveReport) -> String {
+    match (report.judgement, &report.best) {
+        (Some(judgement), Some(best)) => format!(
+            "{} {{{}. {}: {} was best at {}}}",
+            judgement.nag(),
+            score_text(report.score),
+            capitalised(judgement.name()),
+            best,
+            eval_text(report.best_score)
+        ),
+        _ => format!("{{{}}}", score_text(report.score)),
+    }
+}
+
+fn capitalised(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The game's result from its final position, `*` while it goes on.
+fn result(game: &GameState) -> &'static str {
+    let board = game.board();
+    match board.status() {
+        BoardStatus::Ongoing if game.draw_by_rule().is_none() => "*",
+        _ => crate::tuning::game_result(&board),
+    }
+}
+
+/// Prints `reports` for `game`: an annotated PGN followed by each side's
+/// tally, or one JSON object per move and a summary with `json`.
+pub fn print_report(game: &GameState, reports: &[MoveReport], json: bool) {
+    let (start, _) = game.start();
+    let white_first = start.side_to_move() == chess::Color::White;
+    // Index 0 counts White's misses and 1 Black's, in Judgement order.
+    let mut tally = [[0; 3]; 2];
+    for (ply, report) in reports.iter().enumerate() {
+        let side = usize::from((ply % 2 == 0) != white_first);
+        if let Some(judgement) = report.judgement {
+            tally[side][judgement as usize] += 1;
+        }
+        if json {
+            let line = json::Object::new()
+                .raw("ply", ply + 1)
+                .str("move", &report.san)
+                .raw("white_cp", report.score)
+                .opt_str("best", report.best.as_ref())
+                .raw("best_white_cp", report.best_score)
+                .raw("loss_cp", report.loss)
+                .opt_str("judgement", report.judgement.map(Judgement::name));
+            println!("{}", line);
+        }
+    }
+    let annotations: Vec<String> = reports.iter().map(annotation).collect();
+    if json {
+        for (name, counts) in ["white", "black"].iter().zip(tally) {
+            let summary = json::Object::new()
+                .str("side", name)
+                .raw("inaccuracies", counts[0])
+                .raw("mistakes", counts[1])
+                .raw("blunders", counts[2]);
+            println!("{}", summary);
+        }
+        return;
+    }
+    print!(
+        "{}",
+        pgn::write_annotated_game(game, "Analysis", 1, ("?", "?"), result(game), &annotations)
+    );
+    for (name, counts) in ["White", "Black"].iter().zip(tally) {
+        println!(
+            "{}: {} inaccuracies, {} mistakes, {} blunders",
+            name, counts[0], counts[1], counts[2]
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use chess::{
+    between, get_bishop_moves, get_bishop_rays, get_king_moves, get_knight_moves, get_pawn_attacks,
+    get_rook_moves, get_rook_rays, BitBoard, Board, Color, Piece, Square, EMPTY,
+};
+
+/// Squares attacked by the piece on `sq`, given the blockers on `board`.
+pub fn piece_attacks(board: &Board, piece: Piece, colour: Color, sq: Square) -> BitBoard {
+    let blockers = *board.combined();
+    match piece {
+        Piece::Pawn => get_pawn_attacks(sq, colour, !EMPTY),
+        Piece::Knight => get_knight_moves(sq),
+        Piece::Bishop => get_bishop_moves(sq, blockers),
+        Piece::Rook => get_rook_moves(sq, blockers),
+        Piece::Queen => get_bishop_moves(sq, blockers) | get_rook_moves(sq, blockers),
+        Piece::King => get_king_moves(sq),
+    }
+}
+
+/// Every square attacked by at least one of `colour`'s pieces.
+pub fn attacked_squares(board: &Board, colour: Color) -> BitBoard {
+    let mut attacked = EMPTY;
+    for sq in *board.color_combined(colour) {
+        if let Some(piece) = board.piece_on(sq) {
+            attacked |= piece_attacks(board, piece, colour, sq);
+        }
+    }
+    attacked
+}
+
+/// How many of `colour`'s pieces attack each square, indexed like
+/// `Square::to_index`.
+pub fn control_counts(board: &Board, colour: Color) -> [u32; 64] {
+    let mut counts = [0; 64];
+    for sq in *board.color_combined(colour) {
+        if let Some(piece) = board.piece_on(sq) {
+            for target in piece_attacks(board, piece, colour, sq) {
+                counts[target.to_index()] += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// Pieces of both colours attacking `sq`, treating only `occupied` as
+/// blockers and as candidate attackers, so captured pieces can be lifted
+/// off the board to reveal x-rays.
+pub fn attackers_to(board: &Board, sq: Square, occupied: BitBoard) -> BitBoard {
+    let diagonal = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let straight = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let pawns = board.pieces(Piece::Pawn);
+    let attackers = (get_bishop_moves(sq, occupied) & diagonal)
+        | (get_rook_moves(sq, occupied) & straight)
+        | (get_knight_moves(sq) & board.pieces(Piece::Knight))
+        | (get_king_moves(sq) & board.pieces(Piece::King))
+        | (get_pawn_attacks(sq, Color::White, !EMPTY) & pawns & board.color_combined(Color::Black))
+        | (get_pawn_attacks(sq, Color::Black, !EMPTY) & pawns & board.color_combined(Color::White));
+    attackers & occupied
+}
+
+/// `colour`'s pieces that are pinned to their own king by an enemy slider.
+pub fn pinned(board: &Board, colour: Color) -> BitBoard {
+    let king = board.king_square(colour);
+    let enemy = board.color_combined(!colour);
+    let diagonal = (board.pieces(Piece::Bishop) | board.pieces(Piece::Queen)) & enemy;
+    let straight = (board.pieces(Piece::Rook) | board.pieces(Piece::Queen)) & enemy;
+    let pinners = (get_bishop_rays(king) & diagonal) | (get_rook_rays(king) & straight);
+
+    let mut pinned = EMPTY;
+    for pinner in pinners {
+        let blockers = between(king, pinner) & board.combined();
+        if blockers.popcnt() == 1 {
+            pinned |= blockers & board.color_combined(colour);
+        }
+    }
+    pinned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{get_rank, Rank};
+
+    #[test]
+    fn white_covers_the_third_rank_from_the_start() {
+        let attacked = attacked_squares(&Board::default(), Color::White);
+        assert_eq!(attacked & get_rank(Rank::Third), get_rank(Rank::Third));
+        assert_eq!(attacked & get_rank(Rank::Fourth), EMPTY);
+    }
+}
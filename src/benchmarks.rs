@@ -1,4 +1,5 @@
-pub const DEPTHS: &[i8] = &[1,2,3,4,5,6,7];
-pub const CASES: &[(&str, &str)] = &[
-    ("Test1", "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
-];
\ No newline at end of file
+pub const DEPTHS: &[i8] = &[1, 2, 3, 4, 5, 6, 7];
+pub const CASES: &[(&str, &str)] = &[(
+    "Test1",
+    "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+)];
@@ -0,0 +1,173 @@
+use crate::game_state::GameState;
+use crate::rng::Rng;
+use chess::{Board, ChessMove, Color, MoveGen};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+/// Plies from the start of a game that are recorded and looked up.
+pub const BOOK_PLIES: usize = 16;
+
+/// A move whose line scored below this share of the points, over at least
+/// `REFUTED_GAMES` games, is left out of the search.
+const REFUTED_SCORE: f64 = 0.25;
+const REFUTED_GAMES: u32 = 3;
+
+/// Scale of the integer weights a move is drawn by.
+const WEIGHT_SCALE: f64 = 1000.0;
+
+/// How the games through one position ended.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Record {
+    pub white: u32,
+    pub draws: u32,
+    pub black: u32,
+}
+
+impl Record {
+    pub fn games(&self) -> u32 {
+        self.white + self.draws + self.black
+    }
+
+    /// Share of the points `colour` took, with one win and one loss
+    /// counted in up front, so a line played once isn't taken as proven.
+    fn expected(&self, colour: Color) -> f64 {
+        let wins = match colour {
+            Color::White => self.white,
+            Color::Black => self.black,
+        };
+        (wins as f64 + self.draws as f64 / 2.0 + 1.0) / (self.games() as f64 + 2.0)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(u64, Record)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [hash, white, draws, black] = fields.as_slice() else {
+        return None;
+    };
+    let record = Record {
+        white: white.parse().ok()?,
+        draws: draws.parse().ok()?,
+        black: black.parse().ok()?,
+    };
+    Some((u64::from_str_radix(hash, 16).ok()?, record))
+}
+
+/// Positions from the openings of self play games, keyed by their Zobrist
+/// hash, with how those games ended. The file holds one position a line:
+///
+/// ```text
+/// 823c9b50fd114196 12 30 7
+/// ```
+///
+/// the hash in hex, then White's wins, the draws and Black's wins.
+#[derive(Default)]
+pub struct LearnedBook {
+    positions: HashMap<u64, Record>,
+}
+
+impl LearnedBook {
+    /// Reads the book in `path`; a file not written yet is an empty book.
+    pub fn load(path: &str) -> io::Result<LearnedBook> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut book = LearnedBook::default();
+        for (idx, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some((hash, record)) = parse_line(line) else {
+                return Err(io::Error::other(format!("bad line {}: {}", idx + 1, line)));
+            };
+            book.positions.insert(hash, record);
+        }
+        Ok(book)
+    }
+
+    /// Writes the book to `path`, sorted by hash so the file diffs well.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut positions: Vec<(&u64, &Record)> = self.positions.iter().collect();
+        positions.sort_by_key(|&(hash, _)| *hash);
+        let text: String = positions
+            .iter()
+            .map(|(hash, r)| format!("{:016x} {} {} {}\n", hash, r.white, r.draws, r.black))
+            .collect();
+        fs::write(path, text)
+    }
+
+    pub fn known_positions(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Counts `result` for the positions reached in the first `BOOK_PLIES`
+    /// plies of `game`. Unfinished games, `*`, count for nothing.
+    pub fn record_game(&mut self, game: &GameState, result: &str) {
+        let (white, draws, black) = match result {
+            "1-0" => (1, 0, 0),
+            "1/2-1/2" => (0, 1, 0),
+            "0-1" => (0, 0, 1),
+            _ => return,
+        };
+        for board in game.positions().iter().skip(1).take(BOOK_PLIES) {
+            let record = self.positions.entry(board.get_hash()).or_default();
+            record.white += white;
+            record.draws += draws;
+            record.black += black;
+        }
+    }
+
+    /// A move for `board`, `ply` plies into the game, drawn among the
+    /// moves to positions in the book with odds by the square of how well
+    /// they scored for the side to move. Searching instead stays in the
+    /// draw at the odds of an even line, so new moves still get tried;
+    /// that, and positions the book doesn't know, give `None`.
+    pub fn pick(&self, board: &Board, ply: usize, rng: &mut Rng) -> Option<ChessMove> {
+        if ply >= BOOK_PLIES {
+            return None;
+        }
+        let weight = |expected: f64| (WEIGHT_SCALE * expected * expected) as u64;
+        let us = board.side_to_move();
+        let known: Vec<(ChessMove, u64)> = MoveGen::new_legal(board)
+            .filter_map(|mv| {
+                let record = self.positions.get(&board.make_move_new(mv).get_hash())?;
+                Some((mv, weight(record.expected(us))))
+            })
+            .collect();
+        if known.is_empty() {
+            return None;
+        }
+        let total: u64 = known.iter().map(|&(_, w)| w).sum::<u64>() + weight(0.5);
+        let mut draw = rng.below(total);
+        for (mv, w) in known {
+            if draw < w {
+                return Some(mv);
+            }
+            draw -= w;
+        }
+        None
+    }
+
+    /// Moves from `board` the engine kept losing with, for the search to
+    /// pass over so it tries something else. Leaves out none if that
+    /// would be every move.
+    pub fn refuted(&self, board: &Board, ply: usize) -> Vec<ChessMove> {
+        if ply >= BOOK_PLIES {
+            return Vec::new();
+        }
+        let us = board.side_to_move();
+        let refuted: Vec<ChessMove> = MoveGen::new_legal(board)
+            .filter(|&mv| {
+                self.positions
+                    .get(&board.make_move_new(mv).get_hash())
+                    .is_some_and(|r| r.games() >= REFUTED_GAMES && r.expected(us) < REFUTED_SCORE)
+            })
+            .collect();
+        if refuted.len() == MoveGen::new_legal(board).len() {
+            return Vec::new();
+        }
+        refuted
+    }
+}
@@ -0,0 +1,401 @@
+use crate::attacks;
+use crate::fen;
+use crate::game_state::Clocks;
+use chess::{between, BitBoard, Board, BoardBuilder, ChessMove, Color, File, MoveGen, Piece};
+use chess::{Rank, Square, ALL_COLORS, ALL_FILES, EMPTY};
+use std::str::FromStr;
+
+/// The two ways a king can castle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    King,
+    Queen,
+}
+
+impl Side {
+    pub fn san(self) -> &'static str {
+        match self {
+            Side::King => "O-O",
+            Side::Queen => "O-O-O",
+        }
+    }
+
+    /// Where the king and then the rook end up, as in classical chess.
+    fn destinations(self) -> (File, File) {
+        match self {
+            Side::King => (File::G, File::F),
+            Side::Queen => (File::C, File::D),
+        }
+    }
+}
+
+fn back_rank(colour: Color) -> Rank {
+    match colour {
+        Color::White => Rank::First,
+        Color::Black => Rank::Eighth,
+    }
+}
+
+/// A castle written as the king taking its own rook, the Chess960 way,
+/// with the squares the two pieces go from and to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Castle {
+    pub side: Side,
+    pub king: (Square, Square),
+    pub rook: (Square, Square),
+}
+
+impl Castle {
+    fn new(side: Side, king: Square, rook: Square) -> Castle {
+        let (king_to, rook_to) = side.destinations();
+        let rank = king.get_rank();
+        Castle {
+            side,
+            king: (king, Square::make_square(rank, king_to)),
+            rook: (rook, Square::make_square(rank, rook_to)),
+        }
+    }
+
+    /// Every square between where the king and rook start and end must be
+    /// empty but for the two of them, and the king may not start in, pass
+    /// through or land on an attacked square. The rook is lifted off the
+    /// board for the attack test, since it would no longer block a check
+    /// along the back rank once it has moved.
+    fn is_legal(&self, board: &Board) -> bool {
+        let us = board.side_to_move();
+        let (king, king_to) = self.king;
+        let (rook, rook_to) = self.rook;
+        if board.piece_on(rook) != Some(Piece::Rook) || board.color_on(rook) != Some(us) {
+            return false;
+        }
+        let occupied =
+            *board.combined() ^ BitBoard::from_square(king) ^ BitBoard::from_square(rook);
+        let path = between(king, king_to) | BitBoard::from_square(king_to);
+        let span = path | between(rook, rook_to) | BitBoard::from_square(rook_to);
+        if span & occupied != EMPTY {
+            return false;
+        }
+        let enemy = *board.color_combined(!us);
+        (path | BitBoard::from_square(king))
+            .into_iter()
+            .all(|sq| attacks::attackers_to(board, sq, occupied) & enemy == EMPTY)
+    }
+
+    /// `board` once the castle is played.
+    fn play(&self, board: &Board) -> Board {
+        let us = board.side_to_move();
+        let mut builder = BoardBuilder::from(board);
+        builder
+            .clear_square(self.king.0)
+            .clear_square(self.rook.0)
+            .piece(self.king.1, Piece::King, us)
+            .piece(self.rook.1, Piece::Rook, us)
+            .side_to_move(!us)
+            .en_passant(None);
+        Board::try_from(builder).expect("a legal castle leaves a legal position")
+    }
+}
+
+/// The castle `mv` plays on `board`, if it moves the king onto one of its
+/// own rooks.
+pub fn castle(board: &Board, mv: ChessMove) -> Option<Castle> {
+    let (king, rook) = (mv.get_source(), mv.get_dest());
+    let us = board.side_to_move();
+    if board.piece_on(king) != Some(Piece::King)
+        || board.piece_on(rook) != Some(Piece::Rook)
+        || board.color_on(rook) != Some(us)
+    {
+        return None;
+    }
+    let side = if rook.get_file() > king.get_file() {
+        Side::King
+    } else {
+        Side::Queen
+    };
+    Some(Castle::new(side, king, rook))
+}
+
+/// `board` after `mv`, which may be a castle onto the king's own rook.
+pub fn make_move_new(board: &Board, mv: ChessMove) -> Board {
+    match castle(board, mv) {
+        Some(castle) => castle.play(board),
+        None => board.make_move_new(mv),
+    }
+}
+
+/// The castling rights of a Chess960 game, as the file of the rook each
+/// side may still castle with. `Board` can only castle with the king on
+/// the e-file and the rooks in the corners, so in a Chess960 game it is
+/// kept without rights of its own and these stand in for them; outside
+/// one they are empty and every method leaves `Board` to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Castling {
+    /// By colour, then king side and queen side.
+    rooks: [[Option<File>; 2]; 2],
+}
+
+impl Castling {
+    pub fn is_empty(&self) -> bool {
+        self.rooks == [[None; 2]; 2]
+    }
+
+    /// The castles the side to move on `board` can play, king to rook.
+    pub fn moves(&self, board: &Board) -> Vec<ChessMove> {
+        let us = board.side_to_move();
+        let rights = self.rooks[us.to_index()];
+        if rights == [None; 2] || *board.checkers() != EMPTY {
+            return Vec::new();
+        }
+        let king = board.king_square(us);
+        if king.get_rank() != back_rank(us) {
+            return Vec::new();
+        }
+        [Side::King, Side::Queen]
+            .into_iter()
+            .zip(rights)
+            .filter_map(|(side, file)| {
+                let rook = Square::make_square(back_rank(us), file?);
+                Castle::new(side, king, rook)
+                    .is_legal(board)
+                    .then(|| ChessMove::new(king, rook, None))
+            })
+            .collect()
+    }
+
+    /// Every legal move on `board`, castles last.
+    pub fn legal_moves(&self, board: &Board) -> Vec<ChessMove> {
+        let mut moves: Vec<ChessMove> = MoveGen::new_legal(board).collect();
+        moves.extend(self.moves(board));
+        moves
+    }
+
+    pub fn is_legal(&self, board: &Board, mv: ChessMove) -> bool {
+        board.legal(mv) || self.moves(board).contains(&mv)
+    }
+
+    /// Plays `mv`, one of `legal_moves`, from `board` into `result`, and
+    /// returns the rights left: a king move gives up both of its side's,
+    /// and a rook moving or being taken gives up its own.
+    pub fn play(self, board: &Board, mv: ChessMove, result: &mut Board) -> Castling {
+        if self.is_empty() {
+            board.make_move(mv, result);
+            return self;
+        }
+        *result = make_move_new(board, mv);
+        let mut left = self;
+        if board.piece_on(mv.get_source()) == Some(Piece::King) {
+            left.rooks[board.side_to_move().to_index()] = [None; 2];
+        }
+        for colour in ALL_COLORS {
+            for right in &mut left.rooks[colour.to_index()] {
+                let square = right.map(|file| Square::make_square(back_rank(colour), file));
+                if square == Some(mv.get_source()) || square == Some(mv.get_dest()) {
+                    *right = None;
+                }
+            }
+        }
+        left
+    }
+
+    /// Mixed into a position's hash, so positions that differ only in
+    /// their rights don't count as the same. 0 without any rights, which
+    /// leaves a classical game's hashes alone.
+    pub fn key(&self) -> u64 {
+        let mut key = 0;
+        for (idx, file) in self.rooks.iter().flatten().enumerate() {
+            if let Some(file) = file {
+                key ^= splitmix(((idx * 8) + file.to_index()) as u64);
+            }
+        }
+        key
+    }
+
+    /// The rights as a Shredder-FEN castling field, as in `HAha`.
+    pub fn field(&self) -> String {
+        let mut field = String::new();
+        for colour in ALL_COLORS {
+            for file in self.rooks[colour.to_index()].iter().flatten() {
+                let letter = (b'A' + file.to_index() as u8) as char;
+                field.push(match colour {
+                    Color::White => letter,
+                    Color::Black => letter.to_ascii_lowercase(),
+                });
+            }
+        }
+        if field.is_empty() {
+            field.push('-');
+        }
+        field
+    }
+}
+
+/// One of 32 fixed pseudo-random hash keys, from the SplitMix64 mixer.
+fn splitmix(n: u64) -> u64 {
+    let mut z = n.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Splits the castling field off an X-FEN or Shredder-FEN position: the
+/// FEN comes back with no rights of its own, for `Board`, and the rights
+/// as `Castling`. `K` and `Q` name the outermost rook on that side of the
+/// king, a file letter names the rook on that file.
+pub fn parse_fen(fen: &str) -> Result<(String, Castling), String> {
+    let mut fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 3 {
+        return Err("missing castling field".to_string());
+    }
+    let field = fields[2];
+    fields[2] = "-";
+    let builder = BoardBuilder::from_str(&fields.join(" ")).map_err(|e| e.to_string())?;
+
+    let mut castling = Castling::default();
+    for token in field.chars().filter(|&c| c != '-') {
+        let colour = if token.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let (side, file) = castling_right(&builder, colour, token.to_ascii_uppercase())
+            .ok_or_else(|| format!("no rook to castle with for castling right {}", token))?;
+        castling.rooks[colour.to_index()][side as usize] = Some(file);
+    }
+    Ok((fields.join(" "), castling))
+}
+
+/// The side and rook file one castling token (`K`, `Q` or a rook file
+/// `A`-`H`) names for `colour`, if its king and that rook are on the back
+/// rank.
+fn castling_right(builder: &BoardBuilder, colour: Color, token: char) -> Option<(Side, File)> {
+    let piece_on = |file: File| builder[Square::make_square(back_rank(colour), file)];
+    let king_file = ALL_FILES
+        .iter()
+        .copied()
+        .find(|&f| piece_on(f) == Some((Piece::King, colour)))?;
+    let rook_files: Vec<File> = ALL_FILES
+        .iter()
+        .copied()
+        .filter(|&f| piece_on(f) == Some((Piece::Rook, colour)))
+        .collect();
+    let rook_file = match token {
+        'K' => *rook_files.iter().rfind(|&&f| f > king_file)?,
+        'Q' => *rook_files.iter().find(|&&f| f < king_file)?,
+        'A'..='H' => File::from_index(token as usize - 'A' as usize),
+        _ => return None,
+    };
+    if !rook_files.contains(&rook_file) || rook_file == king_file {
+        return None;
+    }
+    let side = if rook_file > king_file {
+        Side::King
+    } else {
+        Side::Queen
+    };
+    Some((side, rook_file))
+}
+
+/// Whether the castling field of `fen` names rook files, as Shredder-FEN
+/// and the X-FEN of some Chess960 positions do.
+pub fn names_rook_files(fen: &str) -> bool {
+    fen.split_whitespace()
+        .nth(2)
+        .is_some_and(|field| field.chars().any(|c| !"KQkq-".contains(c)))
+}
+
+/// `board` as FEN, with the castling field written from `castling` when
+/// the game has Chess960 rights.
+pub fn to_fen(board: &Board, clocks: Clocks, castling: Castling) -> String {
+    let written = fen::to_fen(board, clocks);
+    if castling.is_empty() {
+        return written;
+    }
+    let mut fields: Vec<&str> = written.split(' ').collect();
+    let field = castling.field();
+    fields[2] = &field;
+    fields.join(" ")
+}
+
+/// Number of the Chess960 start position that is the classical one.
+pub const CLASSICAL_POSITION: u32 = 518;
+
+/// FEN of Chess960 start position `number`, from 0 to 959, numbered the
+/// usual (Scharnagl) way: the light and dark bishops, the queen and then
+/// the knights are placed in turn, and the king goes between the rooks.
+/// Castling rights are written as X-FEN `KQkq`, which name the only rooks.
+pub fn start_position(number: u32) -> Option<String> {
+    if number >= 960 {
+        return None;
+    }
+    // Knight squares among the five left once bishops and queen are placed.
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+    let mut rank: [Option<char>; 8] = [None; 8];
+    let n = number as usize;
+    rank[2 * (n % 4) + 1] = Some('B');
+    rank[2 * (n / 4 % 4)] = Some('B');
+    let empty = |rank: &[Option<char>; 8]| -> Vec<usize> {
+        (0..8).filter(|&f| rank[f].is_none()).collect()
+    };
+    rank[empty(&rank)[n / 16 % 6]] = Some('Q');
+    let (first, second) = KNIGHTS[n / 96];
+    let free = empty(&rank);
+    rank[free[first]] = Some('N');
+    rank[free[second]] = Some('N');
+    for (file, piece) in empty(&rank).into_iter().zip(['R', 'K', 'R']) {
+        rank[file] = Some(piece);
+    }
+    let white: String = rank.iter().flatten().collect();
+    Some(format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+        white.to_ascii_lowercase(),
+        white
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn start_positions_are_numbered_the_usual_way() {
+        let classical = start_position(CLASSICAL_POSITION).unwrap();
+        assert_eq!(classical, crate::STARTING_FEN);
+        assert_eq!(&start_position(0).unwrap()[..8], "bbqnnrkr");
+        assert_eq!(start_position(960), None);
+    }
+
+    #[test]
+    fn castles_both_ways_from_a_chess960_setup() {
+        // Start position nrkbbqrn with only the king and rooks left on the
+        // back ranks.
+        let fen = "1rk3r1/pppppppp/8/8/8/8/PPPPPPPP/1RK3R1 w KQkq - 0 1";
+        let (fen, castling) = parse_fen(fen).unwrap();
+        let board = Board::from_str(&fen).unwrap();
+        let king_side = ChessMove::new(Square::C1, Square::G1, None);
+        let queen_side = ChessMove::new(Square::C1, Square::B1, None);
+        assert_eq!(castling.moves(&board), [king_side, queen_side]);
+
+        let mut after = board;
+        let left = castling.play(&board, king_side, &mut after);
+        assert_eq!(after.piece_on(Square::G1), Some(Piece::King));
+        assert_eq!(after.piece_on(Square::F1), Some(Piece::Rook));
+        assert_eq!(after.piece_on(Square::C1), None);
+        assert_eq!(left.field(), "gb");
+        castling.play(&board, queen_side, &mut after);
+        assert_eq!(after.piece_on(Square::C1), Some(Piece::King));
+        assert_eq!(after.piece_on(Square::D1), Some(Piece::Rook));
+        assert_eq!(after.piece_on(Square::B1), None);
+    }
+}
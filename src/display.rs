@@ -0,0 +1,370 @@
+use crate::eval::{calc_board_value, side_material};
+use crate::eval_params::EvalParams;
+use crate::game_state::GameState;
+use crate::search::{is_mate_score, score_to_white, SearchResult, MATE_SCORE};
+use crate::{chess960, pgn, piece_values, threats};
+use chess::{get_rank, BitBoard, Board, ChessMove, Color, Piece, Rank, Square, ALL_RANKS, EMPTY};
+
+/// Shows the board, and with `show_clocks` the FEN move counters below it.
+/// With `flip` the board is seen from Black's side.
+pub(crate) fn show_game(game: &GameState, show_clocks: bool, flip: bool) {
+    show_board(game.board(), flip);
+    if show_clocks {
+        println!("{}", clocks_line(game));
+    }
+}
+
+/// The FEN move counters of `game` as `show_game` prints them.
+fn clocks_line(game: &GameState) -> String {
+    let clocks = game.clocks();
+    format!(
+        "Halfmove clock: {}  Fullmove: {}",
+        clocks.halfmove, clocks.fullmove
+    )
+}
+
+fn show_board(board: Board, flip: bool) {
+    show_board_with_overlay(board, EMPTY, flip);
+}
+
+/// Prints the board, marking the empty squares in `overlay` with "x". With
+/// `flip` it is turned round, rank 8 at the top and the h-file on the left.
+pub fn show_board_with_overlay(board: Board, overlay: BitBoard, flip: bool) {
+    print!("{}", board_text(board, overlay, flip));
+}
+
+/// The board as `show_board_with_overlay` prints it, one line per rank
+/// and the files underneath.
+fn board_text(board: Board, overlay: BitBoard, flip: bool) -> String {
+    let mut text = String::new();
+    let mut ranks: Vec<Rank> = ALL_RANKS.to_vec();
+    if flip {
+        ranks.reverse();
+    }
+    for rank in ranks {
+        text.push_str(&format!("{} ", rank.to_index() + 1));
+        let mut squares: Vec<Square> = get_rank(rank).collect();
+        if flip {
+            squares.reverse();
+        }
+        for sq in squares {
+            let piece = board.piece_on(sq);
+            let sq_char = match board.color_on(sq) {
+                Some(Color::Black) => match piece {
+                    Some(Piece::King) => "♚",
+                    Some(Piece::Queen) => "♛",
+                    Some(Piece::Rook) => "♜",
+                    Some(Piece::Bishop) => "♝",
+                    Some(Piece::Knight) => "♞",
+                    Some(Piece::Pawn) => "♟",
+                    _ => "?",
+                },
+                Some(Color::White) => match piece {
+                    Some(Piece::King) => "♔",
+                    Some(Piece::Queen) => "♕",
+                    Some(Piece::Rook) => "♖",
+                    Some(Piece::Bishop) => "♗",
+                    Some(Piece::Knight) => "♘",
+                    Some(Piece::Pawn) => "♙",
+                    _ => "?",
+                },
+                _ if overlay & BitBoard::from_square(sq) != EMPTY => "x",
+                _ => ".",
+            };
+            text.push_str(sq_char);
+            text.push(' ');
+        }
+        text.push('\n');
+    }
+    if flip {
+        text.push_str("  h g f e d c b a\n");
+    } else {
+        text.push_str("  a b c d e f g h\n");
+    }
+    text
+}
+
+/// Prints the static evaluation of both positions, from White's point of
+/// view, and how much it changes going from `board` to `other`.
+pub fn compare_positions(board: &Board, other: &Board, params: &EvalParams) {
+    let (before, after) = compared_evals(board, other, params);
+    println!("Eval: {:+}", before);
+    println!("Compared eval: {:+}", after);
+    println!("Delta: {:+}", after - before);
+}
+
+/// White's evaluation of `board` and of `other`.
+fn compared_evals(board: &Board, other: &Board, params: &EvalParams) -> (i64, i64) {
+    (
+        score_to_white(calc_board_value(board, params)),
+        score_to_white(calc_board_value(other, params)),
+    )
+}
+
+pub fn show_threats(board: &Board) {
+    let threats = threats::find_threats(board);
+    for (colour, piece, sq) in threats.hanging {
+        println!("Hanging: {:?} {:?} on {}", colour, piece, sq);
+    }
+    for (mv, gain) in threats.winning_captures {
+        println!("Wins material: {} (+{})", mv, gain);
+    }
+    for mv in threats.checks {
+        println!("Check: {}", mv);
+    }
+}
+
+/// Prints how many of each piece both sides have and the material balance
+/// in pawns, leaving piece placement out.
+pub fn show_material(board: &Board) {
+    for line in material_table(board) {
+        println!("{}", line);
+    }
+}
+
+/// The lines `show_material` prints.
+fn material_table(board: &Board) -> Vec<String> {
+    let mut lines = vec!["piece\twhite\tblack".to_string()];
+    for &piece in piece_values::PIECES.iter().filter(|&&p| p != Piece::King) {
+        let count = |colour| (board.pieces(piece) & board.color_combined(colour)).popcnt();
+        lines.push(format!(
+            "{:?}\t{}\t{}",
+            piece,
+            count(Color::White),
+            count(Color::Black)
+        ));
+    }
+    let balance = side_material(board, Color::White) - side_material(board, Color::Black);
+    lines.push(format!(
+        "Balance: {:+.2}",
+        balance as f64 / piece_values::PAWN as f64
+    ));
+    lines
+}
+
+/// Prints each MultiPV line of `start`'s position, best first, as its
+/// rank, score and moves, e.g. "2. +0.25 1. d4 d5 2. c4".
+pub fn show_multipv(start: &GameState, lines: &[SearchResult], san: bool) {
+    let board = start.board();
+    for (rank, line) in lines.iter().enumerate() {
+        println!(
+            "{}. {} {}",
+            rank + 1,
+            eval_text(score_to_white(line.score)),
+            format_line(&board, start.clocks().fullmove, &line.pv, san)
+        );
+    }
+}
+
+/// Writes `line` with move numbers, as in "12... e7e5 13. g1f3", starting
+/// from `board` at move `fullmove`. With `san` the moves are replayed so
+/// each can be written in SAN, as in "12... e5 13. Nf3".
+pub fn format_line(board: &Board, fullmove: u32, line: &[ChessMove], san: bool) -> String {
+    let mut text = Vec::new();
+    let mut white = board.side_to_move() == Color::White;
+    let mut number = fullmove;
+    let mut position = *board;
+    for (idx, &mv) in line.iter().enumerate() {
+        let mv = if san {
+            let written = pgn::san(&position, mv);
+            position = chess960::make_move_new(&position, mv);
+            written
+        } else {
+            mv.to_string()
+        };
+        if white {
+            text.push(format!("{}. {}", number, mv));
+        } else if idx == 0 {
+            text.push(format!("{}... {}", number, mv));
+        } else {
+            text.push(mv.to_string());
+        }
+        if !white {
+            number += 1;
+        }
+        white = !white;
+    }
+    text.join(" ")
+}
+
+/// Centipawn advantage that multiplies the odds of winning by ten, the same
+/// scaling the Elo formula uses for rating points.
+pub(crate) const WIN_PROB_SCALE: f64 = 400.0;
+
+/// Estimated probability that the side the score favours wins.
+fn win_probability(cp: i64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-(cp as f64) / WIN_PROB_SCALE))
+}
+
+/// Formats a White-relative centipawn score as pawns with White's
+/// estimated winning chances, e.g. "+1.20 (67%)".
+pub fn human_score(white_cp: i64) -> String {
+    format!(
+        "{:+.2} ({:.0}%)",
+        white_cp as f64 / piece_values::PAWN as f64,
+        win_probability(white_cp) * 100.0
+    )
+}
+
+/// Formats a White-relative score as pawns, e.g. "+0.35", or as the
+/// distance to mate, e.g. "Black mates in 2".
+pub(crate) fn eval_text(white_score: i64) -> String {
+    if !is_mate_score(white_score) {
+        return format!("{:+.2}", white_score as f64 / piece_values::PAWN as f64);
+    }
+    let plies = MATE_SCORE - white_score.abs();
+    let winner = if white_score > 0 { "White" } else { "Black" };
+    format!("{} mates in {}", winner, (plies + 1) / 2)
+}
+
+/// Width of the `--display-eval-bar` bar in characters.
+const EVAL_BAR_WIDTH: usize = 40;
+/// Scores beyond this many centipawns fill the bar completely.
+const EVAL_BAR_CLAMP: i64 = 10 * piece_values::PAWN;
+
+/// A text bar split between White's share of the winning chances, filled
+/// from the left, and Black's, e.g. "W [####################....] B".
+pub(crate) fn eval_bar(white_cp: i64) -> String {
+    let cp = white_cp.clamp(-EVAL_BAR_CLAMP, EVAL_BAR_CLAMP);
+    let white = (win_probability(cp) * EVAL_BAR_WIDTH as f64).round() as usize;
+    format!(
+        "W [{}{}] B",
+        "#".repeat(white),
+        ".".repeat(EVAL_BAR_WIDTH - white)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state;
+    use std::str::FromStr;
+
+    #[test]
+    fn level_score_is_even_chances() {
+        assert_eq!(human_score(0), "+0.00 (50%)");
+        assert!(win_probability(10 * piece_values::PAWN) > 0.99);
+        assert!(win_probability(-10 * piece_values::PAWN) < 0.01);
+    }
+
+    #[test]
+    fn free_pawn_is_a_pawn_of_delta() {
+        let before = Board::from_str("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let after = Board::from_str("4k3/8/8/3P4/8/8/8/4K3 b - - 0 1").unwrap();
+        let (before, after) = compared_evals(&before, &after, &EvalParams::default());
+        let delta = after - before;
+        assert!(
+            (delta - piece_values::PAWN).abs() <= piece_values::PAWN / 2,
+            "{}",
+            delta
+        );
+    }
+
+    #[test]
+    fn eval_bar_fills_towards_the_better_side() {
+        let half = EVAL_BAR_WIDTH / 2;
+        let even = eval_bar(0);
+        assert_eq!(
+            even,
+            format!("W [{}{}] B", "#".repeat(half), ".".repeat(half))
+        );
+        let winning = eval_bar(5 * piece_values::PAWN);
+        let filled = winning.matches('#').count();
+        assert!(filled > EVAL_BAR_WIDTH * 9 / 10, "{}", winning);
+    }
+
+    #[test]
+    fn halfmove_clock_counts_quiet_moves_and_resets_on_captures() {
+        let mut game = GameState::new(Board::default(), game_state::Clocks::default());
+        let mut play = |moves: &[&str]| {
+            for mv in moves {
+                game.make_move(ChessMove::from_str(mv).unwrap());
+            }
+            clocks_line(&game)
+        };
+        assert_eq!(
+            play(&["g1f3", "g8f6", "f3g1"]),
+            "Halfmove clock: 3  Fullmove: 2"
+        );
+        assert_eq!(play(&["f6g8"]), "Halfmove clock: 4  Fullmove: 3");
+        assert_eq!(play(&["b1c3", "d7d5"]), "Halfmove clock: 0  Fullmove: 4");
+        assert_eq!(play(&["g1f3", "g8f6"]), "Halfmove clock: 2  Fullmove: 5");
+        assert_eq!(play(&["c3d5"]), "Halfmove clock: 0  Fullmove: 5");
+    }
+
+    #[test]
+    fn exchange_up_counts_the_pieces_and_about_two_pawns() {
+        let board =
+            Board::from_str("1nbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RN1QKBNR w KQk - 0 1").unwrap();
+        let table = material_table(&board);
+        assert!(table.contains(&"Rook\t2\t1".to_string()), "{:?}", table);
+        assert!(table.contains(&"Bishop\t1\t2".to_string()), "{:?}", table);
+        assert!(table.contains(&"Pawn\t8\t8".to_string()), "{:?}", table);
+        let balance: f64 = table.last().unwrap()["Balance: ".len()..].parse().unwrap();
+        assert!((1.5..=2.5).contains(&balance), "{}", balance);
+    }
+
+    #[test]
+    fn san_lines_are_numbered_and_annotated() {
+        let moves = |text: &str| -> Vec<ChessMove> {
+            text.split_whitespace()
+                .map(|mv| ChessMove::from_str(mv).unwrap())
+                .collect()
+        };
+        let start = Board::default();
+        let opening = moves("e2e4 e7e5 g1f3");
+        assert_eq!(format_line(&start, 1, &opening, true), "1. e4 e5 2. Nf3");
+        assert_eq!(
+            format_line(&start, 1, &opening, false),
+            "1. e2e4 e7e5 2. g1f3"
+        );
+        let scholar =
+            Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .unwrap();
+        assert_eq!(format_line(&scholar, 4, &moves("h5f7"), true), "4. Qxf7#");
+        let knights = Board::from_str("4k3/8/8/8/8/8/8/1N2KN2 b - - 0 30").unwrap();
+        assert_eq!(
+            format_line(&knights, 30, &moves("e8d8 b1d2 d8c7 d2e4"), true),
+            "30... Kd8 31. Nbd2 Kc7 32. Ne4"
+        );
+    }
+
+    #[test]
+    fn flipped_board_is_the_same_board_turned_round() {
+        let board = Board::from_str("k7/8/8/8/8/8/P7/4K2R w K - 0 1").unwrap();
+        let normal = board_text(board, EMPTY, false);
+        let flipped = board_text(board, EMPTY, true);
+        assert_eq!(
+            normal,
+            [
+                "1 . . . . ♔ . . ♖ ",
+                "2 ♙ . . . . . . . ",
+                "3 . . . . . . . . ",
+                "4 . . . . . . . . ",
+                "5 . . . . . . . . ",
+                "6 . . . . . . . . ",
+                "7 . . . . . . . . ",
+                "8 ♚ . . . . . . . ",
+                "  a b c d e f g h",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            flipped,
+            [
+                "8 . . . . . . . ♚ ",
+                "7 . . . . . . . . ",
+                "6 . . . . . . . . ",
+                "5 . . . . . . . . ",
+                "4 . . . . . . . . ",
+                "3 . . . . . . . . ",
+                "2 . . . . . . . ♙ ",
+                "1 ♖ . . ♔ . . . . ",
+                "  h g f e d c b a",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
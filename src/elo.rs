@@ -0,0 +1,79 @@
+use std::fmt;
+
+/// z-value for a 95% confidence interval.
+const Z_95: f64 = 1.96;
+
+/// Approximate Elo difference implied by a match score.
+pub struct EloEstimate {
+    pub elo: f64,
+    /// Half-width of the 95% confidence interval, in Elo.
+    pub margin: f64,
+    /// The score was 0% or 100%, so `elo` is only a bound.
+    pub clamped: bool,
+}
+
+/// Elo difference for an expected score strictly between 0 and 1.
+fn elo_from_score(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+/// Estimates the Elo difference of the first player from its wins, losses
+/// and draws, using the logistic model and the normal approximation for the
+/// error bars. Perfect scores are clamped half a game from the edge.
+pub fn estimate(wins: u32, losses: u32, draws: u32) -> Option<EloEstimate> {
+    let games = (wins + losses + draws) as f64;
+    if games == 0.0 {
+        return None;
+    }
+    let score = (wins as f64 + draws as f64 / 2.0) / games;
+    let variance = (wins as f64 * (1.0 - score).powi(2)
+        + draws as f64 * (0.5 - score).powi(2)
+        + losses as f64 * score.powi(2))
+        / games;
+    let std_error = (variance / games).sqrt();
+
+    let edge = 0.5 / games;
+    let clamp = |s: f64| s.clamp(edge, 1.0 - edge);
+    let clamped = score <= 0.0 || score >= 1.0;
+    let low = elo_from_score(clamp(score - Z_95 * std_error));
+    let high = elo_from_score(clamp(score + Z_95 * std_error));
+
+    Some(EloEstimate {
+        elo: elo_from_score(clamp(score)),
+        margin: (high - low) / 2.0,
+        clamped,
+    })
+}
+
+impl fmt::Display for EloEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.clamped {
+            let bound = if self.elo > 0.0 { ">" } else { "<" };
+            write!(f, "{} {:+.0} (perfect score)", bound, self.elo)
+        } else {
+            write!(f, "{:+.0} +/- {:.0}", self.elo, self.margin)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn even_score_is_no_difference() {
+        let even = estimate(10, 10, 20).unwrap();
+        assert!(even.elo.abs() < 1e-9 && !even.clamped);
+        assert!(estimate(0, 0, 0).is_none());
+    }
+
+    #[test]
+    fn lopsided_score_favours_the_winner() {
+        // 90% is about +382.
+        let strong = estimate(18, 2, 0).unwrap();
+        assert!((strong.elo - 381.7).abs() < 1.0, "{}", strong.elo);
+        assert!(estimate(2, 18, 0).unwrap().elo < -380.0);
+        let perfect = estimate(10, 0, 0).unwrap();
+        assert!(perfect.clamped && perfect.elo > 400.0);
+    }
+}
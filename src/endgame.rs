@@ -0,0 +1,610 @@
+use crate::eval::LIGHT_SQUARES;
+use crate::piece_values::PAWN;
+use chess::{
+    get_king_moves, get_pawn_attacks, BitBoard, Board, Color, Piece, Square, ALL_COLORS,
+    ALL_SQUARES, EMPTY,
+};
+use std::cell::RefCell;
+use std::sync::OnceLock;
+
+/// Base score for an ending the built-in solvers know to be won. Progress
+/// terms are added on top so the search still makes headway.
+pub const KNOWN_WIN: i64 = 100 * PAWN;
+
+/// One link of the endgame fallback chain, tried in the configured order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndgameSource {
+    Syzygy,
+    Builtin,
+    Search,
+}
+
+impl EndgameSource {
+    pub fn from_name(name: &str) -> Option<EndgameSource> {
+        match name.trim().to_lowercase().as_str() {
+            "syzygy" => Some(EndgameSource::Syzygy),
+            "builtin" => Some(EndgameSource::Builtin),
+            "search" => Some(EndgameSource::Search),
+            _ => None,
+        }
+    }
+
+    /// Whether this source can answer any probe at all in this build.
+    pub fn is_available(self) -> bool {
+        // No Syzygy prober is bundled yet, so a chain that lists it just
+        // falls through to the next source.
+        self != EndgameSource::Syzygy
+    }
+}
+
+/// Walks the chain and returns the first known score for `board`, from
+/// the strong side's point of view together with that side's colour.
+/// `None` means the position has to be searched.
+pub fn probe(board: &Board, chain: &[EndgameSource]) -> Option<(Color, i64)> {
+    for source in chain {
+        match source {
+            EndgameSource::Syzygy => continue,
+            EndgameSource::Builtin => {
+                if let Some(result) = probe_builtin(board) {
+                    return Some(result);
+                }
+            }
+            EndgameSource::Search => return None,
+        }
+    }
+    None
+}
+
+/// What the material alone says about a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Classification {
+    /// The given side mates by force against the bare king.
+    KnownWin(Color),
+    KnownDraw,
+    Unknown,
+}
+
+/// Sorts out the trivial endings by material signature: a rook or queen
+/// against a bare king wins, while a lone minor piece or two knights can't
+/// force mate.
+pub fn classify(board: &Board) -> Classification {
+    material_verdict(board).classification
+}
+
+/// No sequence of legal moves can end in mate: bare kings, a lone knight
+/// or bishop, or only bishops that all stand on one colour of square.
+pub fn is_insufficient_material(board: &Board) -> bool {
+    material_verdict(board).insufficient
+}
+
+/// Entries in the material cache; a power of two so the hash can be masked.
+const MATERIAL_CACHE_ENTRIES: usize = 1 << 12;
+
+/// What `classify` and `is_insufficient_material` said about one material
+/// signature.
+#[derive(Clone, Copy)]
+struct MaterialVerdict {
+    signature: u64,
+    classification: Classification,
+    insufficient: bool,
+}
+
+thread_local! {
+    /// Verdicts by material signature. Captures and promotions are rare,
+    /// so the search keeps asking about the same few signatures.
+    static MATERIAL_CACHE: RefCell<Vec<Option<MaterialVerdict>>> =
+        RefCell::new(vec![None; MATERIAL_CACHE_ENTRIES]);
+}
+
+/// Piece counts for each colour, four bits apiece, with bishops split by
+/// the colour of their square: all the material verdicts depend on.
+fn material_signature(board: &Board) -> u64 {
+    let mut signature = 0;
+    for colour in ALL_COLORS {
+        let own = board.color_combined(colour);
+        let bishops = board.pieces(Piece::Bishop) & own;
+        let counts = [
+            (board.pieces(Piece::Pawn) & own).popcnt(),
+            (board.pieces(Piece::Knight) & own).popcnt(),
+            (bishops & LIGHT_SQUARES).popcnt(),
+            (bishops & !LIGHT_SQUARES).popcnt(),
+            (board.pieces(Piece::Rook) & own).popcnt(),
+            (board.pieces(Piece::Queen) & own).popcnt(),
+        ];
+        for count in counts {
+            signature = signature << 4 | u64::from(count);
+        }
+    }
+    signature
+}
+
+fn material_verdict(board: &Board) -> MaterialVerdict {
+    // Most positions have pawns or heavy pieces and no bare king, which
+    // settles both questions without counting anything.
+    let heavy_or_pawn =
+        board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let more_than_king = |colour: &Color| {
+        let own = board.color_combined(*colour).0;
+        own & own.wrapping_sub(1) != 0
+    };
+    if heavy_or_pawn != EMPTY && ALL_COLORS.iter().all(more_than_king) {
+        return MaterialVerdict {
+            signature: 0,
+            classification: Classification::Unknown,
+            insufficient: false,
+        };
+    }
+    let signature = material_signature(board);
+    let idx = (signature.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 52) as usize
+        & (MATERIAL_CACHE_ENTRIES - 1);
+    MATERIAL_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        match cache[idx] {
+            Some(verdict) if verdict.signature == signature => verdict,
+            _ => {
+                let verdict = MaterialVerdict {
+                    signature,
+                    classification: classify_material(board),
+                    insufficient: insufficient_material(board),
+                };
+                cache[idx] = Some(verdict);
+                verdict
+            }
+        }
+    })
+}
+
+fn classify_material(board: &Board) -> Classification {
+    if is_minor_piece_draw(board) || is_two_knights_draw(board) {
+        return Classification::KnownDraw;
+    }
+    let heavy = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    for colour in ALL_COLORS {
+        let bare_king = board.color_combined(!colour).popcnt() == 1;
+        if bare_king && (heavy & board.color_combined(colour)) != EMPTY {
+            return Classification::KnownWin(colour);
+        }
+    }
+    Classification::Unknown
+}
+
+fn probe_builtin(board: &Board) -> Option<(Color, i64)> {
+    match classify(board) {
+        Classification::KnownDraw => return Some((Color::White, 0)),
+        Classification::KnownWin(strong) if !lone_piece_hangs(board, strong) => {
+            return Some((strong, mating_score(board, strong)))
+        }
+        Classification::KnownWin(_) => return None,
+        Classification::Unknown => {}
+    }
+    let total = board.combined().popcnt();
+    if total == 3 && board.pieces(Piece::Pawn).popcnt() == 1 {
+        return Some(probe_kpk(board));
+    }
+    if total == 4
+        && board.pieces(Piece::Bishop).popcnt() == 1
+        && board.pieces(Piece::Knight).popcnt() == 1
+    {
+        return probe_kbnk(board);
+    }
+    None
+}
+
+fn insufficient_material(board: &Board) -> bool {
+    let heavy_or_pawn =
+        board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    if heavy_or_pawn != EMPTY {
+        return false;
+    }
+    let knights = board.pieces(Piece::Knight);
+    let bishops = board.pieces(Piece::Bishop);
+    if knights.popcnt() + bishops.popcnt() <= 1 {
+        return true;
+    }
+    *knights == EMPTY && (bishops & LIGHT_SQUARES == EMPTY || bishops & !LIGHT_SQUARES == EMPTY)
+}
+
+/// No pawns, no rooks or queens and at most one minor piece per side: an
+/// extra knight or bishop alone can't force mate.
+fn is_minor_piece_draw(board: &Board) -> bool {
+    if (board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen)) != EMPTY
+    {
+        return false;
+    }
+    let minors = board.pieces(Piece::Knight) | board.pieces(Piece::Bishop);
+    ALL_COLORS
+        .iter()
+        .all(|&colour| (minors & board.color_combined(colour)).popcnt() <= 1)
+}
+
+/// Two knights against a bare king: mate exists but can't be forced.
+fn is_two_knights_draw(board: &Board) -> bool {
+    let knights = *board.pieces(Piece::Knight);
+    knights.popcnt() == 2
+        && board.combined().popcnt() == 4
+        && ALL_COLORS
+            .iter()
+            .any(|&colour| (knights & board.color_combined(colour)) == knights)
+}
+
+/// The bare king is to move next to `strong`'s only piece, which its own
+/// king doesn't guard, so the "win" is one capture from a dead draw.
+fn lone_piece_hangs(board: &Board, strong: Color) -> bool {
+    let pieces = board.color_combined(strong) & !board.pieces(Piece::King);
+    if board.side_to_move() == strong || pieces.popcnt() != 1 {
+        return false;
+    }
+    let reach = get_king_moves(board.king_square(!strong));
+    let guarded = get_king_moves(board.king_square(strong));
+    (pieces & reach & !guarded) != EMPTY
+}
+
+/// Known win against a bare king: push it to the edge and bring the
+/// attacking king close, the way every basic mate is played.
+fn mating_score(board: &Board, strong: Color) -> i64 {
+    let weak_king = board.king_square(!strong);
+    let strong_king = board.king_square(strong);
+    let edge = |idx: usize| std::cmp::max(idx, 7 - idx) as i64 - 4;
+    let centre_distance =
+        edge(weak_king.get_file().to_index()) + edge(weak_king.get_rank().to_index());
+    KNOWN_WIN + PAWN / 10 * centre_distance
+        - PAWN / 10 * square_distance(strong_king, weak_king)
+        - box_area(board, strong, weak_king)
+}
+
+/// Squares left to the bare king in the box the strong side's rooks and
+/// queens fence off along their files and ranks; shrinking it is how the
+/// heavy piece makes progress while its king walks up.
+fn box_area(board: &Board, strong: Color, weak_king: Square) -> i64 {
+    let heavy =
+        (board.pieces(Piece::Rook) | board.pieces(Piece::Queen)) & board.color_combined(strong);
+    // Lines on the king's side of `line`, or all eight if it shares it.
+    let side = |king: usize, line: usize| match king.cmp(&line) {
+        std::cmp::Ordering::Less => line,
+        std::cmp::Ordering::Greater => 7 - line,
+        std::cmp::Ordering::Equal => 8,
+    } as i64;
+    let (file, rank) = (
+        weak_king.get_file().to_index(),
+        weak_king.get_rank().to_index(),
+    );
+    heavy
+        .map(|sq| side(file, sq.get_file().to_index()) * side(rank, sq.get_rank().to_index()))
+        .min()
+        .unwrap_or(64)
+}
+
+fn square_distance(a: Square, b: Square) -> i64 {
+    let files = (a.get_file().to_index() as i64 - b.get_file().to_index() as i64).abs();
+    let ranks = (a.get_rank().to_index() as i64 - b.get_rank().to_index() as i64).abs();
+    std::cmp::max(files, ranks)
+}
+
+/// KBN vs K is always won; drive the lone king to a corner of the bishop's
+/// colour and bring the attacking king close.
+fn probe_kbnk(board: &Board) -> Option<(Color, i64)> {
+    let bishop = board.pieces(Piece::Bishop).to_square();
+    let knight = board.pieces(Piece::Knight).to_square();
+    let strong = board.color_on(bishop)?;
+    if board.color_on(knight) != Some(strong) {
+        return None;
+    }
+    let weak_king = board.king_square(!strong);
+    let strong_king = board.king_square(strong);
+
+    let light_bishop = (bishop.get_file().to_index() + bishop.get_rank().to_index()) % 2 == 1;
+    let corners = if light_bishop {
+        [Square::H1, Square::A8]
+    } else {
+        [Square::A1, Square::H8]
+    };
+    let corner_distance = corners
+        .iter()
+        .map(|&c| square_distance(weak_king, c))
+        .min()
+        .unwrap_or(0);
+    let score = KNOWN_WIN + PAWN / 5 * (7 - corner_distance)
+        - PAWN / 10 * square_distance(strong_king, weak_king);
+    Some((strong, score))
+}
+
+/// Looks up a K+P vs K position in the bitbase: a win scores `KNOWN_WIN`
+/// plus the pawn's advancement, a draw scores 0.
+fn probe_kpk(board: &Board) -> (Color, i64) {
+    let pawn = board.pieces(Piece::Pawn).to_square();
+    let strong = board.color_on(pawn).unwrap_or(Color::White);
+
+    let normalise = |sq: Square| -> usize {
+        let mut idx = sq.to_index();
+        if strong == Color::Black {
+            idx ^= 56;
+        }
+        if pawn.get_file().to_index() > 3 {
+            idx ^= 7;
+        }
+        idx
+    };
+    let strong_to_move = board.side_to_move() == strong;
+    let wk = normalise(board.king_square(strong));
+    let bk = normalise(board.king_square(!strong));
+    let psq = normalise(pawn);
+
+    if kpk_table()[kpk_index(strong_to_move, wk, bk, psq)] == WIN {
+        let rank = (psq / 8) as i64;
+        (strong, KNOWN_WIN + PAWN / 10 * rank)
+    } else {
+        (strong, 0)
+    }
+}
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+/// Strong side to move, both king squares, and the pawn restricted to
+/// files a-d on ranks 2-7.
+const KPK_SIZE: usize = 2 * 64 * 64 * 24;
+
+fn kpk_index(white_to_move: bool, wk: usize, bk: usize, psq: usize) -> usize {
+    let pawn_idx = (psq / 8 - 1) * 4 + psq % 8;
+    ((usize::from(white_to_move) * 64 + wk) * 64 + bk) * 24 + pawn_idx
+}
+
+fn kpk_table() -> &'static Vec<u8> {
+    static TABLE: OnceLock<Vec<u8>> = OnceLock::new();
+    TABLE.get_or_init(generate_kpk)
+}
+
+fn sq(idx: usize) -> Square {
+    ALL_SQUARES[idx]
+}
+
+fn king_moves(idx: usize) -> BitBoard {
+    get_king_moves(sq(idx))
+}
+
+fn pawn_attacks(psq: usize) -> BitBoard {
+    get_pawn_attacks(sq(psq), Color::White, !EMPTY)
+}
+
+fn bit(idx: usize) -> BitBoard {
+    BitBoard::from_square(sq(idx))
+}
+
+fn initial_kpk(white_to_move: bool, wk: usize, bk: usize, psq: usize) -> u8 {
+    if square_distance(sq(wk), sq(bk)) <= 1 || wk == psq || bk == psq {
+        return INVALID;
+    }
+    if white_to_move && pawn_attacks(psq) & bit(bk) != EMPTY {
+        return INVALID;
+    }
+    if white_to_move && psq / 8 == 6 {
+        let promo = psq + 8;
+        if wk != promo
+            && (square_distance(sq(bk), sq(promo)) > 1 || square_distance(sq(wk), sq(promo)) == 1)
+        {
+            return WIN;
+        }
+    }
+    if !white_to_move {
+        let escapes = king_moves(bk) & !(king_moves(wk) | pawn_attacks(psq));
+        if escapes == EMPTY {
+            return DRAW;
+        }
+        if king_moves(bk) & bit(psq) & !king_moves(wk) != EMPTY {
+            return DRAW;
+        }
+    }
+    UNKNOWN
+}
+
+fn classify_kpk(db: &[u8], white_to_move: bool, wk: usize, bk: usize, psq: usize) -> u8 {
+    let mut r = INVALID;
+    if white_to_move {
+        for to in king_moves(wk) {
+            r |= db[kpk_index(false, to.to_index(), bk, psq)];
+        }
+        if psq / 8 < 6 {
+            let push = psq + 8;
+            r |= db[kpk_index(false, wk, bk, push)];
+            if psq / 8 == 1 && push != wk && push != bk {
+                r |= db[kpk_index(false, wk, bk, push + 8)];
+            }
+        }
+        if r & WIN != 0 {
+            WIN
+        } else if r & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            DRAW
+        }
+    } else {
+        for to in king_moves(bk) {
+            r |= db[kpk_index(true, wk, to.to_index(), psq)];
+        }
+        if r & DRAW != 0 {
+            DRAW
+        } else if r & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            WIN
+        }
+    }
+}
+
+/// Retrograde generation of the KPK bitbase, with White as the strong side.
+fn generate_kpk() -> Vec<u8> {
+    let mut db = vec![INVALID; KPK_SIZE];
+    let mut positions = Vec::with_capacity(KPK_SIZE);
+    for stm in [false, true] {
+        for wk in 0..64 {
+            for bk in 0..64 {
+                for psq in (8..56).filter(|p| p % 8 < 4) {
+                    let idx = kpk_index(stm, wk, bk, psq);
+                    db[idx] = initial_kpk(stm, wk, bk, psq);
+                    positions.push((idx, stm, wk, bk, psq));
+                }
+            }
+        }
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(idx, stm, wk, bk, psq) in &positions {
+            if db[idx] == UNKNOWN {
+                let result = classify_kpk(&db, stm, wk, bk, psq);
+                if result != UNKNOWN {
+                    db[idx] = result;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    for value in db.iter_mut() {
+        if *value == UNKNOWN {
+            *value = DRAW;
+        }
+    }
+    db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::calc_board_value;
+    use crate::eval_params::EvalParams;
+    use crate::game_state::{Clocks, GameState};
+    use crate::search::{search, SearchConfig};
+    use std::str::FromStr;
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
+
+    #[test]
+    fn builtin_kpk_answers_when_syzygy_is_missing() {
+        let chain = [EndgameSource::Syzygy, EndgameSource::Builtin];
+        let (strong, score) = probe(&board("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), &chain).unwrap();
+        assert_eq!(strong, Color::White);
+        assert!(score >= KNOWN_WIN);
+        // The rook pawn can't get past the king in its corner.
+        let draw = probe(&board("k7/8/8/8/8/8/P7/K7 w - - 0 1"), &chain);
+        assert_eq!(draw, Some((Color::White, 0)));
+        assert_eq!(
+            probe(&board("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), &chain[..1]),
+            None
+        );
+    }
+
+    #[test]
+    fn trivial_endings_are_classified_by_material() {
+        let chain = [EndgameSource::Builtin];
+        let rook = board("8/8/3k4/8/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(classify(&rook), Classification::KnownWin(Color::White));
+        let (strong, score) = probe(&rook, &chain).unwrap();
+        assert_eq!(strong, Color::White);
+        assert!(score > KNOWN_WIN / 2, "{}", score);
+        for lone_minor in [
+            "8/8/3k4/8/8/8/8/1N2K3 w - - 0 1",
+            "8/8/3k4/8/8/8/8/2B1K3 w - - 0 1",
+        ] {
+            let lone_minor = board(lone_minor);
+            assert_eq!(classify(&lone_minor), Classification::KnownDraw);
+            assert_eq!(probe(&lone_minor, &chain), Some((Color::White, 0)));
+        }
+    }
+
+    #[test]
+    fn keeps_the_last_pawn_rather_than_trade_into_a_lone_knight() {
+        assert_eq!(
+            classify(&board("8/8/4k3/8/8/2N2K2/8/8 w - - 0 1")),
+            Classification::KnownDraw
+        );
+        // exd5+ Kxd5 leaves the knight alone.
+        let game = board("8/8/4k3/3p4/4PK2/8/8/7N w - - 0 1");
+        let result = search(&game, 4, &SearchConfig::default());
+        assert_ne!(result.best_move.unwrap().to_string(), "e4d5");
+        assert!(result.score > PAWN, "{}", result.score);
+    }
+
+    #[test]
+    fn insufficient_material_ends_the_game_and_scores_level() {
+        let is_drawn = |fen: &str| {
+            let board = board(fen);
+            let game = GameState::new(board, Clocks::from_fen(fen));
+            let ended = game.draw_by_rule() == Some("insufficient material");
+            assert_eq!(ended, is_insufficient_material(&board), "{}", fen);
+            if ended {
+                assert_eq!(
+                    calc_board_value(&board, &EvalParams::default()),
+                    0,
+                    "{}",
+                    fen
+                );
+            }
+            ended
+        };
+        for drawn in [
+            "8/8/3k4/8/8/8/8/4K3 w - - 0 1",
+            "8/8/3k4/8/8/8/8/1N2K3 w - - 0 1",
+            "8/8/3k4/8/8/8/8/2B1K3 w - - 0 1",
+            "8/8/3k4/8/8/8/8/2n1K3 w - - 0 1",
+            // Bishops all on light squares, wherever they stand.
+            "8/8/3kb3/8/8/8/8/4KB2 w - - 0 1",
+            "8/8/3k4/8/2B5/8/8/4KB2 w - - 0 1",
+        ] {
+            assert!(is_drawn(drawn), "{}", drawn);
+        }
+        for playable in [
+            "8/8/3k4/8/8/8/4P3/4K3 w - - 0 1",
+            "8/8/3k4/8/8/8/8/2B1KB2 w - - 0 1",
+            "8/8/3k4/8/8/8/8/1NB1K3 w - - 0 1",
+            "8/8/3k4/8/8/8/8/R3K3 w - - 0 1",
+        ] {
+            assert!(!is_drawn(playable), "{}", playable);
+        }
+    }
+
+    #[test]
+    fn material_cache_agrees_with_counting_and_saves_work() {
+        for fen in [
+            "8/8/3k4/8/8/8/8/4K3 w - - 0 1",
+            "8/8/3k4/8/8/8/8/1N2K3 w - - 0 1",
+            "8/8/3kb3/8/8/8/8/4KB2 w - - 0 1",
+            "8/8/3k4/8/8/8/8/2B1KB2 w - - 0 1",
+            "8/8/3k4/8/8/8/8/1NN1K3 w - - 0 1",
+            "8/8/3k4/8/8/8/8/R3K3 w - - 0 1",
+            "8/8/3k4/8/8/8/8/Q3K3 b - - 0 1",
+            "8/8/3k4/8/8/8/4P3/4K3 w - - 0 1",
+            "8/3r4/3k4/8/8/8/8/1NB1K3 w - - 0 1",
+        ] {
+            let board = board(fen);
+            // Once to fill the cache and once to read it back.
+            for _ in 0..2 {
+                assert_eq!(classify(&board), classify_material(&board), "{}", fen);
+                assert_eq!(
+                    is_insufficient_material(&board),
+                    insufficient_material(&board),
+                    "{}",
+                    fen
+                );
+            }
+        }
+        // Every node of a bare-material search asks, but only a capture
+        // brings a new signature to work out.
+        MATERIAL_CACHE.with(|cache| cache.borrow_mut().fill(None));
+        let board = board("8/8/3k4/8/3n4/8/8/1RB1K3 w - - 0 1");
+        let nodes = search(&board, 5, &SearchConfig::default()).nodes;
+        let worked_out =
+            MATERIAL_CACHE.with(|cache| cache.borrow().iter().filter(|v| v.is_some()).count());
+        assert!(
+            worked_out > 0 && (worked_out as i64) * 100 < nodes,
+            "{} of {}",
+            worked_out,
+            nodes
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use crate::eval::calc_board_value;
+use crate::game_state::{self, Clocks};
+use crate::search::{score_to_white, search, SearchConfig, SearchResult};
+use crate::{load_board, DEFAULT_DEPTH, MAX_DEPTH};
+use chess::{Board, ChessMove};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// How far a search may go. Whichever limit is reached first ends it, with
+/// the best move of the deepest iteration it finished. With no depth, time
+/// or node limit it searches to the default depth of the command line.
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    /// Deepest iteration, in plies.
+    pub depth: Option<i8>,
+    /// Time for the move in milliseconds.
+    pub movetime: Option<u64>,
+    /// Nodes the search may visit.
+    pub nodes: Option<u64>,
+    /// Set from another thread to stop the search early.
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+impl SearchLimits {
+    /// The depth to search to: the limit if there is one, as deep as the
+    /// engine goes if time or nodes will stop it, or else `default`.
+    pub fn depth_or(&self, default: i8) -> i8 {
+        match self.depth {
+            Some(depth) => depth,
+            None if self.movetime.is_some() || self.nodes.is_some() => MAX_DEPTH,
+            None => default,
+        }
+    }
+
+    /// `config` bounded by these limits. A stop flag already in `config`
+    /// is kept when the limits have none.
+    pub(crate) fn apply(&self, config: &SearchConfig) -> SearchConfig {
+        SearchConfig {
+            movetime: self.movetime,
+            max_nodes: self.nodes,
+            stop: self.stop.clone().or_else(|| config.stop.clone()),
+            ..config.clone()
+        }
+    }
+}
+
+/// The engine without its command line, for embedding in a GUI or a bot:
+/// set up a position, play moves on it, then search it.
+pub struct Engine {
+    board: Board,
+    halfmove: u32,
+    /// Hashes of the positions before the current one, for repetitions.
+    history: Vec<u64>,
+    config: SearchConfig,
+}
+
+impl Default for Engine {
+    /// An engine at the starting position with the default settings.
+    fn default() -> Engine {
+        Engine {
+            board: Board::default(),
+            halfmove: 0,
+            history: Vec::new(),
+            config: SearchConfig::default(),
+        }
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::default()
+    }
+
+    pub fn board(&self) -> Board {
+        self.board
+    }
+
+    /// Sets up the position in `fen`, forgetting the moves before it.
+    pub fn set_position(&mut self, fen: &str) -> Result<(), String> {
+        self.board = load_board(fen)?;
+        self.halfmove = Clocks::from_fen(fen).halfmove;
+        self.history.clear();
+        Ok(())
+    }
+
+    /// Plays `mv` on the current position, which is kept so the search can
+    /// see repetitions of it.
+    pub fn make_move(&mut self, mv: ChessMove) -> Result<(), String> {
+        if !self.board.legal(mv) {
+            return Err(format!("illegal move {}", mv));
+        }
+        self.halfmove = game_state::next_halfmove(&self.board, mv, self.halfmove);
+        self.history.push(self.board.get_hash());
+        self.board = self.board.make_move_new(mv);
+        Ok(())
+    }
+
+    /// Searches the current position within `limits`.
+    pub fn search(&self, limits: &SearchLimits) -> SearchResult {
+        let config = SearchConfig {
+            halfmove_clock: self.halfmove,
+            game_history: self.history.clone(),
+            ..limits.apply(&self.config)
+        };
+        search(&self.board, limits.depth_or(DEFAULT_DEPTH), &config)
+    }
+
+    /// Static evaluation of `board` in centipawns, positive when White is
+    /// better.
+    pub fn eval(&self, board: &Board) -> i64 {
+        score_to_white(calc_board_value(board, &self.config.eval))
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::io;
+
+/// Reads the positions of an EPD file as FENs, named by line number. The
+/// operations after the four position fields are ignored; the move
+/// counters EPD leaves out are taken as a fresh game.
+pub fn load_positions(path: &str) -> io::Result<Vec<(String, String)>> {
+    let text = fs::read_to_string(path)?;
+    let positions = text
+        .lines()
+        .enumerate()
+        .filter_map(|(idx, line)| {
+            let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            Some((
+                format!("line{}", idx + 1),
+                format!("{} 0 1", fields.join(" ")),
+            ))
+        })
+        .collect();
+    Ok(positions)
+}
+
+/// One position of a test suite, with the moves the search should find
+/// (`bm`) or avoid (`am`), in SAN as EPD writes them.
+pub struct TestPosition {
+    pub id: String,
+    pub fen: String,
+    pub best: Vec<String>,
+    pub avoid: Vec<String>,
+}
+
+/// Reads a test suite such as WAC or Bratko-Kopec: EPD records whose
+/// `bm` and `am` operations give the answers. An `id` names the position;
+/// without one it is named by line number. Records with neither answer
+/// are left out.
+pub fn load_suite(path: &str) -> io::Result<Vec<TestPosition>> {
+    let text = fs::read_to_string(path)?;
+    let suite = text
+        .lines()
+        .enumerate()
+        .filter_map(|(idx, line)| {
+            let mut words = line.split_whitespace();
+            let fields: Vec<&str> = words.by_ref().take(4).collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            let mut position = TestPosition {
+                id: format!("line{}", idx + 1),
+                fen: format!("{} 0 1", fields.join(" ")),
+                best: Vec::new(),
+                avoid: Vec::new(),
+            };
+            let operations = words.collect::<Vec<_>>().join(" ");
+            for operation in operations.split(';') {
+                let mut operands = operation.split_whitespace();
+                let opcode = operands.next();
+                let operands: Vec<String> =
+                    operands.map(|o| o.trim_matches('"').to_string()).collect();
+                match opcode {
+                    Some("bm") => position.best = operands,
+                    Some("am") => position.avoid = operands,
+                    Some("id") => position.id = operands.join(" "),
+                    _ => {}
+                }
+            }
+            let answered = !position.best.is_empty() || !position.avoid.is_empty();
+            answered.then_some(position)
+        })
+        .collect();
+    Ok(suite)
+}
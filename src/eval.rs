@@ -0,0 +1,414 @@
+use crate::eval_params::EvalParams;
+use crate::piece_scores::PieceScores;
+use crate::search::MATE_SCORE;
+use crate::{attacks, endgame, king_safety, mobility, pawns, piece_values};
+use chess::{
+    BitBoard, Board, BoardStatus, Color, MoveGen, Piece, Square, ALL_COLORS, ALL_SQUARES, EMPTY,
+};
+
+/// Material lead, in centipawns, from which the stronger side starts to
+/// worry about stalemating the other.
+const STALEMATE_RISK_LEAD: i64 = 5 * piece_values::PAWN;
+/// Legal moves of the losing side at or below which stalemate is close.
+const STALEMATE_RISK_MOVES: usize = 1;
+const STALEMATE_RISK_PENALTY: i64 = piece_values::PAWN / 2;
+
+/// Light squares; a1 is dark.
+pub(crate) const LIGHT_SQUARES: BitBoard = BitBoard(0x55AA_55AA_55AA_55AA);
+/// Pawns needed on one colour before the other colour counts as weak.
+const COLOUR_COMPLEX_PAWNS: u32 = 4;
+/// Penalty for a weak square colour with no bishop to cover it.
+const COLOUR_COMPLEX_PENALTY: i64 = piece_values::PAWN / 5;
+
+/// A pinned piece costs its value divided by this, in centipawns.
+const PINNED_PIECE_DIVISOR: i64 = 16;
+
+/// Sign of a term that favours `colour`; positive scores favour White.
+pub(crate) fn favour(colour: Color) -> i64 {
+    match colour {
+        Color::White => 1,
+        Color::Black => -1,
+    }
+}
+
+pub(crate) fn calc_king_safety(board: &Board) -> i64 {
+    ALL_COLORS
+        .iter()
+        .map(|&colour| -favour(colour) * king_safety::king_safety(board, colour))
+        .sum()
+}
+
+/// Material of one side, kings excluded.
+pub(crate) fn side_material(board: &Board, colour: Color) -> i64 {
+    piece_values::PIECES
+        .iter()
+        .zip(piece_values::PIECE_VALS.iter())
+        .filter(|(&piece, _)| piece != Piece::King)
+        .map(|(&piece, &value)| {
+            (board.pieces(piece) & board.color_combined(colour)).popcnt() as i64 * value
+        })
+        .sum()
+}
+
+/// A side that is far behind and down to one legal move (or none, if it
+/// were its turn) is often a tempo away from stalemate. Penalise the
+/// stronger side for such positions so it tightens the net by giving
+/// check or moving its king rather than boxing the enemy king in. Mates
+/// are scored separately and never reach here.
+pub(crate) fn calc_stalemate_risk(board: &Board) -> i64 {
+    let lead = side_material(board, Color::White) - side_material(board, Color::Black);
+    if lead.abs() < STALEMATE_RISK_LEAD {
+        return 0;
+    }
+    let weak = if lead > 0 { Color::Black } else { Color::White };
+    let weak_to_move = if board.side_to_move() == weak {
+        Some(*board)
+    } else {
+        board.null_move()
+    };
+    match weak_to_move {
+        Some(b)
+            if *b.checkers() == EMPTY && MoveGen::new_legal(&b).len() <= STALEMATE_RISK_MOVES =>
+        {
+            favour(weak) * STALEMATE_RISK_PENALTY
+        }
+        _ => 0,
+    }
+}
+
+/// Pawns fixed mostly on one colour leave the squares of the other colour
+/// without pawn cover. That only hurts when no bishop of the weak colour is
+/// left to guard them.
+pub(crate) fn calc_colour_complex(board: &Board) -> i64 {
+    ALL_COLORS
+        .iter()
+        .map(|&colour| {
+            let own = board.color_combined(colour);
+            let pawns = board.pieces(Piece::Pawn) & own;
+            let bishops = board.pieces(Piece::Bishop) & own;
+            let on_light = (pawns & LIGHT_SQUARES).popcnt();
+            let on_dark = (pawns & !LIGHT_SQUARES).popcnt();
+            let weak = if on_light >= COLOUR_COMPLEX_PAWNS && on_light >= 2 * on_dark {
+                !LIGHT_SQUARES
+            } else if on_dark >= COLOUR_COMPLEX_PAWNS && on_dark >= 2 * on_light {
+                LIGHT_SQUARES
+            } else {
+                return 0;
+            };
+            if bishops & weak != EMPTY {
+                return 0;
+            }
+            -favour(colour) * COLOUR_COMPLEX_PENALTY
+        })
+        .sum()
+}
+
+/// The four centre squares, and the ring of squares around them.
+const CENTRE: BitBoard = BitBoard(0x0000_0018_1800_0000);
+const EXTENDED_CENTRE: BitBoard = BitBoard(0x0000_3C24_243C_0000);
+
+/// Centipawns for each attack on a square, by where the square is.
+const CONTROL_CENTRE: i64 = 4;
+const CONTROL_EXTENDED_CENTRE: i64 = 2;
+const CONTROL_ENEMY_HALF: i64 = 1;
+
+/// How much one attack on `sq` by `colour` is worth: squares in the
+/// centre and in the opponent's half give more room to play.
+fn control_weight(sq: Square, colour: Color) -> i64 {
+    let bit = BitBoard::from_square(sq);
+    let mut weight = 0;
+    if CENTRE & bit != EMPTY {
+        weight += CONTROL_CENTRE;
+    } else if EXTENDED_CENTRE & bit != EMPTY {
+        weight += CONTROL_EXTENDED_CENTRE;
+    }
+    let rank = sq.get_rank().to_index();
+    let enemy_half = match colour {
+        Color::White => rank >= 4,
+        Color::Black => rank < 4,
+    };
+    if enemy_half {
+        weight += CONTROL_ENEMY_HALF;
+    }
+    weight
+}
+
+/// Space and mobility in one: every attack a side has on a square counts,
+/// weighted by `control_weight`.
+pub(crate) fn calc_attack_map(board: &Board, params: &EvalParams) -> i64 {
+    if !params.attack_map {
+        return 0;
+    }
+    ALL_COLORS
+        .iter()
+        .map(|&colour| {
+            let counts = attacks::control_counts(board, colour);
+            let control: i64 = ALL_SQUARES
+                .iter()
+                .map(|&sq| counts[sq.to_index()] as i64 * control_weight(sq, colour))
+                .sum();
+            favour(colour) * control
+        })
+        .sum()
+}
+
+/// Pieces with more safe squares to go to are worth more.
+pub(crate) fn calc_mobility(board: &Board, params: &EvalParams) -> i64 {
+    if !params.mobility {
+        return 0;
+    }
+    ALL_COLORS
+        .iter()
+        .map(|&colour| favour(colour) * mobility::mobility(board, colour))
+        .sum()
+}
+
+pub(crate) fn calc_pawn_structure(board: &Board) -> i64 {
+    favour(Color::White) * pawns::evaluate(board)
+}
+
+/// Pieces pinned to their king can't move off the pin line, and the more
+/// valuable they are the more the pin hurts.
+pub(crate) fn calc_pins(board: &Board) -> i64 {
+    ALL_COLORS
+        .iter()
+        .map(|&colour| {
+            let penalty: i64 = attacks::pinned(board, colour)
+                .filter_map(|sq| board.piece_on(sq))
+                .map(|piece| piece_values::piece_value(piece) / PINNED_PIECE_DIVISOR)
+                .sum();
+            -favour(colour) * penalty
+        })
+        .sum()
+}
+
+/// Remaining non-pawn material, from `MAX_PHASE` with every piece on the
+/// board down to 0 with bare kings and pawns. Promotions can push it above
+/// the maximum, so it is capped.
+pub fn game_phase(board: &Board) -> i64 {
+    let phase: i64 = piece_values::PIECES
+        .iter()
+        .zip(piece_values::PHASE_WEIGHTS.iter())
+        .map(|(&piece, &weight)| board.pieces(piece).popcnt() as i64 * weight)
+        .sum();
+    std::cmp::min(phase, piece_values::MAX_PHASE)
+}
+
+/// Minor pieces are worth more than their pawn count in the middlegame;
+/// the bonus fades out with the phase.
+pub(crate) fn calc_minor_piece_bonus(board: &Board, params: &EvalParams) -> i64 {
+    if !params.material {
+        return 0;
+    }
+    let bonus = params.minor_piece_bonus * game_phase(board) / piece_values::MAX_PHASE;
+    let minors = *board.pieces(Piece::Knight) | *board.pieces(Piece::Bishop);
+    ALL_COLORS
+        .iter()
+        .map(|&colour| {
+            let count = (minors & board.color_combined(colour)).popcnt() as i64;
+            favour(colour) * count * bonus
+        })
+        .sum()
+}
+
+pub(crate) fn calc_board_value(board: &Board, params: &EvalParams) -> i64 {
+    evaluate(board, params, &PieceScores::of(board, params), 0)
+}
+
+/// `calc_board_value` with the material and piece-square totals of `board`
+/// already known, as the search keeps them, and dead draws scored `draw`.
+/// Debug builds check the totals against a full count.
+pub(crate) fn evaluate(board: &Board, params: &EvalParams, scores: &PieceScores, draw: i64) -> i64 {
+    debug_assert_eq!(*scores, PieceScores::of(board, params), "{}", board);
+    let w_move = board.side_to_move() == Color::White;
+    match board.status() {
+        BoardStatus::Ongoing if endgame::is_insufficient_material(board) => draw,
+        BoardStatus::Ongoing => {
+            let value = scores.value(game_phase(board))
+                + calc_king_safety(board)
+                + calc_minor_piece_bonus(board, params)
+                + calc_stalemate_risk(board)
+                + calc_colour_complex(board)
+                + calc_pins(board)
+                + calc_attack_map(board, params)
+                + calc_pawn_structure(board)
+                + calc_mobility(board, params);
+            match params.clamp {
+                Some(limit) => value.clamp(-limit, limit),
+                None => value,
+            }
+        }
+        BoardStatus::Stalemate => draw,
+        BoardStatus::Checkmate => {
+            if w_move {
+                -MATE_SCORE
+            } else {
+                MATE_SCORE
+            }
+        }
+    }
+}
+
+/// Sum of both sides' piece values, ignoring kings and pawns.
+pub(crate) fn non_pawn_material(board: &Board) -> i64 {
+    piece_values::PIECES
+        .iter()
+        .zip(piece_values::PIECE_VALS.iter())
+        .filter(|(&piece, _)| piece != Piece::King && piece != Piece::Pawn)
+        .map(|(&piece, &value)| board.pieces(piece).popcnt() as i64 * value)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{search, SearchConfig};
+    use std::str::FromStr;
+
+    fn value(fen: &str) -> i64 {
+        calc_board_value(&Board::from_str(fen).unwrap(), &EvalParams::default())
+    }
+
+    #[test]
+    fn extra_pawn_is_worth_about_a_pawn() {
+        let score = value("rnbqkbnr/1ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert!(
+            (score - piece_values::PAWN).abs() <= piece_values::PAWN / 4,
+            "{}",
+            score
+        );
+    }
+
+    #[test]
+    fn piece_beats_two_pawns_in_the_middlegame() {
+        // Black has the knight, White the a- and h-pawns.
+        let fen = "rnbqkbnr/1pppppp1/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1";
+        let board = Board::from_str(fen).unwrap();
+        assert!(calc_minor_piece_bonus(&board, &EvalParams::default()) < 0);
+        let score = value(fen);
+        assert!(score < -piece_values::PAWN / 2, "{}", score);
+    }
+
+    #[test]
+    fn boxing_the_bare_king_in_is_penalised() {
+        let boxed = Board::from_str("k7/2Q5/8/8/8/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(calc_stalemate_risk(&boxed), -STALEMATE_RISK_PENALTY);
+        let free = Board::from_str("k7/8/8/8/3Q4/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(calc_stalemate_risk(&free), 0);
+        let mv = search(&boxed, 4, &SearchConfig::default())
+            .best_move
+            .unwrap();
+        assert_ne!(
+            boxed.make_move_new(mv).status(),
+            BoardStatus::Stalemate,
+            "{}",
+            mv
+        );
+    }
+
+    #[test]
+    fn dark_squares_are_weak_without_the_dark_bishop() {
+        // White's pawns all stand on light squares.
+        let light_bishop = Board::from_str("4k3/8/8/8/8/8/P1P1P1P1/4KB2 w - - 0 1").unwrap();
+        assert_eq!(calc_colour_complex(&light_bishop), -COLOUR_COMPLEX_PENALTY);
+        let dark_bishop = Board::from_str("4k3/8/8/8/8/8/P1P1P1P1/2B1K3 w - - 0 1").unwrap();
+        assert_eq!(calc_colour_complex(&dark_bishop), 0);
+    }
+
+    #[test]
+    fn pinned_knight_is_penalised_and_does_not_defend() {
+        // The bishop and queen reach the king's zone either way, but the
+        // knight only defends f8 when the bishop isn't pinning it.
+        let pinned = Board::from_str("4k3/3n4/7Q/1B6/8/8/8/4K3 w - - 0 1").unwrap();
+        let unpinned = Board::from_str("4k3/3n4/7Q/8/8/1B6/8/4K3 w - - 0 1").unwrap();
+        let knight = piece_values::piece_value(Piece::Knight);
+        assert_eq!(calc_pins(&pinned), knight / PINNED_PIECE_DIVISOR);
+        assert_eq!(calc_pins(&unpinned), 0);
+        let attack = |board| king_safety::king_attack(board, Color::Black);
+        assert!(attack(&unpinned) > 0);
+        assert_eq!(attack(&pinned), 2 * attack(&unpinned));
+    }
+
+    #[test]
+    fn phase_runs_from_the_opening_to_bare_kings() {
+        assert_eq!(game_phase(&Board::default()), piece_values::MAX_PHASE);
+        let bare_kings = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game_phase(&bare_kings), 0);
+    }
+
+    #[test]
+    fn positional_only_scores_placement() {
+        let params = EvalParams {
+            material: false,
+            ..EvalParams::default()
+        };
+        // A rook and knight each, placed two ways. In the first White's
+        // knight is centralised and Black's cornered.
+        let active = Board::from_str("n3k2r/8/8/8/3N4/8/8/3RK3 w - - 0 1").unwrap();
+        let passive = Board::from_str("r3k1n1/8/8/8/8/8/8/N3K2R w - - 0 1").unwrap();
+        let (active, passive) = (
+            calc_board_value(&active, &params),
+            calc_board_value(&passive, &params),
+        );
+        assert_ne!(active, passive);
+        assert!(active > 0, "{}", active);
+        assert!(active.abs() < 2 * piece_values::PAWN, "{}", active);
+    }
+
+    #[test]
+    fn sides_are_scored_oppositely() {
+        assert_eq!(
+            value("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            0
+        );
+        let white_rook_up = value("1nbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQk - 0 1");
+        let black_rook_up = value("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/1NBQKBNR w Kkq - 0 1");
+        let rook = piece_values::piece_value(Piece::Rook);
+        assert!(
+            (white_rook_up - rook).abs() <= piece_values::PAWN,
+            "{}",
+            white_rook_up
+        );
+        assert_eq!(black_rook_up, -white_rook_up);
+    }
+
+    #[test]
+    fn controlling_the_centre_scores_higher() {
+        let params = EvalParams {
+            attack_map: true,
+            ..EvalParams::default()
+        };
+        let central = Board::from_str("nn2k3/8/8/8/3NN3/8/8/4K3 w - - 0 1").unwrap();
+        let passive = Board::from_str("nn2k3/8/8/8/8/8/8/NN2K3 w - - 0 1").unwrap();
+        let (central, passive) = (
+            calc_attack_map(&central, &params),
+            calc_attack_map(&passive, &params),
+        );
+        assert!(central > passive, "{} vs {}", central, passive);
+        assert_eq!(
+            calc_attack_map(&Board::default(), &EvalParams::default()),
+            0
+        );
+    }
+
+    #[test]
+    fn clamp_bounds_an_exaggerated_term() {
+        // With this bonus White's extra knight is worth a hundred pawns.
+        let board = Board::from_str("4k3/pppppppp/8/8/8/8/PPPPPPPP/1N2K3 w - - 0 1").unwrap();
+        let exaggerated = EvalParams {
+            minor_piece_bonus: 100 * piece_values::PAWN,
+            ..EvalParams::default()
+        };
+        let limit = 5 * piece_values::PAWN;
+        assert!(calc_board_value(&board, &exaggerated) > limit);
+        let clamped = EvalParams {
+            clamp: Some(limit),
+            ..exaggerated.clone()
+        };
+        assert_eq!(calc_board_value(&board, &clamped), limit);
+        let mirrored = Board::from_str("1n2k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1").unwrap();
+        assert_eq!(calc_board_value(&mirrored, &clamped), -limit);
+        let mated = Board::from_str("k6R/8/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(calc_board_value(&mated, &clamped), MATE_SCORE);
+    }
+}
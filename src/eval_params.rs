@@ -0,0 +1,170 @@
+use crate::piece_values::{self, PAWN};
+
+/// Default futility margins, in centipawns, indexed by remaining depth.
+pub const DEFAULT_FUTILITY_MARGINS: [i64; 4] = [0, PAWN, 2 * PAWN, 3 * PAWN];
+
+/// Default middlegame bonus for each knight and bishop, in centipawns.
+pub const DEFAULT_MINOR_PIECE_BONUS: i64 = PAWN / 2;
+
+/// Tunable evaluation settings. `Default` gives the built-in values.
+#[derive(Clone, Debug)]
+pub struct EvalParams {
+    /// Extra value of a minor piece with full material on the board, fading
+    /// to nothing in the endgame, so a piece outweighs three pawns in the
+    /// middlegame but not once the board empties.
+    pub minor_piece_bonus: i64,
+    /// How far outside the search window the static evaluation must be,
+    /// at each remaining depth, for futility pruning. Depths past the end
+    /// of the table, and depth 0, are never pruned.
+    pub futility_margins: Vec<i64>,
+    /// Count piece values, minor piece bonus included. Without them only
+    /// placement is scored, for looking at piece activity on its own.
+    pub material: bool,
+    /// Score control of the board, square by square, favouring the centre
+    /// and the enemy half.
+    pub attack_map: bool,
+    /// Score how many safe squares each knight, bishop, rook and queen
+    /// can reach.
+    pub mobility: bool,
+    /// Largest static evaluation either way, in centipawns, so one badly
+    /// weighted term can't run away with the score. Mates aren't clamped.
+    pub clamp: Option<i64>,
+    /// Material value of each piece, in `PIECES` order.
+    pub piece_values: [i64; 6],
+    /// Middlegame and endgame piece-square tables for White, in `PIECES`
+    /// order, a1 first.
+    pub piece_squares: [[i64; 64]; 6],
+    pub endgame_piece_squares: [[i64; 64]; 6],
+}
+
+impl Default for EvalParams {
+    fn default() -> EvalParams {
+        EvalParams {
+            minor_piece_bonus: DEFAULT_MINOR_PIECE_BONUS,
+            futility_margins: DEFAULT_FUTILITY_MARGINS.to_vec(),
+            material: true,
+            attack_map: false,
+            mobility: true,
+            clamp: None,
+            piece_values: piece_values::PIECE_VALS,
+            piece_squares: piece_values::PIECE_SQUARES,
+            endgame_piece_squares: piece_values::ENDGAME_PIECE_SQUARES,
+        }
+    }
+}
+
+impl EvalParams {
+    /// Futility margin for a node with `depth` plies left, if that depth is
+    /// pruned at all.
+    pub fn futility_margin(&self, depth: i8) -> Option<i64> {
+        if depth <= 0 {
+            return None;
+        }
+        self.futility_margins.get(depth as usize).copied()
+    }
+}
+
+/// How pieces are named in a parameter file, in `PIECES` order.
+pub const PIECE_NAMES: [&str; 6] = ["king", "queen", "bishop", "rook", "knight", "pawn"];
+
+/// Splits a parameter file into `key = value` pairs, dropping `#`
+/// comments. An array value may run over several lines.
+fn entries(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+    let mut pending: Option<(String, String)> = None;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = match pending.take() {
+            Some((key, value)) => (key, value + " " + line),
+            None => match line.split_once('=') {
+                Some((key, value)) => (key.trim().to_string(), value.trim().to_string()),
+                None => return Err(format!("line {}: expected key = value", number + 1)),
+            },
+        };
+        if value.starts_with('[') && !value.ends_with(']') {
+            pending = Some((key, value));
+            continue;
+        }
+        entries.push((key, value));
+    }
+    match pending {
+        Some((key, _)) => Err(format!("unterminated array for {}", key)),
+        None => Ok(entries),
+    }
+}
+
+fn number(key: &str, value: &str) -> Result<i64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{}: {} is not a whole number", key, value))
+}
+
+fn flag(key: &str, value: &str) -> Result<bool, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{}: {} is not true or false", key, value))
+}
+
+fn numbers(key: &str, value: &str) -> Result<Vec<i64>, String> {
+    let inner = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .ok_or_else(|| format!("{}: expected an array", key))?;
+    inner
+        .split(',')
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(|n| number(key, n))
+        .collect()
+}
+
+fn table(key: &str, value: &str) -> Result<[i64; 64], String> {
+    let values = numbers(key, value)?;
+    values
+        .try_into()
+        .map_err(|v: Vec<i64>| format!("{}: expected 64 values, got {}", key, v.len()))
+}
+
+impl EvalParams {
+    /// Reads a parameter file, a small subset of TOML such as
+    ///
+    /// ```text
+    /// queen_value = 900
+    /// mobility = false
+    /// knight_squares = [ -50, -40, ... ]   # 64 values, a1 first
+    /// ```
+    ///
+    /// on top of `self`: whatever the file leaves out keeps its value.
+    /// Keys are the field names, `clamp` and `futility_margins` included,
+    /// with `<piece>_value`, `<piece>_squares` and `<piece>_endgame_squares`
+    /// for the material and tables.
+    pub fn load(&self, path: &str) -> Result<EvalParams, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut params = self.clone();
+        for (key, value) in entries(&text)? {
+            let piece_key = PIECE_NAMES.iter().enumerate().find_map(|(idx, name)| {
+                key.strip_prefix(name)
+                    .and_then(|rest| rest.strip_prefix('_'))
+                    .map(|rest| (idx, rest))
+            });
+            match (key.as_str(), piece_key) {
+                ("minor_piece_bonus", _) => params.minor_piece_bonus = number(&key, &value)?,
+                ("futility_margins", _) => params.futility_margins = numbers(&key, &value)?,
+                ("material", _) => params.material = flag(&key, &value)?,
+                ("attack_map", _) => params.attack_map = flag(&key, &value)?,
+                ("mobility", _) => params.mobility = flag(&key, &value)?,
+                ("clamp", _) => params.clamp = Some(number(&key, &value)?),
+                (_, Some((idx, "value"))) => params.piece_values[idx] = number(&key, &value)?,
+                (_, Some((idx, "squares"))) => params.piece_squares[idx] = table(&key, &value)?,
+                (_, Some((idx, "endgame_squares"))) => {
+                    params.endgame_piece_squares[idx] = table(&key, &value)?
+                }
+                _ => return Err(format!("unknown parameter {}", key)),
+            }
+        }
+        Ok(params)
+    }
+}
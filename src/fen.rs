@@ -0,0 +1,89 @@
+use crate::game_state::Clocks;
+use chess::Board;
+use std::str::FromStr;
+
+/// Writes `board` as FEN with the given move counters. `Board`'s own
+/// `Display` names the pawn that can be taken en passant rather than the
+/// square behind it, and always writes `0 1` for the counters.
+pub fn to_fen(board: &Board, clocks: Clocks) -> String {
+    let written = board.to_string();
+    let mut fields: Vec<String> = written
+        .split_whitespace()
+        .take(3)
+        .map(str::to_string)
+        .collect();
+    let en_passant = board
+        .en_passant()
+        .and_then(|pawn| pawn.forward(board.side_to_move()));
+    fields.push(en_passant.map_or("-".to_string(), |sq| sq.to_string()));
+    fields.push(clocks.halfmove.to_string());
+    fields.push(clocks.fullmove.to_string());
+    fields.join(" ")
+}
+
+/// `fen` with every field present and the castling rights in `KQkq`
+/// order, the form `to_fen` writes.
+pub fn canonical(fen: &str) -> String {
+    let mut fields: Vec<String> = fen.split_whitespace().map(str::to_string).collect();
+    for (idx, default) in ["", "w", "-", "-", "0", "1"].iter().enumerate() {
+        if fields.len() <= idx {
+            fields.push(default.to_string());
+        }
+    }
+    let castling: String = "KQkq".chars().filter(|&c| fields[2].contains(c)).collect();
+    fields[2] = if castling.is_empty() {
+        "-".to_string()
+    } else {
+        castling
+    };
+    fields[..6].join(" ")
+}
+
+/// Parses `fen` and writes it back out, returning the written FEN if it
+/// differs from the canonical input. An en passant square no pawn can
+/// actually capture on is dropped by `Board`, and isn't a mismatch.
+pub fn roundtrip(fen: &str) -> Result<(), String> {
+    let board = Board::from_str(fen).map_err(|e| e.to_string())?;
+    let written = to_fen(&board, Clocks::from_fen(fen));
+
+    let mut expected = canonical(fen);
+    if board.en_passant().is_none() {
+        let mut fields: Vec<&str> = expected.split(' ').collect();
+        fields[3] = "-";
+        expected = fields.join(" ");
+    }
+    if written == expected {
+        Ok(())
+    } else {
+        Err(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{benchmarks, STARTING_FEN};
+
+    #[test]
+    fn benchmark_fens_round_trip() {
+        for (name, fen) in benchmarks::CASES {
+            assert_eq!(roundtrip(fen), Ok(()), "{}", name);
+        }
+        assert_eq!(roundtrip(STARTING_FEN), Ok(()));
+    }
+
+    #[test]
+    fn round_trip_allows_for_normalisation() {
+        // Castling rights out of order, missing counters, and an en
+        // passant square no pawn can take on.
+        assert_eq!(
+            canonical("4k2r/8/8/8/8/8/8/R3K3 w kQ -"),
+            "4k2r/8/8/8/8/8/8/R3K3 w Qk - 0 1"
+        );
+        assert_eq!(roundtrip("4k2r/8/8/8/8/8/8/R3K3 w kQ -"), Ok(()));
+        assert_eq!(
+            roundtrip("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,1119 @@
+use crate::book::LearnedBook;
+use crate::display::{eval_bar, eval_text, format_line, human_score, show_game};
+use crate::eval::{calc_board_value, non_pawn_material};
+use crate::game_state::GameState;
+use crate::input::CommandInput;
+use crate::modes::run_perft;
+use crate::search::{
+    game_search_config, score_to_cp, score_to_white, search, search_json, SearchConfig,
+    SearchResult,
+};
+use crate::session::Session;
+use crate::time_manager::{GameClock, Instant};
+use crate::{
+    game_state, interrupt, json, match_runner, perft, pgn, piece_values, polyglot, rng, see,
+    time_manager, trace, tuning, MAX_DEPTH, PROGRAM_NAME,
+};
+use chess::{Board, BoardStatus, ChessMove, Color};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How interactive and self play games are searched, shown and saved:
+/// the command line settings the game loops read.
+pub struct PlayOptions {
+    /// Search the expected reply while the human thinks.
+    pub ponder: bool,
+    /// Print principal variations in SAN rather than coordinates.
+    pub pv_san: bool,
+    /// Show the board from Black's side, as when playing Black.
+    pub flip: bool,
+    /// Self-play pacing in milliseconds per legal move, 0 for none.
+    pub move_time_per_piece: u64,
+    /// Print newline-delimited JSON objects instead of text.
+    pub json: bool,
+    pub suggest_draw: bool,
+    pub human_score: bool,
+    pub eval_bar: bool,
+    pub show_clocks: bool,
+    pub depth: i8,
+    pub eval_tune_dump: Option<String>,
+    pub selfplay_pgn: Option<String>,
+    /// Book the openings of finished self play games are counted into.
+    pub learn_book: Option<String>,
+    /// Book the engine draws its opening moves from.
+    pub learned_book: Option<String>,
+    /// Polyglot opening book, tried before `learned_book`.
+    pub book: Option<String>,
+    /// Moves of each side played from `book` before the search takes over.
+    pub book_moves: usize,
+    pub book_selection: polyglot::Selection,
+    /// File the game is written to when it ends.
+    pub pgn: Option<String>,
+    /// Clocks for interactive and self play games.
+    pub tc: Option<match_runner::TimeControl>,
+    pub config: SearchConfig,
+}
+
+/// Score band, in centipawns, treated as level for draw suggestions.
+const DRAW_OFFER_BAND: i64 = piece_values::PAWN * 15 / 100;
+/// Consecutive level engine searches needed before a draw is suggested.
+const DRAW_OFFER_SEARCHES: u32 = 6;
+/// Non-pawn material (both sides) at or below which a draw may be suggested.
+const DRAW_OFFER_MATERIAL: i64 = 16 * piece_values::PAWN;
+
+/// `Event` tag of the self-play games written as PGN.
+const SELFPLAY_EVENT: &str = "Scacchi self-play";
+/// `Event` tag of an interactive game written with `--pgn`.
+const INTERACTIVE_EVENT: &str = "Scacchi interactive game";
+/// Name the PGN gives the human in an interactive game.
+const HUMAN_PLAYER: &str = "Human";
+
+/// Candidate moves listed by the interactive `analyze` command.
+const ANALYZE_LINES: usize = 3;
+
+/// Tracks how long the engine's score has stayed level in a reduced-material
+/// position, so analysis users can be told when a draw would be reasonable.
+struct DrawOffer {
+    level_searches: u32,
+}
+
+impl DrawOffer {
+    pub(crate) fn new() -> DrawOffer {
+        DrawOffer { level_searches: 0 }
+    }
+
+    /// Records the score of the latest engine search and returns true once
+    /// the position has looked dead equal for long enough.
+    fn update(&mut self, board: &Board, score: i64) -> bool {
+        let reduced = non_pawn_material(board) <= DRAW_OFFER_MATERIAL;
+        if reduced && score.abs() <= DRAW_OFFER_BAND {
+            self.level_searches += 1;
+        } else {
+            self.level_searches = 0;
+        }
+        self.level_searches >= DRAW_OFFER_SEARCHES
+    }
+}
+
+/// A search of the position after the reply the engine expects, run
+/// while the human thinks about their move, for `--ponder`.
+pub(crate) struct Ponder {
+    /// The predicted position, and how many plies into the game it is.
+    pub(crate) hash: u64,
+    pub(crate) plies: usize,
+    pub(crate) depth: i8,
+    pub(crate) stop: Arc<AtomicBool>,
+    pub(crate) handle: std::thread::JoinHandle<SearchResult>,
+}
+
+impl Ponder {
+    /// Starts searching to `depth` after the reply `pv` predicts to the
+    /// move just played in `game`.
+    pub(crate) fn start(
+        game: &GameState,
+        pv: &[ChessMove],
+        depth: i8,
+        opts: &PlayOptions,
+    ) -> Option<Ponder> {
+        let reply = *pv.get(1)?;
+        if !game.is_legal(reply) {
+            return None;
+        }
+        let mut predicted = game.clone();
+        predicted.make_move(reply);
+        let board = predicted.board();
+        if predicted.status() != BoardStatus::Ongoing {
+            return None;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let config = SearchConfig {
+            stop: Some(Arc::clone(&stop)),
+            info: false,
+            thinking: false,
+            ..game_search_config(&predicted, &opts.config)
+        };
+        let handle = std::thread::spawn(move || search(&board, depth, &config));
+        Some(Ponder {
+            hash: board.get_hash(),
+            plies: predicted.plies_played(),
+            depth,
+            stop,
+            handle,
+        })
+    }
+
+    /// The finished search if `game` is now at the predicted position and
+    /// still wants `depth`. Otherwise the search is stopped and dropped.
+    pub(crate) fn finish(self, game: &GameState, depth: i8) -> Option<SearchResult> {
+        let hit = game.board().get_hash() == self.hash
+            && game.plies_played() == self.plies
+            && depth == self.depth;
+        if !hit {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        let result = self.handle.join().ok();
+        result.filter(|_| hit)
+    }
+}
+
+/// `config` for the engine's move in `game`, with its time taken from
+/// `clock` in a timed game, and the depth to search to.
+fn timed_search_config(
+    game: &GameState,
+    config: &SearchConfig,
+    depth: i8,
+    clock: Option<&GameClock>,
+) -> (SearchConfig, i8) {
+    let config = game_search_config(game, config);
+    match clock {
+        Some(clock) => (
+            SearchConfig {
+                movetime: Some(clock.budget(game.board().side_to_move())),
+                ..config
+            },
+            MAX_DEPTH,
+        ),
+        None => (config, depth),
+    }
+}
+
+pub(crate) fn colour_name(colour: Color) -> &'static str {
+    match colour {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+/// Takes the time since `started` off `side`'s clock in a timed game.
+/// Returns false if its flag fell.
+fn charge_clock(clock: &mut Option<GameClock>, side: Color, started: Instant) -> bool {
+    match clock {
+        Some(clock) => clock.charge(side, started.elapsed().as_millis() as u64),
+        None => true,
+    }
+}
+
+/// Plays the engine's move in `game`, searching for it unless `pondered`
+/// already holds the search of this position. Returns the search.
+fn exec_ai_turn(
+    game: &mut GameState,
+    opts: &PlayOptions,
+    depth: i8,
+    draw_offer: &mut DrawOffer,
+    pondered: Option<SearchResult>,
+    clock: Option<&GameClock>,
+    avoid: Vec<ChessMove>,
+) -> SearchResult {
+    let result = match pondered {
+        Some(result) => {
+            println!("Ponder hit");
+            result
+        }
+        None => {
+            let (config, depth) = timed_search_config(game, &opts.config, depth, clock);
+            let config = SearchConfig {
+                excluded_moves: avoid,
+                ..config
+            };
+            search(&game.board(), depth, &config)
+        }
+    };
+    if !result.pv.is_empty() {
+        println!(
+            "Line: {}",
+            format_line(
+                &game.board(),
+                game.clocks().fullmove,
+                &result.pv,
+                opts.pv_san
+            )
+        );
+    }
+    match result.best_move {
+        Some(n) => {
+            game.make_move(n);
+            println!("Eval: {}", eval_text(score_to_white(result.score)));
+        }
+        None => {
+            println!("Error!! No move found")
+        }
+    }
+    if opts.human_score {
+        println!("Score: {}", human_score(score_to_white(result.score)));
+    }
+    if opts.eval_bar {
+        println!("{}", eval_bar(score_to_white(result.score)));
+    }
+    if draw_offer.update(&game.board(), result.score)
+        && opts.suggest_draw
+        && !opts.config.analyse_mode
+    {
+        println!("info string draw suggested");
+    }
+    println!("--------------------");
+    show_game(game, opts.show_clocks, opts.flip);
+    result
+}
+
+/// Handles `analyze`, printing the best few moves for the side to move
+/// without playing any of them. Every root move gets a full-width search
+/// of its own, so the scores below the best are exact too. Returns false
+/// if `cmd` isn't an analyze command at all.
+fn exec_analyze(game: &GameState, depth: i8, config: &SearchConfig, cmd: &str) -> bool {
+    if cmd.trim() != "analyze" {
+        return false;
+    }
+    for (rank, (mv, cp)) in analyze_lines(game, depth, config)
+        .iter()
+        .take(ANALYZE_LINES)
+        .enumerate()
+    {
+        println!("{}. {} {:+}", rank + 1, mv, cp);
+    }
+    true
+}
+
+/// Every legal move in `game` with its score in centipawns for the side to
+/// move, best first, as `analyze` ranks them.
+fn analyze_lines(game: &GameState, depth: i8, config: &SearchConfig) -> Vec<(ChessMove, i64)> {
+    let board = game.board();
+    let mut lines: Vec<(ChessMove, i64)> = game
+        .legal_moves()
+        .into_iter()
+        .map(|mv| {
+            let mut child = Board::default();
+            let castling = game.castling().play(&board, mv, &mut child);
+            let score = if child.status() == BoardStatus::Ongoing {
+                let config = SearchConfig {
+                    halfmove_clock: game_state::next_halfmove(&board, mv, game.clocks().halfmove),
+                    castling,
+                    ..config.clone()
+                };
+                search(&child, depth - 1, &config).score
+            } else {
+                calc_board_value(&child, &config.eval)
+            };
+            (mv, score_to_cp(&board, score))
+        })
+        .collect();
+    lines.sort_by_key(|&(_, cp)| -cp);
+    lines
+}
+
+/// Handles `takeback N`, undoing N plies. Returns false if `cmd` isn't a
+/// takeback command at all.
+fn exec_takeback(game: &mut GameState, cmd: &str) -> bool {
+    let mut words = cmd.split_whitespace();
+    if words.next() != Some("takeback") {
+        return false;
+    }
+    match words.next().map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) if game.takeback(n) => {
+            println!("Took back {} plies", n);
+        }
+        Some(Ok(n)) => println!(
+            "Cannot take back {} plies, only {} played",
+            n,
+            game.plies_played()
+        ),
+        _ => println!("Usage: takeback N"),
+    }
+    true
+}
+
+/// Handles `undo`, taking back the user's last move and the engine's reply
+/// so it is the user's turn again. Returns false if `cmd` isn't `undo`.
+fn exec_undo(game: &mut GameState, cmd: &str) -> bool {
+    if cmd.trim() != "undo" {
+        return false;
+    }
+    if game.takeback(2) {
+        println!("Took back your last move");
+    } else {
+        println!("Nothing to undo");
+    }
+    true
+}
+
+/// Handles `perft N`, printing the leaf count N plies below the current
+/// position, and `divide N`, which splits it by move. Returns false if
+/// `cmd` is neither.
+fn exec_perft(game: &GameState, cmd: &str) -> bool {
+    let mut words = cmd.split_whitespace();
+    let command = words.next();
+    if !matches!(command, Some("perft" | "divide")) {
+        return false;
+    }
+    match words.next().map(|n| n.parse::<u32>()) {
+        Some(Ok(n)) if command == Some("divide") => run_perft(game, n, false),
+        Some(Ok(n)) => println!("Nodes: {}", perft::perft(&game.board(), game.castling(), n)),
+        _ => println!("Usage: {} N", command.unwrap_or_default()),
+    }
+    true
+}
+
+/// Handles `see MOVE`, printing the static exchange evaluation of a
+/// move. Returns false if `cmd` isn't a see command at all.
+fn exec_see(game: &GameState, cmd: &str) -> bool {
+    let mut words = cmd.split_whitespace();
+    if words.next() != Some("see") {
+        return false;
+    }
+    let board = game.board();
+    match words.next().and_then(|text| parse_user_move(game, text)) {
+        Some(mv) => println!("SEE {}: {:+}", pgn::san(&board, mv), see::see(&board, mv)),
+        None => println!("Usage: see MOVE, with a legal move"),
+    }
+    true
+}
+
+/// Handles `depth N`, changing the engine's search depth for its next
+/// moves. Returns false if `cmd` isn't a depth command at all.
+fn exec_set_depth(depth: &mut i8, cmd: &str) -> bool {
+    let mut words = cmd.split_whitespace();
+    if words.next() != Some("depth") {
+        return false;
+    }
+    match words.next().map(|n| n.parse::<i8>()) {
+        Some(Ok(n)) if (1..=MAX_DEPTH).contains(&n) => {
+            *depth = n;
+            println!("Depth: {}", n);
+        }
+        _ => println!("Usage: depth N, with N from 1 to {}", MAX_DEPTH),
+    }
+    true
+}
+
+/// Handles `save FILE`, writing the game to a session file that `load`
+/// and `--resume` read back. Returns false if `cmd` isn't a save command
+/// at all.
+fn exec_save(session: &Session, cmd: &str) -> bool {
+    let mut words = cmd.split_whitespace();
+    if words.next() != Some("save") {
+        return false;
+    }
+    match words.next() {
+        Some(path) => match session.save(path) {
+            Ok(()) => println!("Saved the game to {}", path),
+            Err(e) => println!("Cannot save the game to {}: {}", path, e),
+        },
+        None => println!("Usage: save FILE"),
+    }
+    true
+}
+
+/// Handles `load FILE`, replacing the game with one `save` wrote. Returns
+/// whether it loaded, or `None` if `cmd` isn't a load command at all.
+fn exec_load(session: &mut Session, cmd: &str) -> Option<bool> {
+    let mut words = cmd.split_whitespace();
+    if words.next() != Some("load") {
+        return None;
+    }
+    let Some(path) = words.next() else {
+        println!("Usage: load FILE");
+        return Some(false);
+    };
+    match Session::load(path) {
+        Ok(loaded) => {
+            *session = loaded;
+            println!("Loaded the game from {}", path);
+            Some(true)
+        }
+        Err(e) => {
+            println!("Cannot load {}: {}", path, e);
+            Some(false)
+        }
+    }
+}
+
+enum UserTurn {
+    Moved,
+    TookBack,
+    /// `load` replaced the game with a saved one.
+    Loaded,
+    Resigned,
+    /// The input ran out, or the user quit.
+    InputEnded,
+}
+
+/// Prints why the engine would play what it does in the current position
+/// of `game`: the evaluation by term, then a search to `depth` with every
+/// pass over the root moves, its window, how the score came out against it
+/// and how often the table helped. The trace follows a single thread.
+pub fn run_trace(game: &GameState, depth: i8, config: &SearchConfig, json: bool) {
+    let board = game.board();
+    trace::print_eval(&board, &config.eval, json);
+    let config = SearchConfig {
+        trace: true,
+        threads: 1,
+        ..game_search_config(game, config)
+    };
+    let result = search(&board, depth, &config);
+    trace::print_search(&result.trace, json);
+    if !json {
+        match result.best_move {
+            Some(mv) => println!("Best Move: {}", pgn::san(&board, mv)),
+            None => println!("No move to play"),
+        }
+    }
+}
+
+/// Handles `hint`, showing the move the engine would play, `fen`, printing
+/// the position, `eval`, its static evaluation, and `why`, a trace of the
+/// search for the last move played. Returns false if `cmd` is none of them.
+fn exec_info_command(game: &GameState, depth: i8, opts: &PlayOptions, cmd: &str) -> bool {
+    let board = game.board();
+    match cmd.trim() {
+        "hint" => {
+            let config = game_search_config(game, &opts.config);
+            match search(&board, depth, &config).best_move {
+                Some(mv) => println!("Hint: {}", pgn::san(&board, mv)),
+                None => println!("No move to suggest"),
+            }
+        }
+        "fen" => println!("{}", game.fen()),
+        "why" => {
+            let mut before = game.clone();
+            if before.takeback(1) {
+                let mv = game.moves()[game.moves().len() - 1];
+                println!("Why {}:", pgn::san(&before.board(), mv));
+            }
+            run_trace(&before, depth, &opts.config, opts.json);
+        }
+        "eval" => println!(
+            "Eval: {}",
+            eval_text(score_to_white(calc_board_value(&board, &opts.config.eval)))
+        ),
+        _ => return false,
+    }
+    true
+}
+
+/// Reads a move typed as SAN (`Nf3`) or in coordinates (`g1f3`, `e7e8q`),
+/// if it is legal in `game`. A Chess960 castle in coordinates is the king
+/// taking its own rook.
+fn parse_user_move(game: &GameState, text: &str) -> Option<ChessMove> {
+    pgn::parse_san(&game.board(), game.castling(), text.trim()).or_else(|| {
+        ChessMove::from_str(text)
+            .ok()
+            .filter(|&mv| game.is_legal(mv))
+    })
+}
+
+/// Reads commands until one is a legal move or a successful takeback.
+fn exec_user_turn(session: &mut Session, input: &mut CommandInput, opts: &PlayOptions) -> UserTurn {
+    let outcome = loop {
+        let s = match input.next_line() {
+            Some(l) => l,
+            None => return UserTurn::InputEnded,
+        };
+
+        if exec_save(session, &s) {
+            continue;
+        }
+        match exec_load(session, &s) {
+            Some(true) => break UserTurn::Loaded,
+            Some(false) => continue,
+            None => {}
+        }
+        let Session { game, depth, .. } = session;
+        if exec_set_depth(depth, &s)
+            || exec_analyze(game, *depth, &opts.config, &s)
+            || exec_perft(game, &s)
+            || exec_see(game, &s)
+            || exec_info_command(game, *depth, opts, &s)
+        {
+            continue;
+        }
+        match s.trim() {
+            "resign" => return UserTurn::Resigned,
+            "quit" => return UserTurn::InputEnded,
+            _ => {}
+        }
+        let plies = game.plies_played();
+        if exec_takeback(game, &s) || exec_undo(game, &s) {
+            if game.plies_played() < plies {
+                break UserTurn::TookBack;
+            }
+            continue;
+        }
+        if let Some(mv) = parse_user_move(game, &s) {
+            game.make_move(mv);
+            break UserTurn::Moved;
+        } else {
+            let legal: Vec<String> = game
+                .legal_moves()
+                .into_iter()
+                .map(|mv| pgn::san(&game.board(), mv))
+                .collect();
+            println!("Invalid Move. Legal moves: {}", legal.join(" "));
+        }
+    };
+    println!("--------------------");
+    show_game(&session.game, opts.show_clocks, opts.flip);
+    outcome
+}
+
+/// Plays `session` against the user, who types moves and commands on
+/// `input`, and gives it back as the game ended or the input ran out.
+pub fn interactive_loop(
+    mut session: Session,
+    opts: &PlayOptions,
+    input: &mut CommandInput,
+) -> Session {
+    let mut draw_offer = DrawOffer::new();
+    let mut ponder: Option<Ponder> = None;
+    let book = load_learned_book(opts);
+    let polyglot = load_polyglot_book(opts);
+    let mut rng = book_rng(opts);
+    // A game the input ran out on has no result yet.
+    let result = loop {
+        let (ai_colour, depth) = (session.engine, session.depth);
+        match session.game.status() {
+            BoardStatus::Ongoing => {
+                if let Some(rule) = session.game.draw_by_rule() {
+                    println!("Draw by {}", rule);
+                    break "1/2-1/2";
+                }
+                let side = session.game.board().side_to_move();
+                let started = Instant::now();
+                if side == ai_colour {
+                    let players = match ai_colour {
+                        Color::White => (PROGRAM_NAME, HUMAN_PLAYER),
+                        Color::Black => (HUMAN_PLAYER, PROGRAM_NAME),
+                    };
+                    save_on_interrupt(
+                        &session.game,
+                        opts.pgn.as_ref(),
+                        None,
+                        INTERACTIVE_EVENT,
+                        players,
+                    );
+                    if play_book_move(
+                        &mut session.game,
+                        polyglot.as_ref(),
+                        book.as_ref(),
+                        &mut rng,
+                        opts,
+                    ) {
+                        // A book move needs nothing pondered.
+                        if let Some(ponder) = ponder.take() {
+                            ponder.stop.store(true, Ordering::Relaxed);
+                        }
+                    } else {
+                        let pondered = ponder.take().and_then(|p| p.finish(&session.game, depth));
+                        let avoid = book.as_ref().map_or_else(Vec::new, |book| {
+                            book.refuted(&session.game.board(), session.game.plies_played())
+                        });
+                        let result = exec_ai_turn(
+                            &mut session.game,
+                            opts,
+                            depth,
+                            &mut draw_offer,
+                            pondered,
+                            session.clock.as_ref(),
+                            avoid,
+                        );
+                        // A timed ponder search would never stop on its own.
+                        if opts.ponder && session.clock.is_none() {
+                            ponder = Ponder::start(&session.game, &result.pv, depth, opts);
+                        }
+                    }
+                } else {
+                    println!("Your turn...");
+                    match exec_user_turn(&mut session, input, opts) {
+                        UserTurn::InputEnded => break "*",
+                        UserTurn::Resigned => {
+                            println!("You resigned");
+                            break match_runner::loss(!ai_colour);
+                        }
+                        UserTurn::Moved => {}
+                        // The clocks run on, but the turn starts over.
+                        UserTurn::TookBack => continue,
+                        // The saved clocks stand as they were saved.
+                        UserTurn::Loaded => {
+                            draw_offer = DrawOffer::new();
+                            continue;
+                        }
+                    }
+                }
+                if !charge_clock(&mut session.clock, side, started) {
+                    // A move made after the flag fell doesn't count.
+                    session.game.takeback(1);
+                    println!("{} lost on time", colour_name(side));
+                    break match_runner::loss(side);
+                }
+                if let Some(clock) = &session.clock {
+                    println!("{}", clock);
+                }
+            }
+            BoardStatus::Stalemate => {
+                println!("Stalemate...");
+                break "1/2-1/2";
+            }
+            BoardStatus::Checkmate => {
+                println!("Checkmate!!");
+                break tuning::game_result(&session.game.board());
+            }
+        }
+    };
+    if let Some(ponder) = ponder {
+        ponder.stop.store(true, Ordering::Relaxed);
+    }
+    interrupt::set_exit_hook(None);
+
+    if let Some(path) = &opts.pgn {
+        let players = match session.engine {
+            Color::White => (PROGRAM_NAME, HUMAN_PLAYER),
+            Color::Black => (HUMAN_PLAYER, PROGRAM_NAME),
+        };
+        save_pgn(path, &session.game, INTERACTIVE_EVENT, players, result);
+    }
+    session
+}
+
+/// In `--json` self play, searches and plays one move, printing it with
+/// its search. Returns false if there was no move to play.
+fn exec_ai_turn_json(
+    game: &mut GameState,
+    opts: &PlayOptions,
+    clock: Option<&GameClock>,
+    avoid: Vec<ChessMove>,
+) -> bool {
+    let board = game.board();
+    let started = Instant::now();
+    let (config, depth) = timed_search_config(game, &opts.config, opts.depth, clock);
+    let config = SearchConfig {
+        excluded_moves: avoid,
+        ..config
+    };
+    let result = search(&board, depth, &config);
+    let Some(mv) = result.best_move else {
+        return false;
+    };
+    let ms = started.elapsed().as_millis();
+    println!(
+        "{}",
+        search_json(&board, &result, ms)
+            .raw("ply", game.plies_played() + 1)
+            .str("san", pgn::san(&board, mv))
+    );
+    game.make_move(mv);
+    true
+}
+
+/// The `--learned-book`, if one is given and can be read.
+fn load_learned_book(opts: &PlayOptions) -> Option<LearnedBook> {
+    let path = opts.learned_book.as_ref()?;
+    match LearnedBook::load(path) {
+        Ok(book) => Some(book),
+        Err(e) => {
+            println!("Cannot read book {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// The `--book`, if one is given and can be read.
+fn load_polyglot_book(opts: &PlayOptions) -> Option<polyglot::Book> {
+    let path = opts.book.as_ref()?;
+    match polyglot::Book::load(path) {
+        Ok(book) => Some(book),
+        Err(e) => {
+            println!("Cannot read book {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Draws for the book's moves from `--seed`, or the clock without one.
+fn book_rng(opts: &PlayOptions) -> rng::Rng {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+    let seed = opts.config.seed;
+    rng::Rng::new(seed.unwrap_or_else(|| now.map(|d| d.as_nanos() as u64).unwrap_or(0)))
+}
+
+/// Plays a move the Polyglot book, while still in its `--book-moves`, or
+/// failing it the learned `book` picks for the side to move, shown as a
+/// search move would be. Returns false, playing nothing, if the engine
+/// should search.
+fn play_book_move(
+    game: &mut GameState,
+    polyglot: Option<&polyglot::Book>,
+    book: Option<&LearnedBook>,
+    rng: &mut rng::Rng,
+    opts: &PlayOptions,
+) -> bool {
+    let board = game.board();
+    let from_polyglot = polyglot
+        .filter(|_| game.plies_played() < 2 * opts.book_moves)
+        .and_then(|book| book.pick(&board, opts.book_selection, rng));
+    let Some(mv) =
+        from_polyglot.or_else(|| book.and_then(|book| book.pick(&board, game.plies_played(), rng)))
+    else {
+        return false;
+    };
+    if opts.json {
+        let line = json::Object::new()
+            .str("bestmove", mv)
+            .raw("book", true)
+            .raw("ply", game.plies_played() + 1)
+            .str("san", pgn::san(&board, mv));
+        println!("{}", line);
+        game.make_move(mv);
+        return true;
+    }
+    println!("Book move: {}", pgn::san(&board, mv));
+    game.make_move(mv);
+    println!("--------------------");
+    show_game(game, opts.show_clocks, opts.flip);
+    true
+}
+
+pub fn self_play_loop(start: &GameState, opts: &PlayOptions) {
+    let mut game = start.clone();
+    let mut draw_offer = DrawOffer::new();
+    let mut reason = None;
+    let mut clock = opts.tc.map(|tc| GameClock::new(tc.base, tc.increment));
+    // The side that lost on time, if one did.
+    let mut flagged = None;
+    let book = load_learned_book(opts);
+    let polyglot = load_polyglot_book(opts);
+    let mut rng = book_rng(opts);
+    while game.status() == BoardStatus::Ongoing {
+        if let Some(rule) = game.draw_by_rule() {
+            if !opts.json {
+                println!("Draw by {}", rule);
+            }
+            reason = Some(rule);
+            break;
+        }
+        let start = Instant::now();
+        let side = game.board().side_to_move();
+        let legal_moves = game.legal_moves().len();
+        save_on_interrupt(
+            &game,
+            opts.pgn.as_ref(),
+            opts.selfplay_pgn.as_ref(),
+            SELFPLAY_EVENT,
+            (PROGRAM_NAME, PROGRAM_NAME),
+        );
+        let avoid = book.as_ref().map_or_else(Vec::new, |book| {
+            book.refuted(&game.board(), game.plies_played())
+        });
+        if play_book_move(&mut game, polyglot.as_ref(), book.as_ref(), &mut rng, opts) {
+            // Played without a search.
+        } else if opts.json {
+            if !exec_ai_turn_json(&mut game, opts, clock.as_ref(), avoid) {
+                break;
+            }
+        } else {
+            exec_ai_turn(
+                &mut game,
+                opts,
+                opts.depth,
+                &mut draw_offer,
+                None,
+                clock.as_ref(),
+                avoid,
+            );
+        }
+        if !charge_clock(&mut clock, side, start) {
+            game.takeback(1);
+            if !opts.json {
+                println!("{} lost on time", colour_name(side));
+            }
+            reason = Some("time forfeit");
+            flagged = Some(side);
+            break;
+        }
+        if opts.json {
+            continue;
+        }
+        if let Some(clock) = &clock {
+            println!("{}", clock);
+        }
+        // Purely cosmetic: pad the move out so busier positions visibly
+        // take longer to watch.
+        let delay = time_manager::pacing_delay(opts.move_time_per_piece, legal_moves);
+        std::thread::sleep(delay.saturating_sub(start.elapsed()));
+    }
+    interrupt::set_exit_hook(None);
+    let result = match flagged {
+        Some(side) => match_runner::loss(side),
+        None => tuning::game_result(&game.board()),
+    };
+    if opts.json {
+        let board = game.board();
+        let reason = match board.status() {
+            BoardStatus::Checkmate => "checkmate",
+            BoardStatus::Stalemate => "stalemate",
+            BoardStatus::Ongoing => reason.unwrap_or("no move"),
+        };
+        let summary = json::Object::new()
+            .str("result", result)
+            .str("reason", reason)
+            .raw("plies", game.plies_played());
+        println!("{}", summary);
+    }
+
+    if let Some(path) = &opts.eval_tune_dump {
+        let eval = |b: &Board| score_to_white(calc_board_value(b, &opts.config.eval));
+        match tuning::append_records(path, &game.positions(), result, eval) {
+            Ok(n) => println!("Wrote {} positions to {}", n, path),
+            Err(e) => println!("Cannot write {}: {}", path, e),
+        }
+    }
+    if let Some(path) = &opts.learn_book {
+        let learned = LearnedBook::load(path).and_then(|mut book| {
+            book.record_game(&game, result);
+            book.save(path)?;
+            Ok(book.known_positions())
+        });
+        match learned {
+            Ok(n) => println!("Book {} now holds {} positions", path, n),
+            Err(e) => println!("Cannot update book {}: {}", path, e),
+        }
+    }
+    if let Some(path) = &opts.selfplay_pgn {
+        let players = (PROGRAM_NAME, PROGRAM_NAME);
+        match pgn::append_game(path, &game, SELFPLAY_EVENT, players, result) {
+            Ok(round) => println!("Added round {} to {}", round, path),
+            Err(e) => println!("Cannot write {}: {}", path, e),
+        }
+    }
+    if let Some(path) = &opts.pgn {
+        save_pgn(
+            path,
+            &game,
+            SELFPLAY_EVENT,
+            (PROGRAM_NAME, PROGRAM_NAME),
+            result,
+        );
+    }
+}
+
+/// Replays the first game of the PGN file at `path`, taken back to
+/// `move_number` if one is given.
+pub fn load_pgn(path: &str, move_number: Option<(u32, Color)>) -> Result<GameState, String> {
+    let mut game = pgn::load_game(path)?;
+    if let Some((number, side)) = move_number {
+        if !game.rewind_to(number, side) {
+            return Err(format!("the game never reaches move {}", number));
+        }
+    }
+    Ok(game)
+}
+
+/// Has Ctrl-C during the engine's next search write `game` so far, as
+/// unfinished, to `path` with `save_pgn` and append it to `append_path`.
+fn save_on_interrupt(
+    game: &GameState,
+    path: Option<&String>,
+    append_path: Option<&String>,
+    event: &'static str,
+    players: (&'static str, &'static str),
+) {
+    if path.is_none() && append_path.is_none() {
+        return;
+    }
+    let (game, path, append_path) = (game.clone(), path.cloned(), append_path.cloned());
+    interrupt::set_exit_hook(Some(Box::new(move || {
+        if let Some(path) = &append_path {
+            if let Err(e) = pgn::append_game(path, &game, event, players, "*") {
+                println!("Cannot write {}: {}", path, e);
+            }
+        }
+        if let Some(path) = &path {
+            save_pgn(path, &game, event, players, "*");
+        }
+    })));
+}
+
+/// Writes the game to `path` for `--pgn`, reporting the outcome.
+fn save_pgn(path: &str, game: &GameState, event: &str, players: (&str, &str), result: &str) {
+    match pgn::save_game(path, game, event, players, result) {
+        Ok(()) => println!("Wrote the game to {}", path),
+        Err(e) => println!("Cannot write {}: {}", path, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{Piece, Square};
+    use std::env;
+    use std::fs;
+
+    fn options(depth: i8) -> PlayOptions {
+        PlayOptions {
+            ponder: false,
+            pv_san: false,
+            flip: false,
+            move_time_per_piece: 0,
+            json: false,
+            suggest_draw: false,
+            human_score: false,
+            eval_bar: false,
+            show_clocks: false,
+            depth,
+            eval_tune_dump: None,
+            selfplay_pgn: None,
+            learn_book: None,
+            learned_book: None,
+            book: None,
+            book_moves: 0,
+            book_selection: polyglot::Selection::Best,
+            pgn: None,
+            tc: None,
+            config: SearchConfig::default(),
+        }
+    }
+
+    /// A new game at the starting position with the engine playing Black.
+    fn new_session(depth: i8) -> Session {
+        Session {
+            game: GameState::new(Board::default(), game_state::Clocks::default()),
+            engine: Color::Black,
+            depth,
+            clock: None,
+        }
+    }
+
+    #[test]
+    fn level_rook_endgame_suggests_draw() {
+        let board = Board::from_str("8/3k4/8/2r5/8/5R2/3K4/8 w - - 0 1").unwrap();
+        let result = search(&board, 4, &SearchConfig::default());
+        let mut offer = DrawOffer::new();
+        let offers: Vec<bool> = (0..DRAW_OFFER_SEARCHES)
+            .map(|_| offer.update(&board, result.score))
+            .collect();
+        assert_eq!(offers.iter().filter(|&&o| o).count(), 1, "{}", result.score);
+        assert!(offers[offers.len() - 1]);
+        assert!(!DrawOffer::new().update(&Board::default(), 0));
+    }
+
+    #[test]
+    fn replayed_session_ends_the_same() {
+        let dir = env::temp_dir();
+        let commands = dir.join(format!("replay-commands-{}", std::process::id()));
+        let log = dir.join(format!("replay-log-{}", std::process::id()));
+        fs::write(&commands, "e2e4\nd2d4\ng1f3\n").unwrap();
+        let mut recorded = CommandInput::from_file(commands.to_str().unwrap())
+            .and_then(|input| input.log_to(log.to_str().unwrap()))
+            .unwrap();
+        let first = interactive_loop(new_session(2), &options(2), &mut recorded);
+        drop(recorded);
+        let mut replayed = CommandInput::from_file(log.to_str().unwrap()).unwrap();
+        let second = interactive_loop(new_session(2), &options(2), &mut replayed);
+        fs::remove_file(commands).unwrap();
+        fs::remove_file(log).unwrap();
+        assert_eq!(first.game.moves().len(), 6);
+        assert_eq!(first.game.board(), second.game.board());
+    }
+
+    #[test]
+    fn move_file_drives_the_game() {
+        // Black's king is boxed in, so the h-pawn push is its only move.
+        let start = Board::from_str("k7/2K5/7p/8/8/4B3/8/8 w - - 0 1").unwrap();
+        let moves = env::temp_dir().join(format!("moves-file-{}", std::process::id()));
+        fs::write(&moves, "e3f2\nf2g1\ng1e3\n").unwrap();
+        let mut input = CommandInput::from_file(moves.to_str().unwrap()).unwrap();
+        let session = Session {
+            game: GameState::new(start, game_state::Clocks::default()),
+            ..new_session(2)
+        };
+        let session = interactive_loop(session, &options(2), &mut input);
+        fs::remove_file(moves).unwrap();
+        let expected = Board::from_str("k7/2K5/8/8/8/4B2p/8/8 w - - 0 4").unwrap();
+        assert_eq!(session.game.board(), expected);
+    }
+
+    #[test]
+    fn analyze_ranks_winning_the_queen_first() {
+        let board = Board::from_str("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let game = GameState::new(board, game_state::Clocks::default());
+        let lines = analyze_lines(&game, 3, &SearchConfig::default());
+        let top: Vec<String> = lines
+            .iter()
+            .take(ANALYZE_LINES)
+            .map(|(mv, _)| mv.to_string())
+            .collect();
+        assert_eq!(top[0], "d2d5", "{:?}", top);
+    }
+
+    #[test]
+    fn takeback_two_returns_to_the_first_move() {
+        let mut game = GameState::new(Board::default(), game_state::Clocks::default());
+        for mv in ["e2e4", "e7e5", "g1f3"] {
+            game.make_move(ChessMove::from_str(mv).unwrap());
+        }
+        assert!(exec_takeback(&mut game, "takeback 5"));
+        assert_eq!(game.plies_played(), 3);
+        assert!(exec_takeback(&mut game, "takeback 2"));
+        let after_e4 = Board::default().make_move_new(ChessMove::from_str("e2e4").unwrap());
+        assert_eq!(game.board(), after_e4);
+        assert_eq!(game.moves().len(), 1);
+    }
+
+    #[test]
+    fn depth_command_applies_to_the_next_engine_move() {
+        let mut input = CommandInput::from_lines(&["depth 2", "e2e4"]);
+        let session = interactive_loop(new_session(6), &options(6), &mut input);
+        assert_eq!(session.depth, 2);
+        assert_eq!(session.game.moves().len(), 2);
+        let mut input = CommandInput::from_lines(&["depth 0", "depth x"]);
+        assert_eq!(
+            interactive_loop(new_session(6), &options(6), &mut input).depth,
+            6
+        );
+    }
+
+    #[test]
+    fn selfplay_games_append_to_one_pgn() {
+        let path = env::temp_dir().join(format!("selfplay-append-{}", std::process::id()));
+        let opts = PlayOptions {
+            selfplay_pgn: Some(path.to_str().unwrap().to_string()),
+            ..options(2)
+        };
+        // Each side has a mate in one, so each game is a single move.
+        for fen in [
+            "k7/8/1K6/8/8/8/8/7R w - - 0 1",
+            "7r/8/8/8/8/1k6/8/K7 b - - 0 1",
+        ] {
+            let board = Board::from_str(fen).unwrap();
+            self_play_loop(&GameState::new(board, game_state::Clocks::default()), &opts);
+        }
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        let games: Vec<GameState> = text
+            .split("[Event ")
+            .skip(1)
+            .map(|game| pgn::read_game(&format!("[Event {}", game)).unwrap())
+            .collect();
+        assert_eq!(games.len(), 2);
+        assert_ne!(games[0].start_fen(), games[1].start_fen());
+        assert_eq!(games[0].moves()[0].to_string(), "h1h8");
+        assert_eq!(games[1].moves()[0].to_string(), "h8h1");
+        assert!(text.contains("[Round \"1\"]") && text.contains("[Round \"2\"]"));
+    }
+
+    #[test]
+    fn user_moves_read_as_coordinates_or_san() {
+        let start = GameState::new(Board::default(), game_state::Clocks::default());
+        let e4 = ChessMove::new(Square::E2, Square::E4, None);
+        assert_eq!(parse_user_move(&start, "e2e4"), Some(e4));
+        assert_eq!(parse_user_move(&start, "e4"), Some(e4));
+        let nf3 = ChessMove::new(Square::G1, Square::F3, None);
+        assert_eq!(parse_user_move(&start, "Nf3"), Some(nf3));
+        assert_eq!(parse_user_move(&start, "g1f3"), Some(nf3));
+        assert_eq!(parse_user_move(&start, "e2e5"), None);
+        // Promotion can't come up from the start, so it gets its own position.
+        let board = Board::from_str("7k/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let promoting = GameState::new(board, game_state::Clocks::default());
+        let queen = ChessMove::new(Square::E7, Square::E8, Some(Piece::Queen));
+        assert_eq!(parse_user_move(&promoting, "e7e8q"), Some(queen));
+        assert_eq!(parse_user_move(&promoting, "e8=Q+"), Some(queen));
+    }
+}
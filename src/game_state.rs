@@ -0,0 +1,219 @@
+use crate::chess960::{self, Castling};
+use crate::ordering;
+use chess::{Board, BoardStatus, ChessMove, Color, Piece};
+
+/// The FEN move counters, which `Board` doesn't keep.
+#[derive(Clone, Copy)]
+pub struct Clocks {
+    /// Plies since the last capture or pawn move, for the 50-move rule.
+    pub halfmove: u32,
+    /// Starts at 1 and goes up after each Black move.
+    pub fullmove: u32,
+}
+
+impl Default for Clocks {
+    /// The counters of a game's first move.
+    fn default() -> Clocks {
+        Clocks {
+            halfmove: 0,
+            fullmove: 1,
+        }
+    }
+}
+
+impl Clocks {
+    /// Reads the last two fields of `fen`, defaulting to a fresh game when
+    /// they are missing or malformed.
+    pub fn from_fen(fen: &str) -> Clocks {
+        let mut fields = fen.split_whitespace().skip(4);
+        Clocks {
+            halfmove: fields.next().and_then(|f| f.parse().ok()).unwrap_or(0),
+            fullmove: fields.next().and_then(|f| f.parse().ok()).unwrap_or(1),
+        }
+    }
+}
+
+/// The halfmove clock after playing `mv` on `board`, when it stood at
+/// `halfmove` before.
+pub fn next_halfmove(board: &Board, mv: ChessMove, halfmove: u32) -> u32 {
+    let resets = ordering::captured_piece(board, mv).is_some()
+        || board.piece_on(mv.get_source()) == Some(Piece::Pawn);
+    if resets {
+        0
+    } else {
+        halfmove + 1
+    }
+}
+
+/// The current position of a game together with every position before it,
+/// since `Board` alone cannot unmake moves.
+#[derive(Clone)]
+pub struct GameState {
+    board: Board,
+    clocks: Clocks,
+    /// Chess960 castling rights, empty in a classical game.
+    castling: Castling,
+    history: Vec<(Board, Clocks, Castling)>,
+    /// The move played from each position in `history`.
+    moves: Vec<ChessMove>,
+}
+
+impl GameState {
+    pub fn new(board: Board, clocks: Clocks) -> GameState {
+        GameState {
+            board,
+            clocks,
+            castling: Castling::default(),
+            history: Vec::new(),
+            moves: Vec::new(),
+        }
+    }
+
+    /// The game with Chess960 castling `castling`, before any moves.
+    pub fn with_castling(self, castling: Castling) -> GameState {
+        GameState { castling, ..self }
+    }
+
+    pub fn board(&self) -> Board {
+        self.board
+    }
+
+    pub fn clocks(&self) -> Clocks {
+        self.clocks
+    }
+
+    pub fn castling(&self) -> Castling {
+        self.castling
+    }
+
+    /// The legal moves of the current position, Chess960 castles included.
+    pub fn legal_moves(&self) -> Vec<ChessMove> {
+        self.castling.legal_moves(&self.board)
+    }
+
+    pub fn is_legal(&self, mv: ChessMove) -> bool {
+        self.castling.is_legal(&self.board, mv)
+    }
+
+    /// The state of the current position. Unlike `Board::status`, a side
+    /// whose only legal move is a Chess960 castle isn't stalemated.
+    pub fn status(&self) -> BoardStatus {
+        match self.board.status() {
+            BoardStatus::Stalemate if !self.castling.moves(&self.board).is_empty() => {
+                BoardStatus::Ongoing
+            }
+            status => status,
+        }
+    }
+
+    /// The current position as FEN.
+    pub fn fen(&self) -> String {
+        chess960::to_fen(&self.board, self.clocks, self.castling)
+    }
+
+    /// The position the game started from as FEN.
+    pub fn start_fen(&self) -> String {
+        let (board, clocks, castling) =
+            self.history
+                .first()
+                .copied()
+                .unwrap_or((self.board, self.clocks, self.castling));
+        chess960::to_fen(&board, clocks, castling)
+    }
+
+    pub fn make_move(&mut self, mv: ChessMove) {
+        self.history.push((self.board, self.clocks, self.castling));
+        self.moves.push(mv);
+        self.clocks.halfmove = next_halfmove(&self.board, mv, self.clocks.halfmove);
+        if self.board.side_to_move() == Color::Black {
+            self.clocks.fullmove += 1;
+        }
+        let before = self.board;
+        self.castling = self.castling.play(&before, mv, &mut self.board);
+    }
+
+    /// Number of plies played since the game started.
+    pub fn plies_played(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Undoes the last `plies` moves. Refuses, leaving the game untouched,
+    /// if that would go back past the starting position.
+    pub fn takeback(&mut self, plies: usize) -> bool {
+        if plies == 0 || plies > self.history.len() {
+            return false;
+        }
+        let keep = self.history.len() - plies;
+        (self.board, self.clocks, self.castling) = self.history[keep];
+        self.history.truncate(keep);
+        self.moves.truncate(keep);
+        true
+    }
+
+    /// Goes back to the position where `side` is to play move number
+    /// `fullmove`. Refuses, leaving the game untouched, if the game never
+    /// got there.
+    pub fn rewind_to(&mut self, fullmove: u32, side: Color) -> bool {
+        let reached = |&(board, clocks, _): &(Board, Clocks, Castling)| {
+            clocks.fullmove == fullmove && board.side_to_move() == side
+        };
+        if reached(&(self.board, self.clocks, self.castling)) {
+            return true;
+        }
+        match self.history.iter().position(reached) {
+            Some(idx) => self.takeback(self.history.len() - idx),
+            None => false,
+        }
+    }
+
+    /// The position and counters the game started from.
+    pub fn start(&self) -> (Board, Clocks) {
+        self.history
+            .first()
+            .map_or((self.board, self.clocks), |&(board, clocks, _)| {
+                (board, clocks)
+            })
+    }
+
+    /// The moves played so far, in order.
+    pub fn moves(&self) -> &[ChessMove] {
+        &self.moves
+    }
+
+    /// Why the game is drawn by rule although moves remain: the current
+    /// position has occurred three times, 100 plies have passed without a
+    /// capture or pawn move, or neither side has the material to mate.
+    pub fn draw_by_rule(&self) -> Option<&'static str> {
+        let hash = self.board.get_hash() ^ self.castling.key();
+        let repeats = self
+            .earlier_hashes()
+            .into_iter()
+            .filter(|&earlier| earlier == hash)
+            .count();
+        if repeats >= 2 {
+            Some("repetition")
+        } else if self.clocks.halfmove >= 100 {
+            Some("fifty-move rule")
+        } else if crate::endgame::is_insufficient_material(&self.board) {
+            Some("insufficient material")
+        } else {
+            None
+        }
+    }
+
+    /// Hashes of the positions before the current one, oldest first, with
+    /// their Chess960 rights mixed in as the search does.
+    pub fn earlier_hashes(&self) -> Vec<u64> {
+        self.history
+            .iter()
+            .map(|(board, _, castling)| board.get_hash() ^ castling.key())
+            .collect()
+    }
+
+    /// Every position of the game so far, oldest first.
+    pub fn positions(&self) -> Vec<Board> {
+        let mut positions: Vec<Board> = self.history.iter().map(|&(board, ..)| board).collect();
+        positions.push(self.board);
+        positions
+    }
+}
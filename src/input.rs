@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+/// Source of user commands for the interactive loop. Reads from stdin or
+/// from a replay file, and optionally records every line it hands out so
+/// the session can be replayed later with `--replay`.
+pub struct CommandInput {
+    lines: Box<dyn Iterator<Item = String>>,
+    log: Option<File>,
+}
+
+impl CommandInput {
+    pub fn stdin() -> CommandInput {
+        let lines = io::stdin().lock().lines().map_while(Result::ok);
+        CommandInput {
+            lines: Box::new(lines),
+            log: None,
+        }
+    }
+
+    pub fn from_file(path: &str) -> io::Result<CommandInput> {
+        let file = File::open(path)?;
+        let lines = BufReader::new(file).lines().map_while(Result::ok);
+        Ok(CommandInput {
+            lines: Box::new(lines),
+            log: None,
+        })
+    }
+
+    /// Commands typed ahead of time, one per line.
+    #[cfg(test)]
+    pub(crate) fn from_lines(lines: &[&str]) -> CommandInput {
+        let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        CommandInput {
+            lines: Box::new(lines.into_iter()),
+            log: None,
+        }
+    }
+
+    /// Carries on reading from stdin once the current source runs out.
+    pub fn then_stdin(mut self) -> CommandInput {
+        let rest = io::stdin().lock().lines().map_while(Result::ok);
+        self.lines = Box::new(self.lines.chain(rest));
+        self
+    }
+
+    /// Appends every command read from now on to `path`.
+    pub fn log_to(mut self, path: &str) -> io::Result<CommandInput> {
+        self.log = Some(File::create(path)?);
+        Ok(self)
+    }
+
+    /// Returns the next command, or `None` once the input is exhausted.
+    pub fn next_line(&mut self) -> Option<String> {
+        let line = self.lines.next()?;
+        if let Some(log) = self.log.as_mut() {
+            if writeln!(log, "{}", line).is_err() {
+                eprintln!("Failed to write command log");
+                self.log = None;
+            }
+        }
+        Some(line)
+    }
+}
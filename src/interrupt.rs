@@ -0,0 +1,71 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Work an interrupt would otherwise lose, such as the PGN of a game in
+/// progress.
+pub type ExitHook = Box<dyn FnOnce() + Send>;
+
+/// Set by Ctrl-C while a search is running.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+/// Whether a search is running, so Ctrl-C knows to stop it rather than the
+/// whole program.
+static SEARCHING: AtomicBool = AtomicBool::new(false);
+/// Run by `exit` before the program ends.
+static EXIT_HOOK: Mutex<Option<ExitHook>> = Mutex::new(None);
+
+/// Exit status after an interrupt, as shells report a death by SIGINT.
+pub const EXIT_STATUS: i32 = 130;
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_: libc::c_int) {
+    if SEARCHING.load(Ordering::SeqCst) {
+        REQUESTED.store(true, Ordering::SeqCst);
+    } else {
+        // Only async-signal-safe calls belong here.
+        unsafe { libc::_exit(EXIT_STATUS) };
+    }
+}
+
+/// Makes Ctrl-C stop a running search, which then reports its best move so
+/// far, instead of killing the program mid-search. Outside a search Ctrl-C
+/// still exits at once.
+#[cfg(unix)]
+pub fn install() {
+    let handler = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe { libc::signal(libc::SIGINT, handler) };
+}
+
+/// Without Unix signals Ctrl-C keeps its default behaviour.
+#[cfg(not(unix))]
+pub fn install() {}
+
+/// Marks the start or end of a search.
+pub fn set_searching(searching: bool) {
+    SEARCHING.store(searching, Ordering::SeqCst);
+}
+
+/// Whether Ctrl-C has asked the running search to stop.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sets what `exit` runs first, replacing any earlier hook; `None` clears
+/// it.
+pub fn set_exit_hook(hook: Option<ExitHook>) {
+    *EXIT_HOOK.lock().unwrap_or_else(PoisonError::into_inner) = hook;
+}
+
+/// Ends the program after Ctrl-C stopped a search: runs the exit hook,
+/// flushes what was printed, and exits with `EXIT_STATUS`.
+pub fn exit() -> ! {
+    let hook = EXIT_HOOK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+    if let Some(hook) = hook {
+        hook();
+    }
+    let _ = std::io::stdout().flush();
+    std::process::exit(EXIT_STATUS)
+}
@@ -0,0 +1,169 @@
+use crate::attacks;
+use crate::piece_values::{piece_value, PAWN};
+use chess::{
+    get_adjacent_files, get_file, get_king_moves, get_rank, BitBoard, Board, Color, Piece, Rank,
+    EMPTY,
+};
+
+/// Penalty for each missing pawn directly in front of the king.
+const SHIELD_MISSING: i64 = PAWN * 15 / 100;
+/// Penalty for a shield pawn that has advanced one extra square.
+const SHIELD_ADVANCED: i64 = PAWN * 7 / 100;
+/// Penalty for a file next to the king with no pawn of its own side.
+const HALF_OPEN_FILE: i64 = PAWN / 10;
+/// Penalty for a file next to the king with no pawns at all.
+const OPEN_FILE: i64 = PAWN / 4;
+
+/// Weight of an enemy queen when scaling the king safety penalty.
+const QUEEN_WEIGHT: i64 = 8;
+/// Weight of each enemy rook when scaling the king safety penalty.
+const ROOK_WEIGHT: i64 = 1;
+const FULL_WEIGHT: i64 = QUEEN_WEIGHT + 2 * ROOK_WEIGHT;
+
+/// Attacking material, in centipawns, per centipawn of king attack penalty
+/// for each attacker the defence is short of.
+const KING_ATTACK_SCALE: i64 = 64;
+
+/// How much the enemy's attacking material makes king safety matter, out of
+/// `FULL_WEIGHT`. Without the queen this is close to zero.
+fn attack_weight(board: &Board, colour: Color) -> i64 {
+    let enemy = board.color_combined(!colour);
+    let queens = (board.pieces(Piece::Queen) & enemy).popcnt() as i64;
+    let rooks = (board.pieces(Piece::Rook) & enemy).popcnt() as i64;
+    std::cmp::min(queens * QUEEN_WEIGHT + rooks * ROOK_WEIGHT, FULL_WEIGHT)
+}
+
+fn rank_ahead(rank: Rank, colour: Color, steps: usize) -> Option<BitBoard> {
+    let idx = match colour {
+        Color::White => rank.to_index() + steps,
+        Color::Black => rank.to_index().checked_sub(steps)?,
+    };
+    if idx < 8 {
+        Some(get_rank(Rank::from_index(idx)))
+    } else {
+        None
+    }
+}
+
+/// Unscaled penalty for the pawns missing from in front of the king.
+pub fn shield_penalty(board: &Board, colour: Color) -> i64 {
+    let king = board.king_square(colour);
+    let files = get_file(king.get_file()) | get_adjacent_files(king.get_file());
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(colour);
+
+    let near = rank_ahead(king.get_rank(), colour, 1).unwrap_or(EMPTY) & files;
+    let far = rank_ahead(king.get_rank(), colour, 2).unwrap_or(EMPTY) & files;
+
+    let mut penalty = 0;
+    for file_mask in files_of(files) {
+        if pawns & near & file_mask != EMPTY {
+            continue;
+        }
+        if pawns & far & file_mask != EMPTY {
+            penalty += SHIELD_ADVANCED;
+        } else {
+            penalty += SHIELD_MISSING;
+        }
+    }
+    penalty
+}
+
+/// Unscaled penalty for the files on and beside the king that have lost
+/// their pawns, which rooks and queens can use to reach it.
+pub fn open_file_penalty(board: &Board, colour: Color) -> i64 {
+    let king = board.king_square(colour);
+    let files = get_file(king.get_file()) | get_adjacent_files(king.get_file());
+    let pawns = *board.pieces(Piece::Pawn);
+    let own = pawns & board.color_combined(colour);
+    files_of(files)
+        .map(|file_mask| {
+            if pawns & file_mask == EMPTY {
+                OPEN_FILE
+            } else if own & file_mask == EMPTY {
+                HALF_OPEN_FILE
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+fn files_of(mask: BitBoard) -> impl Iterator<Item = BitBoard> {
+    chess::ALL_FILES
+        .iter()
+        .map(|&file| get_file(file))
+        .filter(move |&file_mask| file_mask & mask != EMPTY)
+}
+
+/// Penalty for enemy pieces bearing on `colour`'s king: every piece other
+/// than a pawn or king that attacks the king or a square next to it counts,
+/// except pinned defenders, and if the attackers outnumber such defenders the surplus is charged in
+/// proportion to the attacking material.
+pub fn king_attack(board: &Board, colour: Color) -> i64 {
+    let king = board.king_square(colour);
+    let zone = get_king_moves(king) | BitBoard::from_square(king);
+    // A pinned piece can't leave the pin line to help its king.
+    let pinned = attacks::pinned(board, colour);
+    let pieces_reaching = |side: Color, excluded: BitBoard| {
+        (*board.color_combined(side)
+            & !board.pieces(Piece::Pawn)
+            & !board.pieces(Piece::King)
+            & !excluded)
+            .filter_map(|sq| board.piece_on(sq).map(|piece| (piece, sq)))
+            .filter(|&(piece, sq)| attacks::piece_attacks(board, piece, side, sq) & zone != EMPTY)
+            .map(|(piece, _)| piece_value(piece))
+            .collect::<Vec<i64>>()
+    };
+    let attackers = pieces_reaching(!colour, EMPTY);
+    let defenders = pieces_reaching(colour, pinned);
+    let surplus = attackers.len() as i64 - defenders.len() as i64;
+    if surplus <= 0 {
+        return 0;
+    }
+    surplus * attackers.iter().sum::<i64>() / KING_ATTACK_SCALE
+}
+
+/// King safety penalty for `colour`: the pawn shield and open files,
+/// scaled down as the enemy's queen and rooks leave the board, plus the
+/// pieces attacking it.
+pub fn king_safety(board: &Board, colour: Color) -> i64 {
+    (shield_penalty(board, colour) + open_file_penalty(board, colour))
+        * attack_weight(board, colour)
+        / FULL_WEIGHT
+        + king_attack(board, colour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::calc_board_value;
+    use crate::eval_params::EvalParams;
+    use std::str::FromStr;
+
+    #[test]
+    fn trading_queens_shrinks_the_penalty() {
+        let with = Board::from_str("r2qk2r/pppppppp/8/8/8/8/PPPPP3/RNBQ1RK1 w kq - 0 1").unwrap();
+        let without = Board::from_str("r3k2r/pppppppp/8/8/8/8/PPPPP3/RNB2RK1 w kq - 0 1").unwrap();
+        let (queens, queenless) = (
+            king_safety(&with, Color::White),
+            king_safety(&without, Color::White),
+        );
+        assert!(queens > 0);
+        assert!(queenless * 4 <= queens, "{} vs {}", queenless, queens);
+    }
+
+    #[test]
+    fn massing_pieces_at_the_king_helps_the_attacker() {
+        // The same white pieces, at home and then aimed at the castled king.
+        let home = Board::from_str("r4rk1/ppp2ppp/8/8/8/8/PPP2PPP/RNBQ1RK1 w - - 0 1").unwrap();
+        let massed = Board::from_str("r4rk1/ppp2ppp/8/6NQ/8/3B4/PPP2PPP/R4RK1 w - - 0 1").unwrap();
+        assert_eq!(king_attack(&home, Color::Black), 0);
+        assert!(king_attack(&massed, Color::Black) > 0);
+        let params = EvalParams::default();
+        let (before, after) = (
+            calc_board_value(&home, &params),
+            calc_board_value(&massed, &params),
+        );
+        assert!(after > before, "{} vs {}", after, before);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::game_state::{Clocks, GameState};
+use chess::{Board, BoardBuilder, Piece, ALL_COLORS, ALL_SQUARES};
+use std::str::FromStr;
+
+pub mod annotate;
+pub mod attacks;
+pub mod benchmarks;
+mod book;
+pub mod chess960;
+pub mod display;
+mod elo;
+pub mod endgame;
+mod engine;
+pub mod epd;
+pub mod eval;
+pub mod eval_params;
+mod fen;
+pub mod game_loop;
+pub mod game_state;
+pub mod input;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod interrupt;
+/// Without signals there is no Ctrl-C, and no process to end, on the web.
+#[cfg(target_arch = "wasm32")]
+pub mod interrupt {
+    pub type ExitHook = Box<dyn FnOnce() + Send>;
+
+    pub fn install() {}
+
+    pub fn set_searching(_: bool) {}
+
+    pub fn requested() -> bool {
+        false
+    }
+
+    pub fn set_exit_hook(_: Option<ExitHook>) {}
+
+    /// `requested` never holds, so nothing calls this; should it, the
+    /// module stops as a panic would.
+    pub fn exit() -> ! {
+        std::process::abort()
+    }
+}
+mod json;
+mod king_safety;
+#[cfg(feature = "online")]
+pub mod lichess;
+pub mod match_runner;
+mod mobility;
+pub mod modes;
+pub mod ordering;
+mod pawns;
+mod perft;
+pub mod pgn;
+mod piece_scores;
+pub mod piece_values;
+pub mod polyglot;
+mod polyglot_random;
+mod rng;
+pub mod search;
+mod see;
+pub mod session;
+mod threats;
+pub mod time_manager;
+mod trace;
+pub mod tt;
+mod tuning;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod uci;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod wasm;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod xboard;
+
+pub use engine::{Engine, SearchLimits};
+
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+pub const DEFAULT_DEPTH: i8 = 4;
+/// Deepest search the interactive `depth N` command accepts.
+pub const MAX_DEPTH: i8 = 20;
+
+pub const PROGRAM_NAME: &str = "Scacchi";
+
+/// A game starting from `fen`, read as `load_board` does. With `chess960`,
+/// the castling field may use X-FEN or Shredder-FEN file letters, and the
+/// game castles the Chess960 way, with the king taking its own rook.
+pub fn load_game(fen: &str, chess960: bool) -> Result<GameState, String> {
+    let clocks = Clocks::from_fen(fen);
+    if !chess960 {
+        return Ok(GameState::new(load_board(fen)?, clocks));
+    }
+    let (fen, castling) = chess960::parse_fen(fen)?;
+    Ok(GameState::new(load_board(&fen)?, clocks).with_castling(castling))
+}
+
+/// Parses a FEN, making sure each side has exactly one king before the
+/// position reaches the search, which relies on that invariant.
+pub fn load_board(fen: &str) -> Result<Board, String> {
+    let builder = BoardBuilder::from_str(fen).map_err(|e| e.to_string())?;
+    for colour in ALL_COLORS {
+        let kings = ALL_SQUARES
+            .iter()
+            .filter(|&&sq| builder[sq] == Some((Piece::King, colour)))
+            .count();
+        if kings != 1 {
+            return Err(format!("expected one {:?} king, found {}", colour, kings));
+        }
+    }
+    Board::try_from(builder).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_without_black_king_is_rejected() {
+        let err = load_board("8/8/8/8/8/8/8/4K3 w - - 0 1").unwrap_err();
+        assert_eq!(err, "expected one Black king, found 0");
+    }
+
+    #[test]
+    fn board_with_two_white_kings_is_rejected() {
+        let err = load_board("4k3/8/8/8/8/8/8/K3K3 w - - 0 1").unwrap_err();
+        assert_eq!(err, "expected one White king, found 2");
+    }
+}
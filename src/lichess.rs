@@ -0,0 +1,264 @@
+use crate::game_state::GameState;
+use crate::json::{self, Value};
+use crate::search::{search, SearchConfig};
+use crate::{load_game, time_manager, MAX_DEPTH, STARTING_FEN};
+use chess::{ChessMove, Color};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// How the bot connects and which challenges it takes.
+#[derive(Clone)]
+pub struct Settings {
+    /// A Lichess API token with the `bot:play` scope.
+    pub token: String,
+    pub url: String,
+    /// Range of starting clock times accepted, in seconds.
+    pub min_base: u64,
+    pub max_base: u64,
+    /// Decline rated challenges.
+    pub casual_only: bool,
+}
+
+/// An HTTP request run by `curl`, which keeps TLS out of the build. The
+/// token goes to curl on stdin, so it doesn't show in the process list.
+fn curl(settings: &Settings, method: &str, path: &str, body: Option<&str>) -> io::Result<Child> {
+    let mut command = Command::new("curl");
+    command
+        .args(["--silent", "--show-error", "--no-buffer", "--config", "-"])
+        .args(["--request", method])
+        .arg(format!("{}{}", settings.url, path));
+    if let Some(body) = body {
+        command.args(["--data", body]);
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(
+            stdin,
+            "header = \"Authorization: Bearer {}\"",
+            settings.token
+        )?;
+    }
+    Ok(child)
+}
+
+/// A newline-delimited JSON stream, such as the events of the account or
+/// of one game. The blank lines Lichess sends to keep it open are skipped.
+struct Stream {
+    child: Child,
+    lines: io::Lines<BufReader<ChildStdout>>,
+}
+
+impl Stream {
+    fn open(settings: &Settings, path: &str) -> io::Result<Stream> {
+        let mut child = curl(settings, "GET", path, None)?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("no pipe from curl"))?;
+        Ok(Stream {
+            child,
+            lines: BufReader::new(stdout).lines(),
+        })
+    }
+}
+
+impl Iterator for Stream {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        loop {
+            let line = self.lines.next()?.ok()?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match json::parse(&line) {
+                Ok(value) => return Some(value),
+                Err(e) => eprintln!("Bad event from Lichess: {}: {}", e, line),
+            }
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Sends a request and returns the reply, which for Lichess is JSON.
+fn request(settings: &Settings, method: &str, path: &str, body: Option<&str>) -> io::Result<Value> {
+    let child = curl(settings, method, path, body)?;
+    let output = child.wait_with_output()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    json::parse(text.trim()).map_err(io::Error::other)
+}
+
+/// Why a challenge is declined, as a reason Lichess knows, or `None` to
+/// accept it. Only standard chess with a clock is played.
+fn decline_reason(settings: &Settings, challenge: &Value) -> Option<&'static str> {
+    if challenge.get("variant").get("key").as_str() != Some("standard") {
+        return Some("standard");
+    }
+    let tc = challenge.get("timeControl");
+    if tc.get("type").as_str() != Some("clock") {
+        return Some("timeControl");
+    }
+    let base = tc.get("limit").as_u64().unwrap_or(0);
+    if base < settings.min_base {
+        return Some("tooFast");
+    }
+    if base > settings.max_base {
+        return Some("tooSlow");
+    }
+    if settings.casual_only && challenge.get("rated").as_bool() == Some(true) {
+        return Some("casual");
+    }
+    None
+}
+
+/// The game so far from a `gameState` event: the starting position with
+/// the moves played since in coordinate notation.
+fn replay(initial_fen: &str, moves: &str) -> Result<GameState, String> {
+    let fen = match initial_fen {
+        "startpos" | "" => STARTING_FEN,
+        fen => fen,
+    };
+    let mut game = load_game(fen, false)?;
+    for token in moves.split_whitespace() {
+        let mv = ChessMove::from_str(token).map_err(|e| e.to_string())?;
+        if !game.is_legal(mv) {
+            return Err(format!("illegal move {}", token));
+        }
+        game.make_move(mv);
+    }
+    Ok(game)
+}
+
+/// Plays the game `id` as `us` until it ends, moving whenever the game
+/// stream shows it's the bot's turn.
+fn play_game(settings: &Settings, id: &str, us: &str, config: &SearchConfig) -> io::Result<()> {
+    let mut stream = Stream::open(settings, &format!("/api/bot/game/stream/{}", id))?;
+    let Some(full) = stream.next() else {
+        return Ok(());
+    };
+    let colour = if full.get("white").get("id").as_str() == Some(us) {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let initial_fen = full
+        .get("initialFen")
+        .as_str()
+        .unwrap_or("startpos")
+        .to_string();
+    println!("Game {} started as {:?}", id, colour);
+    let mut state = full.get("state").clone();
+    loop {
+        if state.get("status").as_str() != Some("started") {
+            println!(
+                "Game {} over: {}",
+                id,
+                state.get("status").as_str().unwrap_or("?")
+            );
+            return Ok(());
+        }
+        let moves = state.get("moves").as_str().unwrap_or("");
+        let game = replay(&initial_fen, moves).map_err(io::Error::other)?;
+        if game.board().side_to_move() == colour {
+            let (time, increment) = match colour {
+                Color::White => ("wtime", "winc"),
+                Color::Black => ("btime", "binc"),
+            };
+            let left = state.get(time).as_u64().unwrap_or(0);
+            let increment = state.get(increment).as_u64().unwrap_or(0);
+            let config = SearchConfig {
+                movetime: Some(time_manager::clock_budget(left, increment, None)),
+                ..crate::search::game_search_config(&game, config)
+            };
+            if let Some(mv) = search(&game.board(), MAX_DEPTH, &config).best_move {
+                let path = format!("/api/bot/game/{}/move/{}", id, mv);
+                if let Err(e) = request(settings, "POST", &path, None) {
+                    eprintln!("Cannot play {} in game {}: {}", mv, id, e);
+                }
+            }
+        }
+        // Chat lines and the like carry no position; wait for one that does.
+        state = loop {
+            let Some(event) = stream.next() else {
+                return Ok(());
+            };
+            if event.get("type").as_str() == Some("gameState") {
+                break event;
+            }
+        };
+    }
+}
+
+/// Runs the bot: follows the account's event stream, accepts the
+/// challenges `settings` allows while no game is on, and plays each game
+/// with `config` on a thread of its own, declining challenges meanwhile.
+pub fn run(settings: &Settings, config: &SearchConfig) -> io::Result<()> {
+    let account = request(settings, "GET", "/api/account", None)?;
+    let Some(us) = account.get("id").as_str().map(str::to_string) else {
+        return Err(io::Error::other("no account for this token"));
+    };
+    println!("Connected to {} as {}", settings.url, us);
+    let events = Stream::open(settings, "/api/stream/event")?;
+    let playing = Arc::new(AtomicBool::new(false));
+    let mut games = Vec::new();
+    for event in events {
+        match event.get("type").as_str() {
+            Some("challenge") => {
+                let challenge = event.get("challenge");
+                let Some(id) = challenge.get("id").as_str() else {
+                    continue;
+                };
+                if challenge.get("challenger").get("id").as_str() == Some(us.as_str()) {
+                    continue;
+                }
+                let reason = if playing.load(Ordering::Relaxed) {
+                    Some("later")
+                } else {
+                    decline_reason(settings, challenge)
+                };
+                let (action, body) = match reason {
+                    Some(reason) => ("decline", Some(format!("reason={}", reason))),
+                    None => ("accept", None),
+                };
+                println!("Challenge {}: {}", id, reason.unwrap_or("accepted"));
+                let path = format!("/api/challenge/{}/{}", id, action);
+                if let Err(e) = request(settings, "POST", &path, body.as_deref()) {
+                    eprintln!("Cannot {} challenge {}: {}", action, id, e);
+                }
+            }
+            Some("gameStart") => {
+                let Some(id) = event.get("game").get("gameId").as_str() else {
+                    continue;
+                };
+                playing.store(true, Ordering::Relaxed);
+                let (settings, config) = (settings.clone(), config.clone());
+                let (id, us, playing) = (id.to_string(), us.clone(), Arc::clone(&playing));
+                games.push(thread::spawn(move || {
+                    if let Err(e) = play_game(&settings, &id, &us, &config) {
+                        eprintln!("Game {} stopped: {}", id, e);
+                    }
+                    playing.store(false, Ordering::Relaxed);
+                }));
+            }
+            _ => {}
+        }
+    }
+    // Games still going when the stream closes are played out.
+    for game in games {
+        let _ = game.join();
+    }
+    Ok(())
+}
@@ -1,201 +1,677 @@
 use args::{Args, ArgsError};
-use chess::{get_rank, Board, BoardStatus, ChessMove, Color, MoveGen, Piece, ALL_RANKS};
+use chess::{Board, Color};
+use chess_engine::display::{
+    compare_positions, format_line, human_score, show_board_with_overlay, show_material,
+    show_multipv, show_threats,
+};
+use chess_engine::endgame::EndgameSource;
+use chess_engine::eval::game_phase;
+use chess_engine::eval_params::EvalParams;
+use chess_engine::game_loop::{interactive_loop, load_pgn, run_trace, self_play_loop, PlayOptions};
+use chess_engine::game_state::{Clocks, GameState};
+use chess_engine::input::CommandInput;
+#[cfg(feature = "online")]
+use chess_engine::lichess;
+use chess_engine::modes::{
+    compare_bench, load_bench, run_benchmark, run_board_from_stdin, run_compare_move_order,
+    run_cutoff_stats, run_dump_eval_tables, run_elo, run_fen_roundtrip_test, run_mate_search,
+    run_perft, run_perft_suite, run_reproducibility_check, run_testsuite, run_tune,
+    run_validate_benchmarks, save_bench, BenchOutput,
+};
+use chess_engine::ordering::MoveOrdering;
+use chess_engine::search::{
+    is_mate_score, multipv_search, score_to_cp, score_to_white, search, search_json,
+    weaken_to_skill, CheckExtension, SearchConfig, SearchResult, DEFAULT_ASPIRATION_RESEARCHES,
+    DEFAULT_ASPIRATION_WINDOW, DEFAULT_MAX_EXTENSION, MATE_SCORE, MAX_MATE_PLY, MAX_MULTIPV,
+    MAX_SKILL, MAX_THREADS,
+};
+use chess_engine::session::Session;
+use chess_engine::time_manager::{GameClock, Instant};
+use chess_engine::{annotate, attacks, benchmarks, chess960, epd, eval_params, interrupt};
+use chess_engine::{load_board, load_game, DEFAULT_DEPTH, MAX_DEPTH, PROGRAM_NAME, STARTING_FEN};
+use chess_engine::{match_runner, pgn, piece_values, polyglot, time_manager, tt};
+#[cfg(not(target_arch = "wasm32"))]
+use chess_engine::{uci, xboard};
 use getopts::Occur;
 use std::env;
-use std::io::BufRead;
 use std::str::FromStr;
-use std::time::Instant;
-
-mod benchmarks;
-mod piece_values;
-
-const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-const DEFAULT_DEPTH: i64 = 4;
 
 const PROGRAM_DESC: &str = "A Chess Engine built in Rust";
-const PROGRAM_NAME: &str = "Scacchi";
+fn parse_colour(s: &str) -> Result<Color, ArgsError> {
+    match s.to_lowercase().as_str() {
+        "white" | "w" => Ok(Color::White),
+        "black" | "b" => Ok(Color::Black),
+        _ => Err(ArgsError::new("parse", &format!("Unknown side: '{}'", s))),
+    }
+}
 
-fn calc_piece_value(pc_idx: usize, sq_idx: usize, colour: Option<Color>) -> i64 {
-    match colour {
-        Some(Color::White) => {
-            let sq_value = piece_values::PIECE_SQUARES[pc_idx][sq_idx];
-            return -(piece_values::PIECE_VALS[pc_idx] + sq_value);
-        }
-        Some(Color::Black) => {
-            let sq_value = piece_values::PIECE_SQUARES[pc_idx][63 - sq_idx];
-            return -(piece_values::PIECE_VALS[pc_idx] + sq_value);
+fn parse_endgame_chain(list: &str) -> Result<Vec<EndgameSource>, ArgsError> {
+    let mut chain = Vec::new();
+    for name in list.split(',') {
+        match EndgameSource::from_name(name) {
+            Some(source) => {
+                if !source.is_available() {
+                    println!("Endgame source '{}' is not available, skipping", name);
+                }
+                chain.push(source)
+            }
+            None => {
+                return Err(ArgsError::new(
+                    "parse",
+                    &format!("Unknown endgame source: '{}'", name),
+                ))
+            }
         }
-        None => 0    
     }
+    Ok(chain)
 }
 
-fn calc_pieces_value(board: &Board) -> i64 {
-    let mut result = 0;
-    for pc_idx in 0..6 {
-        let pc_type = piece_values::PIECES[pc_idx];
-        let bboard = *board.pieces(pc_type);
-        for square in bboard {
-            let sq_idx = square.to_index();
-            result += calc_piece_value(pc_idx, sq_idx, board.color_on(square));
-        }
+fn parse_check_extension(name: &str) -> Result<CheckExtension, ArgsError> {
+    CheckExtension::from_name(name).ok_or_else(|| {
+        ArgsError::new(
+            "parse",
+            &format!(
+                "Unknown check extension: '{}', expected off, half or full",
+                name
+            ),
+        )
+    })
+}
+
+fn parse_futility_margins(list: &str) -> Result<Vec<i64>, ArgsError> {
+    list.split(',')
+        .map(|cp| {
+            cp.trim()
+                .parse::<i64>()
+                .map_err(|_| ArgsError::new("parse", &format!("Bad futility margin: '{}'", cp)))
+        })
+        .collect()
+}
+
+/// Checks an `--eval-clamp` bound, which must leave room between it and
+/// the mate scores.
+fn parse_eval_clamp(limit: Option<i64>) -> Result<Option<i64>, ArgsError> {
+    match limit {
+        Some(cp) if cp <= 0 || is_mate_score(cp) => Err(ArgsError::new(
+            "parse",
+            &format!(
+                "Eval clamp must be from 1 to {}, got {}",
+                MATE_SCORE - MAX_MATE_PLY - 1,
+                cp
+            ),
+        )),
+        _ => Ok(limit),
     }
-    result
 }
 
-fn calc_board_value(board: &Board) -> i64 {
-    let w_move = board.side_to_move() == Color::White;
-    let result = match board.status() {
-        BoardStatus::Ongoing => calc_pieces_value(board),
-        BoardStatus::Stalemate => 0,
-        BoardStatus::Checkmate => {
-            if w_move {
-                20000
-            } else {
-                -20000
-            }
-        }
-    };
+/// Checks that `--aspiration-window` is positive.
+fn parse_aspiration_window(window: i64) -> Result<i64, ArgsError> {
+    if window > 0 {
+        Ok(window)
+    } else {
+        Err(ArgsError::new(
+            "parse",
+            &format!("Aspiration window must be positive, got {}", window),
+        ))
+    }
+}
 
-    result
+/// Checks `--multipv` against `MAX_MULTIPV`.
+fn parse_multipv(lines: usize) -> Result<usize, ArgsError> {
+    if (1..=MAX_MULTIPV).contains(&lines) {
+        Ok(lines)
+    } else {
+        Err(ArgsError::new(
+            "parse",
+            &format!("MultiPV must be from 1 to {}, got {}", MAX_MULTIPV, lines),
+        ))
+    }
 }
 
-fn alpha_beta(
-    board: &Board,
-    depth: i8,
-    is_max: bool,
-    alpha: i64,
-    beta: i64,
-    total: &mut i64,
-) -> i64 {
-    if (depth == 0) || (board.status() != BoardStatus::Ongoing) {
-        *total += 1;
-        let val = calc_board_value(board);
-        return val;
-    }
-
-    let mut alpha = alpha;
-    let mut beta = beta;
-
-    if is_max {
-        let mut best_value = i64::MIN;
-        let moves = MoveGen::new_legal(&board);
-        let mut result_board = chess::Board::default();
-        for mv in moves {
-            board.make_move(mv, &mut result_board);
-
-            let value = alpha_beta(&result_board, depth - 1, false, alpha, beta, total);
-            best_value = std::cmp::max(value, best_value);
-
-            alpha = std::cmp::max(alpha, best_value);
-            if beta <= alpha {
-                break;
-            }
-        }
-        return best_value;
+/// Checks `--threads` against `MAX_THREADS`.
+fn parse_threads(threads: usize) -> Result<usize, ArgsError> {
+    if (1..=MAX_THREADS).contains(&threads) {
+        Ok(threads)
     } else {
-        let mut best_value = i64::MAX;
-        let moves = MoveGen::new_legal(&board);
-        let mut result_board = chess::Board::default();
-        for mv in moves {
-            board.make_move(mv, &mut result_board);
-
-            let value = alpha_beta(&result_board, depth - 1, true, alpha, beta, total);
-            best_value = std::cmp::min(value, best_value);
-
-            beta = std::cmp::min(beta, best_value);
-            if beta <= alpha {
-                break;
-            }
-        }
-        return best_value;
+        Err(ArgsError::new(
+            "parse",
+            &format!("Threads must be from 1 to {}, got {}", MAX_THREADS, threads),
+        ))
     }
 }
 
-fn show_board(board: Board) {
-    for (&rank, lbl) in ALL_RANKS.iter().zip("12345678".chars()) {
-        print!("{}", lbl);
-        print!(" ");
-        for sq in get_rank(rank) {
-            let piece = board.piece_on(sq);
-            let sq_char = match board.color_on(sq) {
-                Some(Color::Black) => match piece {
-                    Some(Piece::King) => "♚",
-                    Some(Piece::Queen) => "♛",
-                    Some(Piece::Rook) => "♜",
-                    Some(Piece::Bishop) => "♝",
-                    Some(Piece::Knight) => "♞",
-                    Some(Piece::Pawn) => "♟",
-                    _ => "?",
-                },
-                Some(Color::White) => match piece {
-                    Some(Piece::King) => "♔",
-                    Some(Piece::Queen) => "♕",
-                    Some(Piece::Rook) => "♖",
-                    Some(Piece::Bishop) => "♗",
-                    Some(Piece::Knight) => "♘",
-                    Some(Piece::Pawn) => "♙",
-                    _ => "?",
-                },
-                _ => ".",
-            };
-            print!("{} ", sq_char);
+fn parse_hash(megabytes: usize) -> Result<usize, ArgsError> {
+    if (1..=tt::MAX_HASH_MB).contains(&megabytes) {
+        Ok(megabytes)
+    } else {
+        Err(ArgsError::new(
+            "parse",
+            &format!(
+                "Hash must be from 1 to {} MB, got {}",
+                tt::MAX_HASH_MB,
+                megabytes
+            ),
+        ))
+    }
+}
+
+/// Parses a `--lichess-base` range of seconds, `MIN-MAX`.
+#[cfg(feature = "online")]
+fn parse_base_range(text: &str) -> Result<(u64, u64), ArgsError> {
+    let range = text
+        .split_once('-')
+        .and_then(|(min, max)| Some((min.trim().parse().ok()?, max.trim().parse().ok()?)));
+    match range {
+        Some((min, max)) if min <= max => Ok((min, max)),
+        _ => Err(ArgsError::new(
+            "parse",
+            &format!("Clock range must look like 60-1800, got {}", text),
+        )),
+    }
+}
+
+/// Parses a time control, `BASE` or `BASE+INCREMENT`: the increment is
+/// in seconds and the base in `base_unit_ms` units, seconds for
+/// `--match-tc` and minutes for `--tc`.
+fn parse_time_control(
+    text: Option<String>,
+    base_unit_ms: f64,
+) -> Result<Option<match_runner::TimeControl>, ArgsError> {
+    let Some(text) = text else {
+        return Ok(None);
+    };
+    let (base, increment) = text.split_once('+').unwrap_or((&text, "0"));
+    let millis = |text: &str, unit_ms: f64| {
+        text.parse::<f64>()
+            .ok()
+            .filter(|t| *t >= 0.0)
+            .map(|t| (t * unit_ms) as u64)
+    };
+    match (millis(base, base_unit_ms), millis(increment, 1000.0)) {
+        (Some(base), Some(increment)) if base > 0 => {
+            Ok(Some(match_runner::TimeControl { base, increment }))
         }
-        print!("\n");
+        _ => Err(ArgsError::new(
+            "parse",
+            &format!("Time control must look like 10 or 10+0.5, got {}", text),
+        )),
     }
-    println!("  a b c d e f g h");
 }
 
-fn find_best_move(board: &Board, depth: i8) -> Option<ChessMove> {
-    let black_move = board.side_to_move() == Color::Black;
-    let moves = MoveGen::new_legal(board);
+/// Parses a move number for `--pgn-move`: `12` for White's twelfth move,
+/// `12...` for Black's.
+fn parse_pgn_move(text: Option<String>) -> Result<Option<(u32, Color)>, ArgsError> {
+    let Some(text) = text else {
+        return Ok(None);
+    };
+    let (number, side) = match text.strip_suffix("...") {
+        Some(number) => (number, Color::Black),
+        None => (text.as_str(), Color::White),
+    };
+    match number.parse() {
+        Ok(number) if number > 0 => Ok(Some((number, side))),
+        _ => Err(ArgsError::new(
+            "parse",
+            &format!("Move number must look like 12 or 12..., got {}", text),
+        )),
+    }
+}
 
-    let mut best_value;
-    let mut best_move = None;
+struct Options {
+    is_interactive: bool,
+    is_selfplay: bool,
+    run_benchmark: bool,
+    cutoff_stats: bool,
+    compare_move_order: bool,
+    compare_engines_fen: Option<String>,
+    engine_a: String,
+    engine_b: String,
+    uci: bool,
+    xboard: bool,
+    lichess_bot: bool,
+    #[cfg(feature = "online")]
+    lichess_url: String,
+    /// Starting clock times the bot accepts, in seconds.
+    #[cfg(feature = "online")]
+    lichess_base: (u64, u64),
+    #[cfg(feature = "online")]
+    lichess_casual_only: bool,
+    validate_benchmarks: bool,
+    dump_eval_tables: bool,
+    reproducibility_check: bool,
+    fen_roundtrip_test: bool,
+    perft: Option<u32>,
+    perft_suite: Option<u32>,
+    /// EPD test suite to solve, such as WAC.
+    testsuite: Option<String>,
+    /// Annotate the game in this PGN file, or list of SAN moves.
+    analyze_game: Option<String>,
+    no_banner: bool,
+    /// Colour the human plays in interactive mode; without it the engine
+    /// takes the side to move.
+    play_as: Option<Color>,
+    #[cfg(not(target_arch = "wasm32"))]
+    engine_id: uci::EngineId,
+    board_from_stdin: bool,
+    first_move_only: bool,
+    /// Look only for a forced mate in at most this many moves.
+    mate: Option<u32>,
+    chess960: bool,
+    fen_str: String,
+    show_attacks: Option<Color>,
+    show_threats: bool,
+    show_phase: bool,
+    /// Print the evaluation terms and a traced search of the position.
+    trace: bool,
+    material_table: bool,
+    log_file: Option<String>,
+    replay: Option<String>,
+    moves_file: Option<String>,
+    compare_to_fen: Option<String>,
+    benchmark_epd: Option<String>,
+    bench_output: BenchOutput,
+    /// Save the benchmark rows here, as a later run's baseline.
+    bench_save: Option<String>,
+    /// Compare the benchmark with the rows saved here.
+    bench_baseline: Option<String>,
+    /// Texel-tune the evaluation on the positions in this file.
+    tune: Option<String>,
+    /// Where the tuned constants go; stdout without it.
+    tune_out: Option<String>,
+    tune_iterations: u32,
+    /// List the `book`'s moves for the position instead of playing.
+    opening_explorer: bool,
+    /// Games to play between this configuration and `match_opponent`.
+    match_games: Option<u32>,
+    /// Command line options of the other side in a match.
+    match_opponent: String,
+    /// Command running a UCI engine to play the match against instead.
+    match_engine: Option<String>,
+    match_tc: Option<match_runner::TimeControl>,
+    /// Session file of an interactive game to continue.
+    resume: Option<String>,
+    /// EPD file of the start positions for match games.
+    match_openings: Option<String>,
+    /// PGN file whose first game is replayed to start from.
+    pgn_in: Option<String>,
+    /// Move number, and side to move, to take the loaded game back to.
+    pgn_move: Option<(u32, Color)>,
+    /// Number of self play games to play in a row.
+    games: u32,
+    elo: Option<String>,
+    play: PlayOptions,
+}
 
-    let is_better = {
-        if black_move {
-            best_value = i64::MIN;
-            |x: i64, y: i64| -> bool { x > y }
-        } else {
-            best_value = i64::MAX;
-            |x: i64, y: i64| -> bool { x < y }
+/// Why the command line didn't run, each with the exit status scripts can
+/// tell it apart by: 0 for `--help`, 2 for an unknown option or a value
+/// that doesn't parse, 3 for a bad FEN, 4 for a depth out of range and 5
+/// for modes that can't run together.
+#[derive(Debug)]
+pub enum CliError {
+    /// `--help` was given; holds the usage text.
+    Help(String),
+    Usage(String),
+    BadFen(String),
+    BadDepth(String),
+    ConflictingModes(&'static str, &'static str),
+}
+
+impl CliError {
+    pub fn exit_status(&self) -> i32 {
+        match self {
+            CliError::Help(_) => 0,
+            CliError::Usage(_) => 2,
+            CliError::BadFen(_) => 3,
+            CliError::BadDepth(_) => 4,
+            CliError::ConflictingModes(..) => 5,
         }
-    };
+    }
 
-    let mut total = 0;
-    for mv in moves {
-        let mut new_board = Board::default();
-        board.make_move(mv, &mut new_board);
-        let value = alpha_beta(
-            &new_board,
-            depth,
-            black_move,
-            i64::MIN,
-            i64::MAX,
-            &mut total,
-        );
-        if is_better(value, best_value) {
-            best_value = value;
-            best_move = Some(mv);
+    /// Prints the usage, or the error on stderr, and exits with the status.
+    pub fn exit(&self) -> ! {
+        match self {
+            CliError::Help(usage) => print!("{}", usage),
+            e => eprintln!("{}", e),
         }
+        std::process::exit(self.exit_status())
     }
+}
 
-    best_move
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CliError::Help(usage) => write!(f, "{}", usage),
+            CliError::Usage(e) => write!(f, "{}", e),
+            CliError::BadFen(e) => write!(f, "Bad FEN: {}", e),
+            CliError::BadDepth(depth) => {
+                write!(f, "Depth must be from 1 to {}, got '{}'", MAX_DEPTH, depth)
+            }
+            CliError::ConflictingModes(a, b) => {
+                write!(f, "--{} and --{} can't be used together", a, b)
+            }
+        }
+    }
 }
 
-fn parse(input: &Vec<String>) -> Result<(bool, bool, bool, String, i8), ArgsError> {
+impl From<ArgsError> for CliError {
+    fn from(e: ArgsError) -> CliError {
+        CliError::Usage(e.to_string())
+    }
+}
+
+/// A depth as `--depth` takes it, from 1 to `MAX_DEPTH`.
+fn parse_depth(text: &str) -> Result<i8, CliError> {
+    match text.parse::<i8>() {
+        Ok(depth) if (1..=MAX_DEPTH).contains(&depth) => Ok(depth),
+        _ => Err(CliError::BadDepth(text.to_string())),
+    }
+}
+
+/// Fails on two of the modes that each run the whole session, such as
+/// `--selfplay` with `--interactive`.
+fn check_modes(opts: &Options) -> Result<(), CliError> {
+    let modes = [
+        ("interactive", opts.is_interactive),
+        ("selfplay", opts.is_selfplay),
+        ("uci", opts.uci),
+        ("xboard", opts.xboard),
+        ("lichess-bot", opts.lichess_bot),
+        ("bench", opts.run_benchmark),
+        ("match", opts.match_games.is_some()),
+        ("perft", opts.perft.is_some()),
+        ("perft-suite", opts.perft_suite.is_some()),
+        ("mate", opts.mate.is_some()),
+        ("testsuite", opts.testsuite.is_some()),
+        ("tune", opts.tune.is_some()),
+        ("analyze-game", opts.analyze_game.is_some()),
+    ];
+    let mut chosen = modes.iter().filter(|(_, on)| *on).map(|(name, _)| *name);
+    match (chosen.next(), chosen.next()) {
+        (Some(a), Some(b)) => Err(CliError::ConflictingModes(a, b)),
+        _ => Ok(()),
+    }
+}
+
+fn parse(input: &[String]) -> Result<Options, CliError> {
     let mut args = Args::new(PROGRAM_NAME, PROGRAM_DESC);
     args.flag("h", "help", "Print the usage menu");
     args.flag("i", "interactive", "Run in interactive mode");
     args.flag("s", "selfplay", "Run in self play mode");
     args.flag("b", "bench", "Run benchmark");
+    args.option(
+        "",
+        "bench-output",
+        "Write benchmark rows as text, csv or json - default text",
+        "FORMAT",
+        Occur::Optional,
+        Some("text".to_string()),
+    );
+    args.option(
+        "",
+        "bench-save",
+        "Save the benchmark rows to FILE as CSV, for --bench-baseline",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "bench-baseline",
+        "Compare the benchmark with rows saved by --bench-save; exits 1 if the node counts differ",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.flag(
+        "",
+        "board-from-stdin",
+        "Read FENs from stdin, one per line, and print a best move for each",
+    );
+    args.flag(
+        "",
+        "show-phase",
+        "Print the game phase of the position, from 0 (endgame) to 24 (opening)",
+    );
+    args.flag(
+        "",
+        "trace",
+        "Print the evaluation by term and every root pass of a search, with --json as JSON",
+    );
+    args.flag(
+        "",
+        "material-table-view",
+        "Print the piece counts of both sides and the material balance",
+    );
+    args.flag(
+        "",
+        "display-eval-bar",
+        "Show a text evaluation bar after each engine move",
+    );
+    args.flag(
+        "",
+        "half-move-clock-display",
+        "Show the halfmove clock and fullmove number with the board",
+    );
+    args.flag(
+        "",
+        "show-threats",
+        "List hanging pieces, winning captures and checks in the position",
+    );
+    args.flag(
+        "",
+        "attack-map-eval",
+        "Score control of each square, weighted toward the centre and the enemy half",
+    );
+    args.flag(
+        "",
+        "no-mobility",
+        "Leave the number of squares each piece can reach out of the evaluation",
+    );
+    args.flag(
+        "",
+        "chess960",
+        "Play Chess960 castling: X-FEN or Shredder-FEN rights, castles written king takes rook",
+    );
+    args.option(
+        "",
+        "position960",
+        &format!(
+            "Start from Chess960 position N, 0 to 959 ({} is the classical one)",
+            chess960::CLASSICAL_POSITION
+        ),
+        "N",
+        Occur::Optional,
+        None,
+    );
+    args.flag(
+        "",
+        "tt-any-depth",
+        "Take transposition table scores whatever depth they were searched to",
+    );
+    args.flag(
+        "",
+        "info",
+        "Print UCI info lines with the best line found so far",
+    );
+    args.flag("", "uci", "Speak the UCI protocol on stdin and stdout");
+    args.flag(
+        "",
+        "xboard",
+        "Speak the XBoard (CECP) protocol on stdin and stdout",
+    );
+    args.flag(
+        "",
+        "lichess-bot",
+        "Play on Lichess as a bot, with the API token in LICHESS_BOT_TOKEN; needs the online feature and curl",
+    );
+    args.option(
+        "",
+        "lichess-url",
+        "Lichess server the bot connects to - default https://lichess.org",
+        "URL",
+        Occur::Optional,
+        Some("https://lichess.org".to_string()),
+    );
+    args.option(
+        "",
+        "lichess-base",
+        "Starting clock times, in seconds, of the challenges the bot accepts - default 60-1800",
+        "MIN-MAX",
+        Occur::Optional,
+        Some("60-1800".to_string()),
+    );
+    args.flag(
+        "",
+        "lichess-casual-only",
+        "Make the bot decline rated challenges",
+    );
+    args.flag(
+        "",
+        "uci-analysemode",
+        "Analyse rather than play: full PVs and no draw suggestions",
+    );
+    args.flag(
+        "",
+        "search-seed-from-fen",
+        "Combine --seed with the position, for per-position reproducible choices",
+    );
+    args.flag(
+        "",
+        "no-banner",
+        "Don't print the banner and depth at startup",
+    );
+    args.flag(
+        "",
+        "pv-san",
+        "Print the principal variation in SAN, and after a single search too",
+    );
+    args.flag(
+        "",
+        "positional-only",
+        "Evaluate piece placement only, leaving material out",
+    );
+    args.flag(
+        "",
+        "thinking",
+        "Print depth, score, nodes, speed and line after each search iteration",
+    );
+    args.flag(
+        "",
+        "pv-verify",
+        "Check that every principal variation is legal (always on in debug builds)",
+    );
+    args.flag(
+        "",
+        "dump-eval-tables",
+        "Print the piece values and piece-square tables",
+    );
+    args.flag(
+        "",
+        "validate-benchmarks",
+        "Check that every benchmark position is legal and playable",
+    );
+    args.flag(
+        "",
+        "search-reproducibility-check",
+        "Search each benchmark position twice and check the results match",
+    );
+    args.flag(
+        "",
+        "fen-roundtrip-test",
+        "Check the FEN and the benchmark positions survive parsing and writing back",
+    );
+    args.flag(
+        "",
+        "no-scout",
+        "Search every root move with the full window, to compare against scouting",
+    );
+    args.flag(
+        "",
+        "no-extensions",
+        "Search every move to the nominal depth, checks, promotions and recaptures included",
+    );
+    args.flag(
+        "",
+        "no-null-move",
+        "Turn off null-move pruning, to compare searches with and without it",
+    );
+    args.flag(
+        "",
+        "no-lmr",
+        "Turn off late move reductions, to compare searches with and without them",
+    );
+    args.flag(
+        "",
+        "no-see-pruning",
+        "Search captures in quiescence even when they lose material by static exchange",
+    );
+    args.flag(
+        "",
+        "no-futility",
+        "Turn off futility pruning near the leaves, to check it isn't missing mates",
+    );
+    args.flag(
+        "",
+        "no-delta-pruning",
+        "Search captures in quiescence even when winning the piece can't reach the window",
+    );
+    args.flag(
+        "",
+        "fifty-move-draw",
+        "Score lines reaching the 50-move rule as draws in the search",
+    );
+    args.flag(
+        "",
+        "first-move-only",
+        "Like --board-from-stdin, but with a fast shallow search",
+    );
+    args.flag(
+        "",
+        "compare-move-order",
+        "Report benchmark node counts under each move ordering level",
+    );
+    args.flag(
+        "",
+        "cutoff-stats",
+        "Report the first-move cutoff rate over the benchmark positions",
+    );
+    args.flag(
+        "",
+        "json",
+        "Print one JSON object per line instead of text: searches, self play moves, benchmark \
+         rows, test suite and perft results",
+    );
+    args.flag("", "stats-json", "Same as --json");
+    args.option(
+        "",
+        "mate",
+        "Look for a forced mate in at most N moves and print the mating line",
+        "N",
+        Occur::Optional,
+        None,
+    );
+    args.flag(
+        "",
+        "human-score",
+        "Print scores in pawns with White's estimated winning chances",
+    );
+    args.flag(
+        "",
+        "ponder",
+        "In interactive mode, search the expected reply while you think",
+    );
+    args.flag(
+        "",
+        "suggest-draw",
+        "Suggest a draw when a reduced-material position stays level",
+    );
     args.option(
         "d",
         "depth",
-        "Set the depth of tree search - default 4",
+        "Set the depth of tree search - default 4, or as deep as time allows with --movetime",
         "DEPTH",
-        Occur::Req,
-        Some(DEFAULT_DEPTH.to_string()),
+        Occur::Optional,
+        None,
     );
     args.option(
         "f",
@@ -205,137 +681,1162 @@ fn parse(input: &Vec<String>) -> Result<(bool, bool, bool, String, i8), ArgsErro
         Occur::Optional,
         Some(STARTING_FEN.to_string()),
     );
+    args.option(
+        "",
+        "show-attacks",
+        "Show the board with the empty squares attacked by SIDE marked",
+        "SIDE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "pvs-research-cap",
+        "Search a node with plain alpha-beta after N principal variation re-searches",
+        "N",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "max-pv-reporting-interval",
+        "Print at most one info line per MS milliseconds, besides one per depth",
+        "MS",
+        Occur::Optional,
+        Some("0".to_string()),
+    );
+    args.option(
+        "",
+        "seed",
+        "Seed for random tiebreaks between equal moves and --eval-noise",
+        "N",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "skill",
+        "Playing strength from 0 to 20 - default 20, full strength",
+        "LEVEL",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "draw-score",
+        "Value of a draw for the engine in centipawns, above 0 to seek draws - default 0",
+        "CP",
+        Occur::Optional,
+        Some("0".to_string()),
+    );
+    args.option(
+        "",
+        "contempt",
+        "Score draws CP centipawns worse for the engine, to play on for a win - default 0",
+        "CP",
+        Occur::Optional,
+        Some("0".to_string()),
+    );
+    args.option(
+        "",
+        "eval-noise",
+        "Randomly change root move scores by up to CP centipawns (needs --seed) - default 0",
+        "CP",
+        Occur::Optional,
+        Some("0".to_string()),
+    );
+    args.option(
+        "",
+        "benchmark-positions-from-epd",
+        "Run the benchmark (-b) on the positions in an EPD file",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "compare-to-fen",
+        "Print the evaluation of --fen and FEN and the difference between them",
+        "FEN",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "perft",
+        "Count the leaf nodes DEPTH plies deep, split by root move",
+        "DEPTH",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "analyze-game",
+        "Search after every move of the game in the PGN file FILE and write it back annotated, flagging inaccuracies, mistakes and blunders",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "testsuite",
+        "Search each position of an EPD test suite, within --depth, --movetime or --nodes, and score the bm/am answers",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "perft-suite",
+        "Check perft counts of the standard test positions up to DEPTH",
+        "DEPTH",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "hash",
+        &format!(
+            "Transposition table size in megabytes - default {}",
+            tt::megabytes_for_entries(tt::DEFAULT_ENTRIES)
+        ),
+        "MB",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "aspiration-researches",
+        "Aspiration window failures per depth before a full-width search, 0 for none - default 3",
+        "N",
+        Occur::Optional,
+        Some(DEFAULT_ASPIRATION_RESEARCHES.to_string()),
+    );
+    args.option(
+        "",
+        "aspiration-window",
+        &format!(
+            "Half-width of the first aspiration window in centipawns, doubling on each failure - default {}",
+            DEFAULT_ASPIRATION_WINDOW
+        ),
+        "CP",
+        Occur::Optional,
+        Some(DEFAULT_ASPIRATION_WINDOW.to_string()),
+    );
+    args.option(
+        "",
+        "search-log",
+        "Append the score of every root move at each depth to FILE (- for stderr)",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "elo",
+        "Estimate the Elo difference from a match result given as W,L,D",
+        "W,L,D",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "eval-tune-dump",
+        "Append the quiet positions of self play games to FILE as fen, eval, result",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "eval-params",
+        "Load evaluation parameters from FILE, overriding the matching options",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "tune",
+        "Tune piece values and square tables on FILE of fen and result lines",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "tune-out",
+        "Write the tuned constants to FILE, as --eval-params reads them if it ends in .toml, else as Rust source - default stdout",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "tune-iterations",
+        "Gradient descent passes for --tune - default 500",
+        "N",
+        Occur::Optional,
+        Some("500".to_string()),
+    );
+    args.flag(
+        "",
+        "flip",
+        "Show the board from Black's side - always on with --play-as black",
+    );
+    args.option(
+        "",
+        "play-as",
+        "Colour to play in interactive mode, white or black - default the side not to move",
+        "COLOUR",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "color",
+        "Same as --play-as",
+        "COLOUR",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "check-extension",
+        "Search checks deeper by off, half or full plies - default off",
+        "AMOUNT",
+        Occur::Optional,
+        Some("off".to_string()),
+    );
+    args.option(
+        "",
+        "max-extension",
+        "Most plies extensions may add along one line - default 4",
+        "PLIES",
+        Occur::Optional,
+        Some(DEFAULT_MAX_EXTENSION.to_string()),
+    );
+    args.option(
+        "",
+        "engine-name",
+        "Name the engine gives in UCI's id line",
+        "NAME",
+        Occur::Optional,
+        Some(PROGRAM_NAME.to_string()),
+    );
+    args.option(
+        "",
+        "author",
+        "Author the engine gives in UCI's id line",
+        "NAME",
+        Occur::Optional,
+        Some(format!("The {} authors", PROGRAM_NAME)),
+    );
+    args.option(
+        "",
+        "multipv",
+        "Find the K best moves, each with its score and line - default 1",
+        "K",
+        Occur::Optional,
+        Some("1".to_string()),
+    );
+    args.option(
+        "",
+        "threads",
+        "Search threads sharing the transposition table - default 1",
+        "N",
+        Occur::Optional,
+        Some("1".to_string()),
+    );
+    args.flag(
+        "",
+        "parallel-root",
+        "Split the root moves between the --threads, each searching with its own table, \
+         instead of sharing one",
+    );
+    args.option(
+        "",
+        "eval-clamp",
+        "Bound the static evaluation to +-CP centipawns, mates excepted",
+        "CP",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "pgn",
+        "Write the interactive or self play game to the PGN file FILE when it ends, replacing it",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "pgn-out",
+        "Same as --pgn",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "match",
+        "Play N games against the --match-opponent options or --match-engine",
+        "N",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "match-opponent",
+        "Command line options of the other side in a match, e.g. \"--depth 3\"",
+        "OPTIONS",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "match-engine",
+        "Play the match against the UCI engine COMMAND runs, e.g. \"stockfish\"",
+        "COMMAND",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "tc",
+        "Play interactive and self play games on the clock: minutes each, with an optional \
+         increment in seconds, e.g. 5+3. The engine times its moves by its clock and a side \
+         whose flag falls loses",
+        "MINUTES[+INC]",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "resume",
+        "Continue the interactive game saved in FILE with the save command, with its side, \
+         depth and clocks",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "match-tc",
+        "Time control of match games in seconds, with an optional increment, e.g. 10+0.1",
+        "SECONDS[+INC]",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "match-openings",
+        "Start match games from the positions in the EPD file FILE, each played with both colours",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "pgn-in",
+        "Replay the first game in the PGN file FILE and start from where it ends",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "pgn-move",
+        "Start from move N of the --pgn-in game, with White to move, or Black for N...",
+        "N",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "selfplay-pgn-append",
+        "Append each finished self play game to the PGN file FILE",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "learn-book",
+        "Count the opening of each finished self play game into the book FILE",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "learned-book",
+        "Play opening moves from the book FILE, favouring lines that scored well",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "book",
+        "Play opening moves from the Polyglot book FILE, drawn by its weights",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "book-moves",
+        "Play --book moves only in the first N moves of each side - default 12",
+        "N",
+        Occur::Optional,
+        Some("12".to_string()),
+    );
+    args.option(
+        "",
+        "book-select",
+        "Choose --book moves: best, the heaviest, or weighted, drawn by weight - \
+         default weighted",
+        "MODE",
+        Occur::Optional,
+        Some("weighted".to_string()),
+    );
+    args.flag(
+        "",
+        "opening-explorer",
+        "List the --book moves for the position with their weights, with --json as JSON",
+    );
+    args.option(
+        "",
+        "games",
+        "Number of self play games to play - default 1",
+        "N",
+        Occur::Optional,
+        Some("1".to_string()),
+    );
+    args.option(
+        "",
+        "futility-margins",
+        "Comma separated futility margins in centipawns by remaining depth - default 0,100,200,300",
+        "LIST",
+        Occur::Optional,
+        Some(
+            eval_params::DEFAULT_FUTILITY_MARGINS
+                .iter()
+                .map(|cp| cp.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+    );
+    args.option(
+        "",
+        "eval-piece-count-adjustment",
+        "Middlegame bonus in centipawns for each minor piece, fading in the endgame - default 50",
+        "CP",
+        Occur::Optional,
+        Some(eval_params::DEFAULT_MINOR_PIECE_BONUS.to_string()),
+    );
+    args.option(
+        "",
+        "movetime",
+        "Limit the search to MS milliseconds per move",
+        "MS",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "nodes",
+        "Stop the search after N nodes, playing the best move found so far",
+        "N",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "move-time-per-piece",
+        "Make each self-play move take at least MS milliseconds per legal move - default 0",
+        "MS",
+        Occur::Optional,
+        Some("0".to_string()),
+    );
+    args.option(
+        "",
+        "move-overhead",
+        "Milliseconds kept back from the move time for communication lag - default 30",
+        "MS",
+        Occur::Optional,
+        Some(time_manager::DEFAULT_MOVE_OVERHEAD_MS.to_string()),
+    );
+    args.option(
+        "",
+        "panic-time",
+        "Extra milliseconds a --movetime search may use when its score drops - default 0",
+        "MS",
+        Occur::Optional,
+        Some("0".to_string()),
+    );
+    args.option(
+        "",
+        "endgame-chain",
+        "Comma separated endgame sources to try in order: syzygy, builtin, search",
+        "LIST",
+        Occur::Optional,
+        Some("builtin,search".to_string()),
+    );
+    args.option(
+        "",
+        "log-file",
+        "Record the commands typed in interactive mode to FILE",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "replay",
+        "Play an interactive game from a command log written by --log-file",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "compare-engines-fen",
+        "Search FEN with the --engine-a and --engine-b settings and compare the results",
+        "FEN",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "engine-a",
+        "Command line options of the first engine for --compare-engines-fen",
+        "ARGS",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "engine-b",
+        "Command line options of the second engine for --compare-engines-fen",
+        "ARGS",
+        Occur::Optional,
+        None,
+    );
+    args.option(
+        "",
+        "moves-file",
+        "Play the human moves in FILE, one per line, then continue from stdin",
+        "FILE",
+        Occur::Optional,
+        None,
+    );
     args.parse(input)?;
 
-    let is_help = args.value_of("help")?;
-    if is_help {
-        args.full_usage();
+    if args.value_of("help")? {
+        return Err(CliError::Help(args.full_usage()));
+    }
+    // A timed or node-limited search stops on its own limit, so only an
+    // explicit depth caps it.
+    let limited = args.optional_value_of::<u64>("movetime")?.is_some()
+        || args.optional_value_of::<u64>("nodes")?.is_some();
+    let depth = match args.optional_value_of::<String>("depth")? {
+        Some(depth) => parse_depth(&depth)?,
+        None if limited => MAX_DEPTH,
+        None => DEFAULT_DEPTH,
+    };
+    let play_as = args
+        .optional_value_of::<String>("play-as")?
+        .or(args.optional_value_of("color")?)
+        .map(|s| parse_colour(&s))
+        .transpose()?;
+    let mut opts = Options {
+        is_interactive: args.value_of("interactive")?,
+        is_selfplay: args.value_of("selfplay")?,
+        run_benchmark: args.value_of("bench")?,
+        cutoff_stats: args.value_of("cutoff-stats")?,
+        compare_move_order: args.value_of("compare-move-order")?,
+        compare_engines_fen: args.optional_value_of("compare-engines-fen")?,
+        engine_a: args.optional_value_of("engine-a")?.unwrap_or_default(),
+        engine_b: args.optional_value_of("engine-b")?.unwrap_or_default(),
+        uci: args.value_of("uci")?,
+        xboard: args.value_of("xboard")?,
+        lichess_bot: args.value_of("lichess-bot")?,
+        #[cfg(feature = "online")]
+        lichess_url: args.value_of("lichess-url")?,
+        #[cfg(feature = "online")]
+        lichess_base: parse_base_range(&args.value_of::<String>("lichess-base")?)?,
+        #[cfg(feature = "online")]
+        lichess_casual_only: args.value_of("lichess-casual-only")?,
+        validate_benchmarks: args.value_of("validate-benchmarks")?,
+        dump_eval_tables: args.value_of("dump-eval-tables")?,
+        reproducibility_check: args.value_of("search-reproducibility-check")?,
+        fen_roundtrip_test: args.value_of("fen-roundtrip-test")?,
+        perft: args.optional_value_of("perft")?,
+        perft_suite: args.optional_value_of("perft-suite")?,
+        testsuite: args.optional_value_of("testsuite")?,
+        analyze_game: args.optional_value_of("analyze-game")?,
+        no_banner: args.value_of("no-banner")?,
+        play_as,
+        #[cfg(not(target_arch = "wasm32"))]
+        engine_id: uci::EngineId {
+            name: args.value_of("engine-name")?,
+            author: args.value_of("author")?,
+        },
+        board_from_stdin: args.value_of("board-from-stdin")?,
+        first_move_only: args.value_of("first-move-only")?,
+        mate: args.optional_value_of("mate")?,
+        chess960: args.value_of::<bool>("chess960")?
+            || args.optional_value_of::<u32>("position960")?.is_some(),
+        fen_str: match args.optional_value_of::<u32>("position960")? {
+            Some(number) => chess960::start_position(number).ok_or_else(|| {
+                ArgsError::new(
+                    "parse",
+                    &format!("Chess960 positions run from 0 to 959, got {}", number),
+                )
+            })?,
+            None => args.value_of("fen")?,
+        },
+        show_attacks: args
+            .optional_value_of::<String>("show-attacks")?
+            .map(|s| parse_colour(&s))
+            .transpose()?,
+        show_threats: args.value_of("show-threats")?,
+        show_phase: args.value_of("show-phase")?,
+        trace: args.value_of("trace")?,
+        material_table: args.value_of("material-table-view")?,
+        log_file: args.optional_value_of("log-file")?,
+        replay: args.optional_value_of("replay")?,
+        moves_file: args.optional_value_of("moves-file")?,
+        compare_to_fen: args.optional_value_of("compare-to-fen")?,
+        benchmark_epd: args.optional_value_of("benchmark-positions-from-epd")?,
+        bench_output: parse_bench_output(&args.value_of::<String>("bench-output")?)?,
+        bench_save: args.optional_value_of("bench-save")?,
+        bench_baseline: args.optional_value_of("bench-baseline")?,
+        tune: args.optional_value_of("tune")?,
+        tune_out: args.optional_value_of("tune-out")?,
+        tune_iterations: args.value_of("tune-iterations")?,
+        opening_explorer: args.value_of("opening-explorer")?,
+        match_games: args.optional_value_of("match")?,
+        match_opponent: args
+            .optional_value_of("match-opponent")?
+            .unwrap_or_default(),
+        match_engine: args.optional_value_of("match-engine")?,
+        match_tc: parse_time_control(args.optional_value_of("match-tc")?, 1000.0)?,
+        resume: args.optional_value_of("resume")?,
+        match_openings: args.optional_value_of("match-openings")?,
+        pgn_in: args.optional_value_of("pgn-in")?,
+        pgn_move: parse_pgn_move(args.optional_value_of("pgn-move")?)?,
+        games: args.value_of("games")?,
+        elo: args.optional_value_of("elo")?,
+        play: PlayOptions {
+            ponder: args.value_of("ponder")?,
+            pv_san: args.value_of("pv-san")?,
+            flip: args.value_of("flip")? || play_as == Some(Color::Black),
+            move_time_per_piece: args.value_of("move-time-per-piece")?,
+            json: args.value_of::<bool>("json")? || args.value_of::<bool>("stats-json")?,
+            suggest_draw: args.value_of("suggest-draw")?,
+            human_score: args.value_of("human-score")?,
+            eval_bar: args.value_of("display-eval-bar")?,
+            show_clocks: args.value_of("half-move-clock-display")?,
+            depth,
+            eval_tune_dump: args.optional_value_of("eval-tune-dump")?,
+            selfplay_pgn: args.optional_value_of("selfplay-pgn-append")?,
+            learn_book: args.optional_value_of("learn-book")?,
+            learned_book: args.optional_value_of("learned-book")?,
+            book: args.optional_value_of("book")?,
+            book_moves: args.value_of("book-moves")?,
+            book_selection: parse_book_selection(&args.value_of::<String>("book-select")?)?,
+            pgn: args
+                .optional_value_of("pgn")?
+                .or(args.optional_value_of("pgn-out")?),
+            tc: parse_time_control(args.optional_value_of("tc")?, 60_000.0)?,
+            config: SearchConfig {
+                endgame_chain: parse_endgame_chain(&args.value_of::<String>("endgame-chain")?)?,
+                movetime: args.optional_value_of("movetime")?,
+                max_nodes: args.optional_value_of("nodes")?,
+                move_overhead: args.value_of("move-overhead")?,
+                panic_time: args.value_of("panic-time")?,
+                eval: EvalParams {
+                    minor_piece_bonus: args.value_of("eval-piece-count-adjustment")?,
+                    futility_margins: parse_futility_margins(
+                        &args.value_of::<String>("futility-margins")?,
+                    )?,
+                    material: !args.value_of::<bool>("positional-only")?,
+                    attack_map: args.value_of("attack-map-eval")?,
+                    mobility: !args.value_of::<bool>("no-mobility")?,
+                    clamp: parse_eval_clamp(args.optional_value_of("eval-clamp")?)?,
+                    ..EvalParams::default()
+                },
+                pv_verify: args.value_of::<bool>("pv-verify")? || cfg!(debug_assertions),
+                thinking: args.value_of("thinking")?,
+                search_log: args.optional_value_of("search-log")?,
+                tt_depth_check: !args.value_of::<bool>("tt-any-depth")?,
+                tt_entries: args
+                    .optional_value_of("hash")?
+                    .map(parse_hash)
+                    .transpose()?
+                    .map_or(tt::DEFAULT_ENTRIES, tt::entries_for_megabytes),
+                extensions: !args.value_of::<bool>("no-extensions")?,
+                check_extension: parse_check_extension(
+                    &args.value_of::<String>("check-extension")?,
+                )?,
+                max_extension: args.value_of("max-extension")?,
+                scout: !args.value_of::<bool>("no-scout")?,
+                null_move: !args.value_of::<bool>("no-null-move")?,
+                late_move_reductions: !args.value_of::<bool>("no-lmr")?,
+                see_pruning: !args.value_of::<bool>("no-see-pruning")?,
+                futility_pruning: !args.value_of::<bool>("no-futility")?,
+                delta_pruning: !args.value_of::<bool>("no-delta-pruning")?,
+                fifty_move_draw: args.value_of("fifty-move-draw")?,
+                halfmove_clock: Clocks::from_fen(&args.value_of::<String>("fen")?).halfmove,
+                game_history: Vec::new(),
+                castling: chess960::Castling::default(),
+                ordering: MoveOrdering::HashMove,
+                pvs_research_cap: args.optional_value_of("pvs-research-cap")?,
+                info: args.value_of("info")?,
+                info_interval: args.value_of("max-pv-reporting-interval")?,
+                aspiration_researches: args.value_of("aspiration-researches")?,
+                aspiration_window: parse_aspiration_window(args.value_of("aspiration-window")?)?,
+                analyse_mode: args.value_of("uci-analysemode")?,
+                seed: args.optional_value_of("seed")?,
+                seed_from_fen: args.value_of("search-seed-from-fen")?,
+                eval_noise: args.value_of("eval-noise")?,
+                suboptimal_percent: 0,
+                suboptimal_margin: 0,
+                draw_score: args.value_of::<i64>("draw-score")?
+                    - args.value_of::<i64>("contempt")?,
+                stop: None,
+                threads: parse_threads(args.value_of("threads")?)?,
+                parallel_root: args.value_of("parallel-root")?,
+                multipv: parse_multipv(args.value_of("multipv")?)?,
+                excluded_moves: Vec::new(),
+                trace: false,
+            },
+        },
     };
-    let is_interactive = args.value_of("interactive")?;
-    let is_selfplay = args.value_of("selfplay")?;
-    let run_benchmark = args.value_of("bench")?;
-    let fen_str = args.value_of("fen")?;
-    let play_count = args.value_of::<String>("depth")?.parse::<i8>().unwrap();
-    println!("Depth: {}", play_count);
-    Ok((
-        is_interactive,
-        is_selfplay,
-        run_benchmark,
-        fen_str,
-        play_count,
-    ))
+    if let Some(path) = args.optional_value_of::<String>("eval-params")? {
+        opts.play.config.eval = opts.play.config.eval.load(&path).map_err(|e| {
+            ArgsError::new("parse", &format!("Bad eval parameters {}: {}", path, e))
+        })?;
+    }
+    if let Some(skill) = args.optional_value_of::<i64>("skill")? {
+        apply_skill(&mut opts, skill)?;
+    }
+    check_modes(&opts)?;
+    Ok(opts)
 }
 
-fn exec_ai_turn(board: &mut Board, ply_count: i8) {
-    match find_best_move(board, ply_count) {
-        Some(n) => *board = board.make_move_new(n),
-        None => {
-            println!("Error!! No move found")
-        }
+/// Applies `--skill`, which must be from 0 to `MAX_SKILL`, with
+/// `weaken_to_skill`.
+fn apply_skill(opts: &mut Options, skill: i64) -> Result<(), ArgsError> {
+    if !(0..=MAX_SKILL).contains(&skill) {
+        return Err(ArgsError::new(
+            "parse",
+            &format!("Skill must be from 0 to {}, got {}", MAX_SKILL, skill),
+        ));
     }
-    println!("--------------------");
-    show_board(*board);
+    weaken_to_skill(skill, &mut opts.play.depth, &mut opts.play.config);
+    Ok(())
 }
 
-fn exec_user_turn(board: &mut Board) {
-    let stdin = std::io::stdin();
-    for line in stdin.lock().lines() {
-        let s = match line {
-            Ok(l) => l,
-            Err(_) => "".to_string(),
-        };
+fn parse_book_selection(name: &str) -> Result<polyglot::Selection, ArgsError> {
+    polyglot::Selection::from_name(name).ok_or_else(|| {
+        ArgsError::new(
+            "parse",
+            &format!(
+                "Unknown book selection '{}': expected best or weighted",
+                name
+            ),
+        )
+    })
+}
 
-        if let Ok(mv) = ChessMove::from_san(&board, &s) {
-            *board = board.make_move_new(mv);
-            break;
-        } else {
-            println!("Invalid Move");
+/// Plays a `--match` of `games` games, as A with these options against B
+/// with `--match-opponent` or `--match-engine`. Without `--match-openings`
+/// every game starts from `start`.
+fn run_match(opts: &Options, games: u32, start: GameState) {
+    let openings = match &opts.match_openings {
+        Some(path) => match epd::load_positions(path) {
+            Ok(positions) => positions
+                .iter()
+                .filter_map(|(_, fen)| {
+                    let board = Board::from_str(fen).ok()?;
+                    Some(GameState::new(board, Clocks::from_fen(fen)))
+                })
+                .collect(),
+            Err(e) => {
+                println!("Cannot read EPD file: {}", e);
+                return;
+            }
+        },
+        None => vec![start],
+    };
+    if openings.is_empty() {
+        println!("No start positions for the match");
+        return;
+    }
+    let mut first = match_runner::Player::Engine {
+        depth: opts.play.depth,
+        config: Box::new(opts.play.config.clone()),
+    };
+    let mut second = match &opts.match_engine {
+        Some(command) => match match_runner::UciEngine::start(command) {
+            Ok(engine) => match_runner::Player::External {
+                engine,
+                depth: opts.play.depth,
+            },
+            Err(e) => {
+                println!("Cannot start {}: {}", command, e);
+                return;
+            }
+        },
+        None => {
+            let mut args = vec![PROGRAM_NAME.to_string()];
+            args.extend(opts.match_opponent.split_whitespace().map(str::to_string));
+            match parse(&args) {
+                Ok(other) => match_runner::Player::Engine {
+                    depth: other.play.depth,
+                    config: Box::new(other.play.config),
+                },
+                Err(e) => {
+                    println!("Bad --match-opponent options: {}", e);
+                    return;
+                }
+            }
         }
+    };
+    if let Err(e) = match_runner::run(&mut first, &mut second, &openings, games, opts.match_tc) {
+        println!("Match stopped: {}", e);
+    }
+}
 
+/// Plays on Lichess for `--lichess-bot` until the event stream closes.
+#[cfg(feature = "online")]
+fn run_lichess_bot(opts: &Options) {
+    let Ok(token) = std::env::var("LICHESS_BOT_TOKEN") else {
+        println!("Set LICHESS_BOT_TOKEN to the bot account's API token");
+        return;
+    };
+    let settings = lichess::Settings {
+        token,
+        url: opts.lichess_url.trim_end_matches('/').to_string(),
+        min_base: opts.lichess_base.0,
+        max_base: opts.lichess_base.1,
+        casual_only: opts.lichess_casual_only,
+    };
+    if let Err(e) = lichess::run(&settings, &opts.play.config) {
+        println!("Lichess bot stopped: {}", e);
     }
-    println!("--------------------");
-    show_board(*board);
 }
 
-fn interactive_loop(mut board: Board, ply_count: i8) {
-    let mut ai_turn = true;
-    loop {
-        match board.status() {
-            BoardStatus::Ongoing => {
-                if ai_turn {
-                    exec_ai_turn(&mut board, ply_count);
-                } else {
-                    println!("Your turn...");
-                    exec_user_turn(&mut board);
+#[cfg(not(feature = "online"))]
+fn run_lichess_bot(_opts: &Options) {
+    println!("This build can't play online; rebuild with --features online");
+}
+
+fn parse_bench_output(name: &str) -> Result<BenchOutput, ArgsError> {
+    BenchOutput::from_name(name).ok_or_else(|| {
+        ArgsError::new(
+            "parse",
+            &format!(
+                "Unknown bench output '{}': expected text, csv or json",
+                name
+            ),
+        )
+    })
+}
+
+/// Runs the engine as its command line does, `args` starting with the
+/// program name.
+fn run(args: &[String]) -> Result<(), CliError> {
+    let opts = parse(args)?;
+    interrupt::install();
+
+    // A GUI expects nothing but protocol output, banner included.
+    #[cfg(not(target_arch = "wasm32"))]
+    if opts.uci {
+        uci::run(&opts.play.config, opts.play.depth, &opts.engine_id);
+        return Ok(());
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if opts.xboard {
+        xboard::run(&opts.play.config, opts.play.depth, &opts.engine_id);
+        return Ok(());
+    }
+    if opts.lichess_bot {
+        run_lichess_bot(&opts);
+        return Ok(());
+    }
+    // Modes meant for scripts keep stdout to their own output as well.
+    let machine_output = opts.board_from_stdin || opts.first_move_only || opts.play.json;
+    if !opts.no_banner && !machine_output {
+        println!("Scacchi !!");
+        println!("Depth: {}", opts.play.depth);
+    }
+
+    if opts.run_benchmark {
+        let cases = match &opts.benchmark_epd {
+            Some(path) => match epd::load_positions(path) {
+                Ok(cases) => cases,
+                Err(e) => {
+                    println!("Cannot read EPD file: {}", e);
+                    return Ok(());
                 }
-                ai_turn = !ai_turn;
-            }
-            BoardStatus::Stalemate => {
-                println!("Stalemate...");
-                return;
+            },
+            None => benchmarks::CASES
+                .iter()
+                .map(|&(name, fen)| (name.to_string(), fen.to_string()))
+                .collect(),
+        };
+        let output = if opts.play.json {
+            BenchOutput::Json
+        } else {
+            opts.bench_output
+        };
+        let rows = run_benchmark(
+            &cases,
+            output,
+            opts.play.config.threads,
+            opts.play.config.parallel_root,
+        );
+        if let Some(path) = &opts.bench_save {
+            match save_bench(path, &rows) {
+                Ok(()) => println!("Saved benchmark to {}", path),
+                Err(e) => println!("Cannot write {}: {}", path, e),
             }
-            BoardStatus::Checkmate => {
-                println!("Checkmate!!");
-                return;
+        }
+        if let Some(path) = &opts.bench_baseline {
+            match load_bench(path) {
+                Ok(baseline) if compare_bench(&rows, &baseline) => {}
+                Ok(_) => std::process::exit(1),
+                Err(e) => println!("Cannot read baseline {}: {}", path, e),
             }
         }
+        return Ok(());
     }
-}
 
-fn self_play_loop(mut board: Board, ply_count: i8) {
-    loop {
-        if board.status() == BoardStatus::Ongoing {
-            exec_ai_turn(&mut board, ply_count)
-        } else {
-            return;
+    if opts.dump_eval_tables {
+        run_dump_eval_tables();
+        return Ok(());
+    }
+
+    if opts.validate_benchmarks {
+        let bad = run_validate_benchmarks();
+        println!("{} of {} benchmark cases bad", bad, benchmarks::CASES.len());
+        return Ok(());
+    }
+
+    if opts.fen_roundtrip_test {
+        let failures = run_fen_roundtrip_test(&opts.fen_str);
+        if failures > 0 {
+            println!("{} FENs did not round-trip", failures);
+            std::process::exit(1);
         }
+        return Ok(());
     }
-}
 
-fn run_benchmark() {
-    println!("name\tdepth\tduration");
-    for (name, fen) in benchmarks::CASES {
-        let start = Instant::now();
-        match Board::from_str(fen) {
-            Ok(board) => {
-                for &depth in benchmarks::DEPTHS {
-                    find_best_move(&board, depth);
-                    let duration = start.elapsed().as_millis();
-                    println!("{}\t{}\t{}", name, depth, duration);
-                }
+    if let Some(path) = &opts.analyze_game {
+        match pgn::load_game(path) {
+            Ok(game) => {
+                let reports = annotate::analyze_game(&game, opts.play.depth, &opts.play.config);
+                annotate::print_report(&game, &reports, opts.play.json);
             }
-            Err(_) => {}
+            Err(e) => println!("Bad PGN: {}", e),
         }
+        return Ok(());
     }
-}
 
-fn main() {
-    println!("Scacchi !!");
+    if let Some(path) = &opts.testsuite {
+        match epd::load_suite(path) {
+            Ok(suite) => run_testsuite(&suite, opts.play.depth, &opts.play.config, opts.play.json),
+            Err(e) => println!("Cannot read EPD file: {}", e),
+        }
+        return Ok(());
+    }
 
-    let args: Vec<String> = env::args().collect();
-    let (is_interactive, is_selfplay, run_bench, fen_str, play_count) = parse(&args).unwrap();
+    if let Some(depth) = opts.perft_suite {
+        let failures = run_perft_suite(depth);
+        if failures > 0 {
+            println!("{} perft counts are wrong", failures);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    if run_bench {
-        run_benchmark();
-        return;
+    if opts.reproducibility_check {
+        let failures = run_reproducibility_check(opts.play.depth, &opts.play.config);
+        if failures > 0 {
+            println!("Search is not reproducible in {} positions", failures);
+            std::process::exit(1);
+        }
+        return Ok(());
     }
 
-    let board = match Board::from_str(fen_str.as_str()) {
-        Ok(b) => b,
-        Err(_) => {
-            println!("Bad FEN");
-            return;
+    if opts.compare_move_order {
+        run_compare_move_order(opts.play.depth, &opts.play.config);
+        return Ok(());
+    }
+
+    if opts.cutoff_stats {
+        run_cutoff_stats(opts.play.depth, &opts.play.config);
+        return Ok(());
+    }
+
+    if let Some(fen) = &opts.compare_engines_fen {
+        match load_board(fen) {
+            Ok(board) => run_compare_engines(
+                &board,
+                Clocks::from_fen(fen).halfmove,
+                [("a", &opts.engine_a), ("b", &opts.engine_b)],
+            ),
+            Err(e) => return Err(CliError::BadFen(e)),
         }
+        return Ok(());
+    }
+
+    if let Some(result) = &opts.elo {
+        run_elo(result);
+        return Ok(());
+    }
+
+    if let Some(path) = &opts.tune {
+        run_tune(
+            path,
+            &opts.play.config.eval,
+            opts.tune_iterations,
+            opts.tune_out.as_deref(),
+        );
+        return Ok(());
+    }
+
+    if opts.board_from_stdin || opts.first_move_only {
+        run_board_from_stdin(
+            opts.play.depth,
+            &opts.play.config,
+            opts.chess960,
+            opts.first_move_only,
+        );
+        return Ok(());
+    }
+
+    // The game so far: a replayed PGN game, or just the FEN.
+    let start = match &opts.pgn_in {
+        Some(path) => match load_pgn(path, opts.pgn_move) {
+            Ok(game) => game,
+            Err(e) => {
+                println!("Bad PGN: {}", e);
+                return Ok(());
+            }
+        },
+        None => load_game(opts.fen_str.as_str(), opts.chess960).map_err(CliError::BadFen)?,
     };
+    let board = start.board();
 
-    if is_selfplay {
-        self_play_loop(board, play_count);
-        println!("Good Game!");
-        return;
+    if let Some(games) = opts.match_games {
+        run_match(&opts, games, start);
+        return Ok(());
+    }
+
+    if let Some(depth) = opts.perft {
+        run_perft(&start, depth, opts.play.json);
+        return Ok(());
+    }
+
+    if let Some(colour) = opts.show_attacks {
+        show_board_with_overlay(
+            board,
+            attacks::attacked_squares(&board, colour),
+            opts.play.flip,
+        );
+        return Ok(());
+    }
+
+    if let Some(other_fen) = &opts.compare_to_fen {
+        match load_board(other_fen) {
+            Ok(other) => compare_positions(&board, &other, &opts.play.config.eval),
+            Err(e) => return Err(CliError::BadFen(e)),
+        }
+        return Ok(());
+    }
+
+    if opts.show_threats {
+        show_threats(&board);
+        return Ok(());
+    }
+
+    if opts.material_table {
+        show_material(&board);
+        return Ok(());
+    }
+
+    if let Some(moves) = opts.mate {
+        run_mate_search(&start, moves, &opts.play.config, opts.play.json);
+        return Ok(());
+    }
+
+    if opts.show_phase {
+        println!(
+            "Phase: {} / {}",
+            game_phase(&board),
+            piece_values::MAX_PHASE
+        );
+        return Ok(());
+    }
+
+    if opts.trace {
+        run_trace(&start, opts.play.depth, &opts.play.config, opts.play.json);
+        return Ok(());
     }
 
-    if !is_interactive {
-        match find_best_move(&board, play_count) {
+    if opts.opening_explorer {
+        let Some(path) = &opts.play.book else {
+            return Err(CliError::Usage(
+                "--opening-explorer needs --book".to_string(),
+            ));
+        };
+        match polyglot::Book::load(path) {
+            Ok(book) => polyglot::print_explorer(&book, &board, opts.play.json),
+            Err(e) => println!("Cannot read book {}: {}", path, e),
+        }
+        return Ok(());
+    }
+
+    if opts.is_selfplay {
+        for _ in 0..opts.games {
+            self_play_loop(&start, &opts.play);
+        }
+        if !opts.play.json {
+            println!("Good Game!");
+        }
+        return Ok(());
+    }
+
+    if !opts.is_interactive && opts.replay.is_none() && opts.moves_file.is_none() {
+        let config = SearchConfig {
+            halfmove_clock: start.clocks().halfmove,
+            game_history: start.earlier_hashes(),
+            castling: start.castling(),
+            ..opts.play.config.clone()
+        };
+        let started = Instant::now();
+        let mut lines = multipv_search(&board, opts.play.depth, &config);
+        let result = lines.remove(0);
+        if opts.play.json {
+            let ms = started.elapsed().as_millis();
+            if lines.is_empty() {
+                println!("{}", search_json(&board, &result, ms));
+                return Ok(());
+            }
+            // Every MultiPV line shares the time of the whole search.
+            for (idx, line) in std::iter::once(&result).chain(&lines).enumerate() {
+                println!("{}", search_json(&board, line, ms).raw("multipv", idx + 1));
+            }
+            return Ok(());
+        }
+        match result.best_move {
             Some(n) => {
                 println!("Best Move: {}", n)
             }
@@ -343,7 +1844,131 @@ fn main() {
                 println!("Error!! No move found!")
             }
         }
+        if opts.play.pv_san && !result.pv.is_empty() {
+            let fullmove = start.clocks().fullmove;
+            println!("Line: {}", format_line(&board, fullmove, &result.pv, true));
+        }
+        if opts.play.human_score {
+            println!("Score: {}", human_score(score_to_white(result.score)));
+        }
+        if !lines.is_empty() {
+            lines.insert(0, result);
+            show_multipv(&start, &lines, opts.play.pv_san);
+        }
     } else {
-        interactive_loop(board, play_count);
+        let input = match (&opts.replay, &opts.moves_file) {
+            (Some(path), _) => CommandInput::from_file(path),
+            (None, Some(path)) => CommandInput::from_file(path).map(CommandInput::then_stdin),
+            (None, None) => Ok(CommandInput::stdin()),
+        };
+        let input = match (input, &opts.log_file) {
+            (Ok(input), Some(path)) => input.log_to(path),
+            (input, _) => input,
+        };
+        match input {
+            Ok(mut input) => match &opts.resume {
+                Some(path) => match Session::load(path) {
+                    Ok(session) => {
+                        interactive_loop(session, &opts.play, &mut input);
+                    }
+                    Err(e) => println!("Cannot resume {}: {}", path, e),
+                },
+                None => {
+                    let session = Session {
+                        engine: match opts.play_as {
+                            Some(human) => !human,
+                            None => start.board().side_to_move(),
+                        },
+                        game: start,
+                        depth: opts.play.depth,
+                        clock: opts.play.tc.map(|tc| GameClock::new(tc.base, tc.increment)),
+                    };
+                    interactive_loop(session, &opts.play, &mut input);
+                }
+            },
+            Err(e) => println!("Cannot open command file: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Searches `board` once with each engine's settings, given as command
+/// line arguments, and prints their best moves and scores side by side.
+fn run_compare_engines(board: &Board, halfmove: u32, engines: [(&str, &str); 2]) {
+    let results = match compare_engines(board, halfmove, engines) {
+        Ok(results) => results,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    println!("engine\tdepth\tbestmove\tscore");
+    for ((name, _), (depth, result)) in engines.iter().zip(&results) {
+        let best = result
+            .best_move
+            .map_or("(none)".to_string(), |mv| mv.to_string());
+        println!(
+            "{}\t{}\t{}\t{:+}",
+            name,
+            depth,
+            best,
+            score_to_cp(board, result.score)
+        );
+    }
+    if results[0].1.best_move == results[1].1.best_move {
+        println!("Agree");
+    } else {
+        println!("Disagree");
+    }
+}
+
+/// Each engine's search depth and result for `run_compare_engines`.
+fn compare_engines(
+    board: &Board,
+    halfmove: u32,
+    engines: [(&str, &str); 2],
+) -> Result<Vec<(i8, SearchResult)>, String> {
+    let mut results = Vec::new();
+    for (name, engine_args) in engines {
+        let input: Vec<String> = std::iter::once(PROGRAM_NAME)
+            .chain(engine_args.split_whitespace())
+            .map(str::to_string)
+            .collect();
+        let opts =
+            parse(&input).map_err(|e| format!("Bad arguments for engine {}: {}", name, e))?;
+        let config = SearchConfig {
+            halfmove_clock: halfmove,
+            ..opts.play.config
+        };
+        results.push((opts.play.depth, search(board, opts.play.depth, &config)));
+    }
+    Ok(results)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Err(e) = run(&args) {
+        e.exit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compared_engines_agree_unless_one_sees_further() {
+        let board =
+            Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .unwrap();
+        let same = compare_engines(&board, 0, [("a", "--depth 3"), ("b", "--depth 3")]).unwrap();
+        assert_eq!(same[0].1.best_move, same[1].1.best_move);
+        // Mate in two: 1. Kb6 or 1. Kc7, then the rook mates.
+        let board = Board::from_str("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let results = compare_engines(&board, 0, [("a", "--depth 1"), ("b", "--depth 4")]).unwrap();
+        let (shallow, deep) = (&results[0].1, &results[1].1);
+        assert!(!is_mate_score(shallow.score));
+        assert_ne!(shallow.best_move, deep.best_move);
+        assert_eq!(deep.score, MATE_SCORE - 3);
     }
 }
@@ -0,0 +1,275 @@
+use crate::game_state::GameState;
+use crate::search::{search, SearchConfig};
+use crate::time_manager::{GameClock, Instant};
+use crate::{elo, fen, tuning};
+use chess::{BoardStatus, ChessMove, Color};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+
+/// Plies after which a game still going is scored as a draw.
+const MAX_PLIES: usize = 400;
+
+/// Time for each side's whole game, plus an increment per move, in
+/// milliseconds.
+#[derive(Clone, Copy)]
+pub struct TimeControl {
+    pub base: u64,
+    pub increment: u64,
+}
+
+/// Another engine, spoken to over UCI on its stdin and stdout.
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciEngine {
+    /// Starts the engine run by `command`, a program followed by its
+    /// arguments, and waits for it to be ready.
+    pub fn start(command: &str) -> io::Result<UciEngine> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::other("no engine command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("no pipes to engine"));
+        };
+        let mut engine = UciEngine {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        };
+        engine.send("uci")?;
+        engine.wait_for("uciok")?;
+        engine.send("isready")?;
+        engine.wait_for("readyok")?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()
+    }
+
+    /// Reads lines until one starts with `prefix`, and returns it.
+    fn wait_for(&mut self, prefix: &str) -> io::Result<String> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("engine quit before {}", prefix),
+                ));
+            }
+            if line.starts_with(prefix) {
+                return Ok(line.trim().to_string());
+            }
+        }
+    }
+
+    /// The engine's move in `game`, searched as `go` with `limits`.
+    fn best_move(&mut self, game: &GameState, limits: &str) -> io::Result<Option<ChessMove>> {
+        let (start, clocks) = game.start();
+        let mut position = format!("position fen {}", fen::to_fen(&start, clocks));
+        if !game.moves().is_empty() {
+            position.push_str(" moves");
+            for mv in game.moves() {
+                position.push_str(&format!(" {}", mv));
+            }
+        }
+        self.send(&position)?;
+        self.send(&format!("go {}", limits))?;
+        let reply = self.wait_for("bestmove")?;
+        Ok(reply
+            .split_whitespace()
+            .nth(1)
+            .and_then(|mv| ChessMove::from_str(mv).ok()))
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+
+/// One side of a match.
+pub enum Player {
+    /// This engine, searching to `depth` with `config`.
+    Engine {
+        depth: i8,
+        config: Box<SearchConfig>,
+    },
+    /// An external engine, told to search to `depth` when there is no
+    /// clock.
+    External { engine: UciEngine, depth: i8 },
+}
+
+impl Player {
+    /// The move to play in `game`, going by `clock` when the game is timed.
+    fn choose(
+        &mut self,
+        game: &GameState,
+        clock: Option<&GameClock>,
+    ) -> io::Result<Option<ChessMove>> {
+        let side = game.board().side_to_move();
+        match self {
+            Player::Engine { depth, config } => {
+                let config = SearchConfig {
+                    movetime: match clock {
+                        Some(clock) => Some(clock.budget(side)),
+                        None => config.movetime,
+                    },
+                    halfmove_clock: game.clocks().halfmove,
+                    fifty_move_draw: true,
+                    game_history: game.earlier_hashes(),
+                    ..(**config).clone()
+                };
+                Ok(search(&game.board(), *depth, &config).best_move)
+            }
+            Player::External { engine, depth } => {
+                let limits = match clock {
+                    Some(clock) => format!(
+                        "wtime {} btime {} winc {} binc {}",
+                        clock.left()[Color::White.to_index()],
+                        clock.left()[Color::Black.to_index()],
+                        clock.increment(),
+                        clock.increment()
+                    ),
+                    None => format!("depth {}", depth),
+                };
+                engine.best_move(game, &limits)
+            }
+        }
+    }
+}
+
+/// The result of a game `side` lost.
+pub fn loss(side: Color) -> &'static str {
+    match side {
+        Color::White => "0-1",
+        Color::Black => "1-0",
+    }
+}
+
+/// Plays one game from `start` and returns its result and how it ended.
+/// A side that runs out of time, or plays no legal move, loses.
+fn play_game(
+    white: &mut Player,
+    black: &mut Player,
+    start: &GameState,
+    tc: Option<TimeControl>,
+) -> io::Result<(&'static str, &'static str)> {
+    let mut game = start.clone();
+    let mut clock = tc.map(|tc| GameClock::new(tc.base, tc.increment));
+    loop {
+        let board = game.board();
+        match board.status() {
+            BoardStatus::Checkmate => return Ok((tuning::game_result(&board), "checkmate")),
+            BoardStatus::Stalemate => return Ok(("1/2-1/2", "stalemate")),
+            BoardStatus::Ongoing => {}
+        }
+        if let Some(rule) = game.draw_by_rule() {
+            return Ok(("1/2-1/2", rule));
+        }
+        if game.plies_played() - start.plies_played() >= MAX_PLIES {
+            return Ok(("1/2-1/2", "move limit"));
+        }
+
+        let side = board.side_to_move();
+        let player = match side {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+        let started = Instant::now();
+        let mv = player.choose(&game, clock.as_ref())?;
+        if let Some(clock) = clock.as_mut() {
+            if !clock.charge(side, started.elapsed().as_millis() as u64) {
+                return Ok((loss(side), "time forfeit"));
+            }
+        }
+        match mv.filter(|&mv| board.legal(mv)) {
+            Some(mv) => game.make_move(mv),
+            None => return Ok((loss(side), "no legal move played")),
+        }
+    }
+}
+
+/// Plays `games` games between `first` and `second`, cycling through
+/// `openings` and playing each twice with the colours swapped, then prints
+/// the score and the Elo difference it implies for `first`.
+pub fn run(
+    first: &mut Player,
+    second: &mut Player,
+    openings: &[GameState],
+    games: u32,
+    tc: Option<TimeControl>,
+) -> io::Result<()> {
+    let (mut wins, mut losses, mut draws) = (0, 0, 0);
+    for round in 0..games {
+        let start = &openings[(round / 2) as usize % openings.len()];
+        let first_white = round % 2 == 0;
+        let (result, reason) = if first_white {
+            play_game(first, second, start, tc)?
+        } else {
+            play_game(second, first, start, tc)?
+        };
+        let first_won = match result {
+            "1-0" => Some(first_white),
+            "0-1" => Some(!first_white),
+            _ => None,
+        };
+        match first_won {
+            Some(true) => wins += 1,
+            Some(false) => losses += 1,
+            None => draws += 1,
+        }
+        let pairing = if first_white { "A-B" } else { "B-A" };
+        println!("Game {} {}: {} ({})", round + 1, pairing, result, reason);
+    }
+    println!("A: {} wins, {} draws, {} losses", wins, draws, losses);
+    if let Some(estimate) = elo::estimate(wins, losses, draws) {
+        println!("Elo: {}", estimate);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::Clocks;
+    use crate::search::{weaken_to_skill, MAX_SKILL};
+    use chess::Board;
+
+    fn player(skill: i64) -> Player {
+        let mut depth = 3;
+        let mut config = SearchConfig {
+            seed: Some(1),
+            ..SearchConfig::default()
+        };
+        weaken_to_skill(skill, &mut depth, &mut config);
+        Player::Engine {
+            depth,
+            config: Box::new(config),
+        }
+    }
+
+    #[test]
+    fn skill_zero_loses_to_full_strength() {
+        let (mut strong, mut weak) = (player(MAX_SKILL), player(0));
+        let start = GameState::new(Board::default(), Clocks::default());
+        let as_white = play_game(&mut strong, &mut weak, &start, None).unwrap();
+        let as_black = play_game(&mut weak, &mut strong, &start, None).unwrap();
+        assert_eq!(as_white.0, "1-0", "{:?}", as_white);
+        assert_eq!(as_black.0, "0-1", "{:?}", as_black);
+    }
+}
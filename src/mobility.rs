@@ -0,0 +1,39 @@
+use crate::attacks;
+use chess::{get_pawn_attacks, BitBoard, Board, Color, Piece, EMPTY};
+
+/// For each piece that is scored: the number of squares it is expected to
+/// reach in an ordinary position, and centipawns per square above or below
+/// that.
+const MOBILITY: [(Piece, i64, i64); 4] = [
+    (Piece::Knight, 4, 4),
+    (Piece::Bishop, 6, 4),
+    (Piece::Rook, 7, 2),
+    (Piece::Queen, 14, 1),
+];
+
+/// Squares attacked by `colour`'s pawns.
+fn pawn_attacks(board: &Board, colour: Color) -> BitBoard {
+    let mut attacked = EMPTY;
+    for sq in board.pieces(Piece::Pawn) & board.color_combined(colour) {
+        attacked |= get_pawn_attacks(sq, colour, !EMPTY);
+    }
+    attacked
+}
+
+/// Mobility of `colour`'s knights, bishops, rooks and queens: the squares
+/// each attacks that are neither taken by its own side nor covered by an
+/// enemy pawn, where it would usually just be lost.
+pub fn mobility(board: &Board, colour: Color) -> i64 {
+    let usable = !board.color_combined(colour) & !pawn_attacks(board, !colour);
+    MOBILITY
+        .iter()
+        .map(|&(piece, expected, weight)| {
+            (board.pieces(piece) & board.color_combined(colour))
+                .map(|sq| {
+                    let reach = attacks::piece_attacks(board, piece, colour, sq) & usable;
+                    (reach.popcnt() as i64 - expected) * weight
+                })
+                .sum::<i64>()
+        })
+        .sum()
+}
@@ -0,0 +1,867 @@
+use crate::display::format_line;
+use crate::endgame::EndgameSource;
+use crate::eval_params::EvalParams;
+use crate::game_state::GameState;
+use crate::input::CommandInput;
+use crate::search::{
+    find_best_move, is_mate_score, score_to_cp, search, search_json, SearchConfig, SearchStats,
+    FIRST_MOVE_DEPTH, FIRST_MOVE_TT_ENTRIES, MATE_SCORE,
+};
+use crate::time_manager::Instant;
+use crate::{
+    benchmarks, chess960, elo, epd, fen, json, load_board, load_game, ordering, perft, pgn,
+    piece_values, tuning, MAX_DEPTH,
+};
+use chess::{Board, ChessMove};
+use std::io::Write;
+use std::str::FromStr;
+
+/// Texel-tunes the piece values and square tables on the positions in
+/// `path`, starting from the current evaluation, then writes them out as
+/// Rust constants or a parameter file.
+pub fn run_tune(path: &str, eval: &EvalParams, iterations: u32, out: Option<&str>) {
+    let (samples, skipped) = match tuning::load_samples(path, eval) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            println!("Cannot read {}: {}", path, e);
+            return;
+        }
+    };
+    if samples.is_empty() {
+        println!("No usable positions in {}", path);
+        return;
+    }
+    println!("Loaded {} positions, skipped {}", samples.len(), skipped);
+    let mut params = tuning::Params::from_eval(eval);
+    let k = tuning::fit_k(&samples, &params);
+    println!(
+        "K: {:.3}, error {:.6}",
+        k,
+        tuning::error(&samples, &params, k)
+    );
+    tuning::tune(&samples, &mut params, k, iterations);
+    let written = match out {
+        Some(out) if out.ends_with(".toml") => {
+            std::fs::File::create(out).and_then(|mut file| params.write_params(&mut file))
+        }
+        Some(out) => std::fs::File::create(out).and_then(|mut file| params.write_rust(&mut file)),
+        None => params.write_rust(&mut std::io::stdout()),
+    };
+    match (written, out) {
+        (Ok(()), Some(out)) => println!("Wrote tuned constants to {}", out),
+        (Ok(()), None) => {}
+        (Err(e), _) => println!("Cannot write tuned constants: {}", e),
+    }
+}
+
+/// Searches `start` for a mate in at most `moves` moves by the side to
+/// move. Everything that could overlook a mate is turned off: null moves,
+/// reductions, futility pruning and the endgame rules, whose won scores
+/// aren't mates.
+pub fn run_mate_search(start: &GameState, moves: u32, config: &SearchConfig, json: bool) {
+    let board = start.board();
+    let plies = (2 * moves).saturating_sub(1).min(MAX_DEPTH as u32) as i8;
+    let config = SearchConfig {
+        null_move: false,
+        late_move_reductions: false,
+        futility_pruning: false,
+        delta_pruning: false,
+        endgame_chain: vec![EndgameSource::Search],
+        halfmove_clock: start.clocks().halfmove,
+        game_history: start.earlier_hashes(),
+        castling: start.castling(),
+        ..config.clone()
+    };
+    let result = search(&board, plies, &config);
+    let cp = score_to_cp(&board, result.score);
+    let found = (MATE_SCORE - cp + 1) / 2;
+    if is_mate_score(cp) && cp > 0 && found <= moves as i64 {
+        // The PV stops short where the table cut the search off, so the
+        // line is rebuilt one move at a time, each side playing its best
+        // in the plies left.
+        let mut line = Vec::new();
+        let (mut position, mut castling) = (board, start.castling());
+        for left in (1..2 * found as i8).rev() {
+            let config = SearchConfig {
+                castling,
+                ..config.clone()
+            };
+            let Some(mv) = search(&position, left, &config).best_move else {
+                break;
+            };
+            line.push(mv);
+            let before = position;
+            castling = castling.play(&before, mv, &mut position);
+        }
+        if json {
+            println!(
+                "{}",
+                json::Object::new().raw("mate", found).strs("pv", &line)
+            );
+            return;
+        }
+        let line = format_line(&board, start.clocks().fullmove, &line, true);
+        println!("Mate in {}: {}", found, line);
+    } else if json {
+        println!(
+            "{}",
+            json::Object::new().raw("mate", "null").raw("moves", moves)
+        );
+    } else {
+        println!("No mate in {}", moves);
+    }
+}
+
+/// Prints the perft count below each move of `game`'s position, then the
+/// total.
+pub fn run_perft(game: &GameState, depth: u32, json: bool) {
+    let start = Instant::now();
+    let split = perft::divide(&game.board(), game.castling(), depth);
+    for (mv, nodes) in &split {
+        if json {
+            println!(
+                "{}",
+                json::Object::new().str("move", mv).raw("nodes", nodes)
+            );
+        } else {
+            println!("{}: {}", mv, nodes);
+        }
+    }
+    let total: u64 = if depth == 0 {
+        1
+    } else {
+        split.iter().map(|&(_, nodes)| nodes).sum()
+    };
+    let ms = start.elapsed().as_millis();
+    if json {
+        let summary = json::Object::new()
+            .raw("depth", depth)
+            .raw("nodes", total)
+            .raw("time_ms", ms);
+        println!("{}", summary);
+        return;
+    }
+    println!();
+    println!("Nodes: {}", total);
+    println!("Time: {} ms", ms);
+}
+
+/// Checks move generation on the standard perft positions, to `depth` or
+/// as deep as each has a published count. Returns the number of counts
+/// that came out wrong.
+pub fn run_perft_suite(depth: u32) -> usize {
+    let mut failures = 0;
+    for (name, fen, counts) in perft::SUITE {
+        let Ok(board) = Board::from_str(fen) else {
+            println!("{}: bad FEN", name);
+            failures += 1;
+            continue;
+        };
+        for (d, &expected) in (1..=depth).zip(counts.iter()) {
+            let nodes = perft::perft(&board, chess960::Castling::default(), d);
+            let verdict = if nodes == expected {
+                "ok"
+            } else {
+                failures += 1;
+                "FAIL"
+            };
+            println!("{}\t{}\t{}\t{}\t{}", name, d, nodes, expected, verdict);
+        }
+    }
+    failures
+}
+
+/// Searches each test position within `depth` and the configured limits,
+/// and checks the move against its answers: one of the `bm` moves,
+/// and none of the `am` moves. Prints a row per position and the total
+/// solved. Answers that aren't legal SAN in their position count as
+/// unsolved.
+pub fn run_testsuite(suite: &[epd::TestPosition], depth: i8, config: &SearchConfig, json: bool) {
+    if !json {
+        println!("id\tresult\tmove\texpected\ttime\tnodes");
+    }
+    let (mut solved, mut total_ms) = (0, 0);
+    for position in suite {
+        let Ok(board) = Board::from_str(&position.fen) else {
+            if json {
+                let row = json::Object::new().str("id", &position.id);
+                println!("{}", row.str("error", "bad FEN"));
+            } else {
+                println!("{}\tbad FEN", position.id);
+            }
+            continue;
+        };
+        let started = Instant::now();
+        let result = search(&board, depth, config);
+        let ms = started.elapsed().as_millis();
+        total_ms += ms;
+        let answers = |moves: &[String]| -> Option<Vec<ChessMove>> {
+            moves
+                .iter()
+                .map(|m| pgn::parse_san(&board, chess960::Castling::default(), m))
+                .collect()
+        };
+        let ok = match (
+            answers(&position.best),
+            answers(&position.avoid),
+            result.best_move,
+        ) {
+            (Some(best), Some(avoid), Some(mv)) => {
+                (best.is_empty() || best.contains(&mv)) && !avoid.contains(&mv)
+            }
+            _ => false,
+        };
+        if ok {
+            solved += 1;
+        }
+        let expected = match (position.best.is_empty(), position.avoid.is_empty()) {
+            (false, true) => format!("bm {}", position.best.join(" ")),
+            (true, false) => format!("am {}", position.avoid.join(" ")),
+            _ => format!(
+                "bm {} am {}",
+                position.best.join(" "),
+                position.avoid.join(" ")
+            ),
+        };
+        let played = result
+            .best_move
+            .map_or("-".to_string(), |mv| pgn::san(&board, mv));
+        if json {
+            let row = search_json(&board, &result, ms)
+                .str("id", &position.id)
+                .raw("solved", ok)
+                .str("san", &played)
+                .strs("bm", &position.best)
+                .strs("am", &position.avoid);
+            println!("{}", row);
+            continue;
+        }
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            position.id,
+            if ok { "ok" } else { "FAIL" },
+            played,
+            expected,
+            ms,
+            result.nodes
+        );
+    }
+    if json {
+        let summary = json::Object::new()
+            .raw("solved", solved)
+            .raw("positions", suite.len())
+            .raw("time_ms", total_ms);
+        println!("{}", summary);
+    } else {
+        println!("Solved {} of {} in {} ms", solved, suite.len(), total_ms);
+    }
+}
+
+/// How `--bench` writes its rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BenchOutput {
+    /// Tab-separated, for people.
+    Text,
+    Csv,
+    /// One JSON object per row.
+    Json,
+}
+
+impl BenchOutput {
+    pub fn from_name(name: &str) -> Option<BenchOutput> {
+        match name.trim().to_lowercase().as_str() {
+            "text" => Some(BenchOutput::Text),
+            "csv" => Some(BenchOutput::Csv),
+            "json" => Some(BenchOutput::Json),
+            _ => None,
+        }
+    }
+}
+
+/// One benchmark search: a case searched to one depth.
+pub struct BenchRow {
+    pub(crate) name: String,
+    pub(crate) depth: i8,
+    pub(crate) time_ms: u128,
+    pub(crate) nodes: i64,
+}
+
+/// Nodes per second of `nodes` searched in `time_ms`.
+pub(crate) fn nps(nodes: i64, time_ms: u128) -> u128 {
+    (nodes as u128 * 1000).checked_div(time_ms).unwrap_or(0)
+}
+
+impl BenchRow {
+    pub(crate) fn print(&self, output: BenchOutput) {
+        let nps = nps(self.nodes, self.time_ms);
+        match output {
+            BenchOutput::Text => println!(
+                "{}\t{}\t{}\t{}\t{}",
+                self.name, self.depth, self.time_ms, self.nodes, nps
+            ),
+            BenchOutput::Csv => println!(
+                "{},{},{},{},{}",
+                self.name, self.depth, self.time_ms, self.nodes, nps
+            ),
+            BenchOutput::Json => {
+                let row = json::Object::new()
+                    .str("name", &self.name)
+                    .raw("depth", self.depth)
+                    .raw("time_ms", self.time_ms)
+                    .raw("nodes", self.nodes)
+                    .raw("nps", nps);
+                println!("{}", row);
+            }
+        }
+    }
+}
+
+/// Header of the CSV rows, which `--bench-save` files use too.
+const BENCH_CSV_HEADER: &str = "name,depth,time_ms,nodes,nps";
+
+/// Searches every case to each benchmark depth, timing each search on its
+/// own, and prints a row per search followed by the signature: the total
+/// node count. The searches are untimed and start from an empty table, so
+/// the signature only changes when the search itself does. They use the
+/// default settings but for `threads` and `parallel_root`, which change
+/// the wall-clock time, though Lazy SMP threads change the count as well.
+pub fn run_benchmark(
+    cases: &[(String, String)],
+    output: BenchOutput,
+    threads: usize,
+    parallel_root: bool,
+) -> Vec<BenchRow> {
+    let config = SearchConfig {
+        threads,
+        parallel_root,
+        ..SearchConfig::default()
+    };
+    match output {
+        BenchOutput::Text => println!("name\tdepth\tduration\tnodes\tnps"),
+        BenchOutput::Csv => println!("{}", BENCH_CSV_HEADER),
+        BenchOutput::Json => {}
+    }
+    let mut rows = Vec::new();
+    for (name, fen) in cases {
+        let Ok(board) = Board::from_str(fen) else {
+            continue;
+        };
+        for &depth in benchmarks::DEPTHS {
+            let start = Instant::now();
+            let nodes = search(&board, depth, &config).nodes;
+            let row = BenchRow {
+                name: name.clone(),
+                depth,
+                time_ms: start.elapsed().as_millis(),
+                nodes,
+            };
+            row.print(output);
+            rows.push(row);
+        }
+    }
+    let nodes: i64 = rows.iter().map(|row| row.nodes).sum();
+    let time_ms: u128 = rows.iter().map(|row| row.time_ms).sum();
+    match output {
+        BenchOutput::Text => println!(
+            "Bench: {} nodes {} ms {} nps",
+            nodes,
+            time_ms,
+            nps(nodes, time_ms)
+        ),
+        BenchOutput::Csv => println!("total,,{},{},{}", time_ms, nodes, nps(nodes, time_ms)),
+        BenchOutput::Json => {
+            let total = json::Object::new()
+                .raw("signature", nodes)
+                .raw("time_ms", time_ms)
+                .raw("nps", nps(nodes, time_ms));
+            println!("{}", total);
+        }
+    }
+    rows
+}
+
+/// Writes `rows` to `path` as CSV, for `--bench-baseline` to compare with.
+pub fn save_bench(path: &str, rows: &[BenchRow]) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    writeln!(file, "{}", BENCH_CSV_HEADER)?;
+    for row in rows {
+        let nps = nps(row.nodes, row.time_ms);
+        writeln!(
+            file,
+            "{},{},{},{},{}",
+            row.name, row.depth, row.time_ms, row.nodes, nps
+        )?;
+    }
+    Ok(())
+}
+
+/// Reads the rows of a CSV benchmark file, skipping its header and total.
+pub fn load_bench(path: &str) -> Result<Vec<BenchRow>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    text.lines()
+        .filter(|line| *line != BENCH_CSV_HEADER && !line.starts_with("total,"))
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            let bad = || format!("bad benchmark row '{}'", line);
+            if fields.len() < 4 {
+                return Err(bad());
+            }
+            Ok(BenchRow {
+                name: fields[0].to_string(),
+                depth: fields[1].parse().map_err(|_| bad())?,
+                time_ms: fields[2].parse().map_err(|_| bad())?,
+                nodes: fields[3].parse().map_err(|_| bad())?,
+            })
+        })
+        .collect()
+}
+
+/// Compares a benchmark run with a saved baseline: every search must have
+/// visited the same number of nodes, so the search behaves as before, and
+/// the total time shows whether it got faster. Prints each search that
+/// differs, then the verdict. Returns whether the node counts all match.
+pub fn compare_bench(rows: &[BenchRow], baseline: &[BenchRow]) -> bool {
+    let mut matches = rows.len() == baseline.len();
+    for row in rows {
+        let old = baseline
+            .iter()
+            .find(|old| old.name == row.name && old.depth == row.depth);
+        match old {
+            Some(old) if old.nodes == row.nodes => {}
+            Some(old) => {
+                println!(
+                    "{} depth {}: {} nodes, baseline {}",
+                    row.name, row.depth, row.nodes, old.nodes
+                );
+                matches = false;
+            }
+            None => {
+                println!("{} depth {}: not in baseline", row.name, row.depth);
+                matches = false;
+            }
+        }
+    }
+    let total = |rows: &[BenchRow]| -> (i64, u128) {
+        (
+            rows.iter().map(|row| row.nodes).sum(),
+            rows.iter().map(|row| row.time_ms).sum(),
+        )
+    };
+    let ((nodes, ms), (old_nodes, old_ms)) = (total(rows), total(baseline));
+    if matches {
+        println!("Signature {} matches the baseline", nodes);
+    } else {
+        println!(
+            "Signature {} differs from the baseline {}",
+            nodes, old_nodes
+        );
+    }
+    match (nps(nodes, ms) as f64, nps(old_nodes, old_ms) as f64) {
+        (now, before) if before > 0.0 => println!(
+            "Speed: {:.0} nps, baseline {:.0} ({:+.1}%)",
+            now,
+            before,
+            (now / before - 1.0) * 100.0
+        ),
+        _ => {}
+    }
+    matches
+}
+
+/// Checks every benchmark case: the FEN must load as a legal position with
+/// one king per side, and a depth 1 search must find a legal move in it.
+/// Returns the number of bad cases.
+pub fn run_validate_benchmarks() -> usize {
+    let mut bad = 0;
+    for (name, fen) in benchmarks::CASES {
+        let problem = match load_board(fen) {
+            Err(e) => Some(e),
+            Ok(board) => match find_best_move(&board, 1) {
+                Some(mv) if board.legal(mv) => None,
+                Some(mv) => Some(format!("illegal move {}", mv)),
+                None => Some("no move found".to_string()),
+            },
+        };
+        match problem {
+            Some(e) => {
+                println!("{}\tbad\t{}", name, e);
+                bad += 1;
+            }
+            None => println!("{}\tok", name),
+        }
+    }
+    bad
+}
+
+/// Round-trips `fen` and every benchmark position through `Board` and
+/// reports those that come back different. Returns the number of
+/// mismatches.
+pub fn run_fen_roundtrip_test(fen: &str) -> usize {
+    let mut failures = 0;
+    let cases = std::iter::once(("fen", fen)).chain(benchmarks::CASES.iter().copied());
+    for (name, fen) in cases {
+        match fen::roundtrip(fen) {
+            Ok(()) => println!("{}\tok", name),
+            Err(written) => {
+                println!("{}\tmismatch\t{}\t{}", name, fen::canonical(fen), written);
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+/// Searches every benchmark position twice to `depth` and reports any whose
+/// node count or best move differ between the runs. The searches are
+/// untimed, so both runs must do exactly the same work. Returns the number
+/// of positions that differed.
+pub fn run_reproducibility_check(depth: i8, config: &SearchConfig) -> usize {
+    let config = SearchConfig {
+        movetime: None,
+        ..config.clone()
+    };
+    let mut failures = 0;
+    for (name, fen) in benchmarks::CASES {
+        if let Ok(board) = Board::from_str(fen) {
+            let first = search(&board, depth, &config);
+            let second = search(&board, depth, &config);
+            if first.nodes == second.nodes && first.best_move == second.best_move {
+                println!("{}\tok\t{} nodes", name, first.nodes);
+            } else {
+                println!(
+                    "{}\tdiffers\t{} nodes {:?} vs {} nodes {:?}",
+                    name,
+                    first.nodes,
+                    first.best_move.map(|mv| mv.to_string()),
+                    second.nodes,
+                    second.best_move.map(|mv| mv.to_string())
+                );
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+/// Prints each piece's value and its piece-square tables as grids from
+/// White's side with rank 8 at the top, so they read like the board rather
+/// than like the source tables, which start at a1. The endgame table is
+/// only shown where it differs. Black uses the same tables mirrored.
+pub fn run_dump_eval_tables() {
+    for (idx, piece) in piece_values::PIECES.iter().enumerate() {
+        println!("{:?} (value {})", piece, piece_values::PIECE_VALS[idx]);
+        let middlegame = &piece_values::PIECE_SQUARES[idx];
+        let endgame = &piece_values::ENDGAME_PIECE_SQUARES[idx];
+        if middlegame == endgame {
+            print_square_table(middlegame);
+        } else {
+            println!("Middlegame");
+            print_square_table(middlegame);
+            println!("Endgame");
+            print_square_table(endgame);
+        }
+    }
+}
+
+fn print_square_table(table: &[i64; 64]) {
+    for line in square_table_rows(table) {
+        println!("{}", line);
+    }
+    println!("     a   b   c   d   e   f   g   h");
+    println!();
+}
+
+/// The ranks of `table` as `print_square_table` shows them, rank 8 first.
+fn square_table_rows(table: &[i64; 64]) -> Vec<String> {
+    (0..8)
+        .rev()
+        .map(|rank| {
+            let row: Vec<String> = (0..8)
+                .map(|file| format!("{:4}", table[rank * 8 + file]))
+                .collect();
+            format!("{} {}", rank + 1, row.join(""))
+        })
+        .collect()
+}
+
+/// Nodes searching `board` to `depth` takes under each move ordering
+/// level, in `ALL_ORDERINGS` order.
+fn nodes_by_ordering(board: &Board, depth: i8, config: &SearchConfig) -> Vec<i64> {
+    ordering::ALL_ORDERINGS
+        .iter()
+        .map(|&level| {
+            let config = SearchConfig {
+                ordering: level,
+                ..config.clone()
+            };
+            search(board, depth, &config).nodes
+        })
+        .collect()
+}
+
+/// Searches every benchmark position under each move ordering level in
+/// turn and prints the node counts side by side, then their totals and
+/// how much each level changed the total from the one before.
+pub fn run_compare_move_order(depth: i8, config: &SearchConfig) {
+    let names: Vec<&str> = ordering::ALL_ORDERINGS.iter().map(|o| o.name()).collect();
+    println!("name\tdepth\t{}", names.join("\t"));
+    let mut totals = [0; ordering::ALL_ORDERINGS.len()];
+    for (name, fen) in benchmarks::CASES {
+        if let Ok(board) = Board::from_str(fen) {
+            let nodes = nodes_by_ordering(&board, depth, config);
+            for (total, n) in totals.iter_mut().zip(&nodes) {
+                *total += n;
+            }
+            println!("{}\t{}\t{}", name, depth, tab_separated(&nodes));
+        }
+    }
+    println!("total\t{}\t{}", depth, tab_separated(&totals));
+    let changes: Vec<String> = totals
+        .windows(2)
+        .map(|pair| match pair[0] {
+            0 => "-".to_string(),
+            before => format!("{:+.1}%", (pair[1] - before) as f64 * 100.0 / before as f64),
+        })
+        .collect();
+    println!("change\t{}\t-\t{}", depth, changes.join("\t"));
+}
+
+fn tab_separated(values: &[i64]) -> String {
+    let texts: Vec<String> = values.iter().map(i64::to_string).collect();
+    texts.join("\t")
+}
+
+/// Reports how often the first move tried produces the beta cutoff, across
+/// the benchmark positions. Well ordered searches stay above ~85%.
+pub fn run_cutoff_stats(depth: i8, config: &SearchConfig) {
+    println!(
+        "name\tdepth\tcutoffs\tfirst_move_rate\tresearches\tpvs_fallbacks\t\
+         aspiration_researches\ttt_hit_rate\ttt_cutoff_rate\tnull_cutoffs\treductions\t\
+         reduction_researches"
+    );
+    let mut overall = SearchStats::default();
+    for (name, fen) in benchmarks::CASES {
+        if let Ok(board) = Board::from_str(fen) {
+            let result = search(&board, depth, config);
+            println!(
+                "{}\t{}\t{}\t{:.3}\t{}\t{}\t{}\t{:.3}\t{:.3}\t{}\t{}\t{}",
+                name,
+                depth,
+                result.stats.cutoffs,
+                result.stats.first_move_cutoff_rate(),
+                result.stats.researches,
+                result.stats.pvs_fallbacks,
+                result.stats.aspiration_researches,
+                result.stats.tt_hit_rate(),
+                result.stats.tt_cutoff_rate(),
+                result.stats.null_cutoffs,
+                result.stats.reductions,
+                result.stats.reduction_researches
+            );
+            overall.add(&result.stats);
+        }
+    }
+    println!(
+        "total\t{}\t{}\t{:.3}\t{}\t{}\t{}\t{:.3}\t{:.3}\t{}\t{}\t{}",
+        depth,
+        overall.cutoffs,
+        overall.first_move_cutoff_rate(),
+        overall.researches,
+        overall.pvs_fallbacks,
+        overall.aspiration_researches,
+        overall.tt_hit_rate(),
+        overall.tt_cutoff_rate(),
+        overall.null_cutoffs,
+        overall.reductions,
+        overall.reduction_researches
+    );
+}
+
+/// Prints a best move for every FEN read from stdin, until EOF. With
+/// `--first-move-only` each search is a shallow one without extensions and
+/// with a small hash table, for throughput over accuracy.
+pub fn run_board_from_stdin(
+    depth: i8,
+    config: &SearchConfig,
+    chess960: bool,
+    first_move_only: bool,
+) {
+    let (depth, config) = if first_move_only {
+        first_move_settings(config)
+    } else {
+        (depth, config.clone())
+    };
+    let mut input = CommandInput::stdin();
+    while let Some(line) = input.next_line() {
+        let fen = line.trim();
+        if !fen.is_empty() {
+            println!("{}", fen_reply(fen, depth, &config, chess960));
+        }
+    }
+}
+
+/// Depth and settings of a `--first-move-only` search.
+fn first_move_settings(config: &SearchConfig) -> (i8, SearchConfig) {
+    let config = SearchConfig {
+        tt_entries: FIRST_MOVE_TT_ENTRIES,
+        extensions: false,
+        ..config.clone()
+    };
+    (FIRST_MOVE_DEPTH, config)
+}
+
+/// What `--board-from-stdin` prints for `fen`: its best move, or why the
+/// FEN can't be read.
+fn fen_reply(fen: &str, depth: i8, config: &SearchConfig, chess960: bool) -> String {
+    let game = match load_game(fen, chess960) {
+        Ok(game) => game,
+        Err(e) => return format!("Bad FEN: {}", e),
+    };
+    let config = SearchConfig {
+        halfmove_clock: game.clocks().halfmove,
+        castling: game.castling(),
+        ..config.clone()
+    };
+    match search(&game.board(), depth, &config).best_move {
+        Some(mv) => format!("bestmove {}", mv),
+        None => "bestmove (none)".to_string(),
+    }
+}
+
+/// Prints the Elo estimate for a "W,L,D" match result.
+pub fn run_elo(result: &str) {
+    let counts: Vec<u32> = result
+        .split(',')
+        .filter_map(|n| n.trim().parse().ok())
+        .collect();
+    match counts.as_slice() {
+        &[w, l, d] => match elo::estimate(w, l, d) {
+            Some(estimate) => println!("Elo: {}", estimate),
+            None => println!("No games played"),
+        },
+        _ => println!("Expected a match result as W,L,D"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_DEPTH;
+    use chess::{Color, Piece};
+
+    #[test]
+    fn one_bestmove_per_fen_in_order() {
+        let config = SearchConfig::default();
+        let fens = [
+            "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+            "r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1",
+        ];
+        let replies: Vec<String> = fens
+            .iter()
+            .map(|fen| fen_reply(fen, 3, &config, false))
+            .collect();
+        assert_eq!(replies, ["bestmove a1a8", "bestmove a8a1"]);
+        assert!(fen_reply("8/8/8/8/8/8/8/8 w - - 0 1", 3, &config, false).starts_with("Bad FEN"));
+    }
+
+    #[test]
+    fn first_move_only_is_much_cheaper_and_still_legal() {
+        let (depth, fast) = first_move_settings(&SearchConfig::default());
+        let (mut fast_nodes, mut full_nodes) = (0, 0);
+        for &(_, fen, _) in perft::SUITE {
+            let board = Board::from_str(fen).unwrap();
+            let result = search(&board, depth, &fast);
+            assert!(board.legal(result.best_move.unwrap()), "{}", fen);
+            fast_nodes += result.nodes;
+            full_nodes += search(&board, DEFAULT_DEPTH, &SearchConfig::default()).nodes;
+        }
+        assert!(
+            fast_nodes * 10 < full_nodes,
+            "{} vs {}",
+            fast_nodes,
+            full_nodes
+        );
+    }
+
+    #[test]
+    fn every_benchmark_case_is_valid() {
+        for (name, fen) in benchmarks::CASES {
+            let board = load_board(fen).unwrap();
+            for colour in [Color::White, Color::Black] {
+                let kings = board.pieces(Piece::King) & board.color_combined(colour);
+                assert_eq!(kings.popcnt(), 1, "{}", name);
+            }
+        }
+        assert_eq!(run_validate_benchmarks(), 0);
+    }
+
+    #[test]
+    fn searches_are_reproducible() {
+        assert_eq!(run_reproducibility_check(5, &SearchConfig::default()), 0);
+    }
+
+    #[test]
+    fn pawn_table_dump_is_symmetric() {
+        let pawn = piece_values::PIECES
+            .iter()
+            .position(|&p| p == Piece::Pawn)
+            .unwrap();
+        let rows = square_table_rows(&piece_values::PIECE_SQUARES[pawn]);
+        let values: Vec<Vec<i64>> = rows
+            .iter()
+            .map(|row| {
+                row.split_whitespace()
+                    .skip(1)
+                    .map(|v| v.parse().unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(values.iter().map(Vec::len).sum::<usize>(), 64);
+        for row in &values {
+            let mirrored: Vec<i64> = row.iter().rev().copied().collect();
+            assert_eq!(row, &mirrored);
+        }
+        // Pawns never stand on the first or last rank.
+        assert!(values[0].iter().chain(&values[7]).all(|&v| v == 0));
+    }
+
+    #[test]
+    fn epd_benchmark_covers_both_positions_at_every_depth() {
+        let path = std::env::temp_dir().join(format!("bench-epd-{}", std::process::id()));
+        let epd = "8/8/4k3/8/8/4K3/4P3/8 w - - id \"kpk\";\n\
+                   8/8/4k3/8/8/4K3/8/7Q b - -\n";
+        std::fs::write(&path, epd).unwrap();
+        let cases = epd::load_positions(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let rows = run_benchmark(&cases, BenchOutput::Text, 1, false);
+        let depths = benchmarks::DEPTHS;
+        assert_eq!(rows.len(), 2 * depths.len());
+        for (row, (name, depth)) in rows.iter().zip(
+            ["line1", "line2"]
+                .iter()
+                .flat_map(|name| depths.iter().map(move |depth| (name, depth))),
+        ) {
+            assert_eq!((row.name.as_str(), row.depth), (*name, *depth));
+        }
+    }
+
+    #[test]
+    fn more_ordering_means_fewer_nodes() {
+        for (name, fen) in benchmarks::CASES {
+            let board = Board::from_str(fen).unwrap();
+            let nodes = nodes_by_ordering(&board, 5, &SearchConfig::default());
+            // Each level may give a little back, but none by much, and all
+            // of them together cut the tree to under a third.
+            for pair in nodes.windows(2) {
+                assert!(pair[1] * 10 <= pair[0] * 13, "{}: {:?}", name, nodes);
+            }
+            assert!(
+                nodes[nodes.len() - 1] * 3 < nodes[0],
+                "{}: {:?}",
+                name,
+                nodes
+            );
+        }
+    }
+}
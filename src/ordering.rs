@@ -0,0 +1,268 @@
+use crate::piece_values::{piece_value, PAWN};
+use crate::see;
+use chess::{Board, ChessMove, Color, Piece, Square};
+
+/// Move ordering heuristics, each level including the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MoveOrdering {
+    /// Moves in generator order.
+    None,
+    /// Captures first, most valuable victim then least valuable attacker.
+    MvvLva,
+    /// Captures that lose material by static exchange moved down, below
+    /// the killers.
+    See,
+    /// Then the quiet moves that caused a cutoff at the same ply.
+    Killers,
+    /// Then quiet moves by how often they caused cutoffs anywhere.
+    History,
+    /// The quiet move that last refuted the opponent's move, just below
+    /// the killers.
+    CounterMove,
+    /// Quiet moves also by how well they did after the same move one and
+    /// two plies before.
+    Continuation,
+    /// The transposition table's best move before everything else.
+    HashMove,
+}
+
+pub const ALL_ORDERINGS: [MoveOrdering; 8] = [
+    MoveOrdering::None,
+    MoveOrdering::MvvLva,
+    MoveOrdering::See,
+    MoveOrdering::Killers,
+    MoveOrdering::History,
+    MoveOrdering::CounterMove,
+    MoveOrdering::Continuation,
+    MoveOrdering::HashMove,
+];
+
+impl MoveOrdering {
+    pub fn name(self) -> &'static str {
+        match self {
+            MoveOrdering::None => "none",
+            MoveOrdering::MvvLva => "mvv-lva",
+            MoveOrdering::See => "see",
+            MoveOrdering::Killers => "killers",
+            MoveOrdering::History => "history",
+            MoveOrdering::CounterMove => "countermove",
+            MoveOrdering::Continuation => "continuation",
+            MoveOrdering::HashMove => "hash",
+        }
+    }
+}
+
+const HASH_MOVE_KEY: i64 = 1 << 40;
+const CAPTURE_KEY: i64 = 1 << 30;
+const KILLER_KEY: i64 = 1 << 20;
+/// Below both killers.
+const COUNTER_MOVE_KEY: i64 = KILLER_KEY - 2;
+/// History scores stop growing here, so they stay below the killers. The
+/// same goes for a history and its continuations added together.
+const HISTORY_MAX: i64 = KILLER_KEY / 2;
+/// Losing captures come after the killers but before any history move,
+/// the least bad first.
+const LOSING_CAPTURE_KEY: i64 = HISTORY_MAX + (1 << 16);
+
+/// Each kind of piece on each square, which is what the countermove and
+/// continuation tables tell moves apart by. Colours needn't be, since the
+/// side to move tells whose move each one was.
+const PIECE_SQUARES: usize = 6 * 64;
+
+/// A move played on the way to a node, as the countermove and continuation
+/// tables see it: which piece went to which square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Played(usize);
+
+impl Played {
+    /// `mv` as played on `board`.
+    pub fn of(board: &Board, mv: ChessMove) -> Played {
+        let piece = board.piece_on(mv.get_source()).unwrap_or(Piece::Pawn);
+        Played(piece_square(piece, mv.get_dest()))
+    }
+}
+
+fn piece_square(piece: Piece, square: Square) -> usize {
+    piece.to_index() * 64 + square.to_index()
+}
+
+/// Killer moves by ply and cutoff history by side, source and destination,
+/// learned during one search. The countermove table holds the quiet move
+/// that last refuted each previous move, and the continuation tables score
+/// a quiet move's piece and square one and two plies after each previous
+/// one.
+pub struct OrderingTables {
+    killers: Vec<[Option<ChessMove>; 2]>,
+    history: Vec<i64>,
+    counter_moves: Vec<Option<ChessMove>>,
+    continuation: [Vec<i64>; 2],
+}
+
+fn history_index(colour: Color, mv: ChessMove) -> usize {
+    (colour.to_index() * 64 + mv.get_source().to_index()) * 64 + mv.get_dest().to_index()
+}
+
+/// The piece `mv` takes on its destination square. A Chess960 castle,
+/// written as the king taking its own rook, takes nothing.
+pub fn captured_piece(board: &Board, mv: ChessMove) -> Option<Piece> {
+    let dest = mv.get_dest();
+    board
+        .piece_on(dest)
+        .filter(|_| board.color_on(dest) != Some(board.side_to_move()))
+}
+
+/// Neither a capture nor a promotion.
+pub fn is_quiet_move(board: &Board, mv: ChessMove) -> bool {
+    captured_piece(board, mv).is_none() && mv.get_promotion().is_none() && !is_en_passant(board, mv)
+}
+
+fn is_en_passant(board: &Board, mv: ChessMove) -> bool {
+    board.piece_on(mv.get_source()) == Some(Piece::Pawn)
+        && mv.get_source().get_file() != mv.get_dest().get_file()
+        && board.piece_on(mv.get_dest()).is_none()
+}
+
+impl Default for OrderingTables {
+    fn default() -> OrderingTables {
+        OrderingTables::new()
+    }
+}
+
+impl OrderingTables {
+    pub fn new() -> OrderingTables {
+        OrderingTables {
+            killers: Vec::new(),
+            history: vec![0; 2 * 64 * 64],
+            counter_moves: vec![None; PIECE_SQUARES],
+            continuation: [
+                vec![0; PIECE_SQUARES * PIECE_SQUARES],
+                vec![0; PIECE_SQUARES * PIECE_SQUARES],
+            ],
+        }
+    }
+
+    /// Index of `mv` on `board` in the continuation table of `previous`.
+    fn continuation_index(board: &Board, mv: ChessMove, previous: Played) -> usize {
+        let piece = board.piece_on(mv.get_source()).unwrap_or(Piece::Pawn);
+        previous.0 * PIECE_SQUARES + piece_square(piece, mv.get_dest())
+    }
+
+    /// Remembers a quiet move that caused a beta cutoff `depth` plies from
+    /// the leaves and `ply` plies from the root, `previous` being the last
+    /// two moves before it, the latest first. The quiet moves `tried` before
+    /// it lose as much in the continuation tables as it gains, so a move
+    /// that keeps failing after the same reply drops back.
+    pub fn record_cutoff(
+        &mut self,
+        board: &Board,
+        mv: ChessMove,
+        ply: usize,
+        depth: i8,
+        previous: [Option<Played>; 2],
+        tried: &[ChessMove],
+    ) {
+        if !is_quiet_move(board, mv) {
+            return;
+        }
+        let bonus = depth as i64 * depth as i64;
+        if let Some(last) = previous[0] {
+            self.counter_moves[last.0] = Some(mv);
+        }
+        for (table, played) in self.continuation.iter_mut().zip(previous) {
+            if let Some(played) = played {
+                let entry = &mut table[Self::continuation_index(board, mv, played)];
+                *entry = std::cmp::min(*entry + bonus, HISTORY_MAX);
+                for &other in tried {
+                    if is_quiet_move(board, other) {
+                        let entry = &mut table[Self::continuation_index(board, other, played)];
+                        *entry = std::cmp::max(*entry - bonus, -HISTORY_MAX);
+                    }
+                }
+            }
+        }
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; 2]);
+        }
+        let slots = &mut self.killers[ply];
+        if slots[0] != Some(mv) {
+            slots[1] = slots[0];
+            slots[0] = Some(mv);
+        }
+        let entry = &mut self.history[history_index(board.side_to_move(), mv)];
+        *entry = std::cmp::min(*entry + bonus, HISTORY_MAX);
+    }
+
+    fn key(
+        &self,
+        board: &Board,
+        mv: ChessMove,
+        level: MoveOrdering,
+        ply: usize,
+        hash_move: Option<ChessMove>,
+        previous: [Option<Played>; 2],
+    ) -> i64 {
+        if level >= MoveOrdering::HashMove && hash_move == Some(mv) {
+            return HASH_MOVE_KEY;
+        }
+        if level >= MoveOrdering::MvvLva && !is_quiet_move(board, mv) {
+            let victim = if is_en_passant(board, mv) {
+                PAWN
+            } else {
+                captured_piece(board, mv).map_or(0, piece_value)
+            };
+            let promotion = mv.get_promotion().map_or(0, piece_value);
+            let attacker = board.piece_on(mv.get_source()).map_or(0, piece_value);
+            if level >= MoveOrdering::See && promotion == 0 {
+                let gain = see::see(board, mv);
+                if gain < 0 {
+                    return LOSING_CAPTURE_KEY + gain;
+                }
+            }
+            return CAPTURE_KEY + 16 * (victim + promotion) - attacker;
+        }
+        if level >= MoveOrdering::Killers {
+            if let Some(slot) = self
+                .killers
+                .get(ply)
+                .and_then(|slots| slots.iter().position(|&k| k == Some(mv)))
+            {
+                return KILLER_KEY - slot as i64;
+            }
+        }
+        if level >= MoveOrdering::CounterMove
+            && previous[0].is_some_and(|last| self.counter_moves[last.0] == Some(mv))
+        {
+            return COUNTER_MOVE_KEY;
+        }
+        let mut score = 0;
+        if level >= MoveOrdering::History {
+            score += self.history[history_index(board.side_to_move(), mv)];
+        }
+        if level >= MoveOrdering::Continuation {
+            for (table, played) in self.continuation.iter().zip(previous) {
+                if let Some(played) = played {
+                    score += table[Self::continuation_index(board, mv, played)];
+                }
+            }
+        }
+        std::cmp::min(score, HISTORY_MAX)
+    }
+
+    /// Sorts `moves` best first under `level`, `previous` being the last
+    /// two moves played, the latest first. The sort is stable, so moves the
+    /// heuristics can't tell apart keep generator order.
+    pub fn order(
+        &self,
+        board: &Board,
+        moves: &mut [ChessMove],
+        level: MoveOrdering,
+        ply: usize,
+        hash_move: Option<ChessMove>,
+        previous: [Option<Played>; 2],
+    ) {
+        if level == MoveOrdering::None {
+            return;
+        }
+        moves.sort_by_cached_key(|&mv| -self.key(board, mv, level, ply, hash_move, previous));
+    }
+}
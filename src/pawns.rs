@@ -0,0 +1,171 @@
+use crate::piece_values::PAWN;
+use chess::{
+    get_adjacent_files, get_file, get_rank, BitBoard, Board, Color, Piece, Rank, Square, ALL_FILES,
+    EMPTY,
+};
+use std::cell::RefCell;
+
+/// Bonus for each passed pawn by how many ranks it has advanced from its
+/// own back rank.
+const PASSED_PAWN_BONUS: [i64; 8] = [
+    0,
+    PAWN / 20,
+    PAWN / 20,
+    PAWN / 10,
+    PAWN / 5,
+    PAWN * 35 / 100,
+    PAWN * 55 / 100,
+    0,
+];
+
+/// Penalty for each pawn on a file beyond the first.
+const DOUBLED_PAWN_PENALTY: i64 = PAWN / 5;
+/// Penalty for each pawn with no friendly pawn on either neighbouring file.
+const ISOLATED_PAWN_PENALTY: i64 = PAWN / 10;
+
+/// Entries in the pawn structure cache; a power of two so the hash can be
+/// masked.
+const PAWN_CACHE_ENTRIES: usize = 1 << 14;
+
+/// A cached structure score and the exact pawns it belongs to.
+#[derive(Clone, Copy)]
+struct PawnEntry {
+    white: BitBoard,
+    black: BitBoard,
+    score: i64,
+}
+
+thread_local! {
+    /// Structure scores by pawn placement. Pawns move far less often than
+    /// pieces, so most lookups in a search hit.
+    static PAWN_CACHE: RefCell<Vec<Option<PawnEntry>>> =
+        RefCell::new(vec![None; PAWN_CACHE_ENTRIES]);
+}
+
+/// Bonus for each passed pawn with a passed neighbour, by how many ranks it
+/// has advanced from its own back rank. A pair on the 6th rank is close to
+/// unstoppable, so the bonus climbs steeply.
+const CONNECTED_PASSER_BONUS: [i64; 8] = [
+    0,
+    0,
+    PAWN / 20,
+    PAWN / 10,
+    PAWN / 5,
+    PAWN * 35 / 100,
+    PAWN * 60 / 100,
+    0,
+];
+
+/// Ranks counted from `colour`'s side of the board, 0 being its back rank.
+fn relative_rank(sq: Square, colour: Color) -> usize {
+    match colour {
+        Color::White => sq.get_rank().to_index(),
+        Color::Black => 7 - sq.get_rank().to_index(),
+    }
+}
+
+/// Squares on `sq`'s file and the files next to it that lie ahead of it
+/// for `colour`.
+fn front_span(sq: Square, colour: Color) -> BitBoard {
+    let files = get_file(sq.get_file()) | get_adjacent_files(sq.get_file());
+    let rank = relative_rank(sq, colour);
+    (rank + 1..8)
+        .map(|ahead| match colour {
+            Color::White => get_rank(Rank::from_index(ahead)),
+            Color::Black => get_rank(Rank::from_index(7 - ahead)),
+        })
+        .fold(EMPTY, |span, rank| span | rank)
+        & files
+}
+
+/// `colour`'s pawns with no enemy pawn ahead of them on their own file or
+/// the files either side, which can't be stopped by pawns alone.
+pub fn passed_pawns(board: &Board, colour: Color) -> BitBoard {
+    let pawns = board.pieces(Piece::Pawn);
+    let enemy_pawns = pawns & board.color_combined(!colour);
+    let mut passed = EMPTY;
+    for sq in pawns & board.color_combined(colour) {
+        if front_span(sq, colour) & enemy_pawns == EMPTY {
+            passed |= BitBoard::from_square(sq);
+        }
+    }
+    passed
+}
+
+/// Bonus for `colour`'s passed pawns that stand beside or diagonally behind
+/// another passed pawn on a neighbouring file, where they can defend each
+/// other forward.
+fn connected_passers(board: &Board, colour: Color) -> i64 {
+    let passed = passed_pawns(board, colour);
+    passed
+        .filter(|&sq| {
+            let rank = sq.get_rank().to_index();
+            let neighbours = (rank.saturating_sub(1)..=std::cmp::min(rank + 1, 7))
+                .fold(EMPTY, |mask, r| mask | get_rank(Rank::from_index(r)))
+                & get_adjacent_files(sq.get_file());
+            passed & neighbours != EMPTY
+        })
+        .map(|sq| CONNECTED_PASSER_BONUS[relative_rank(sq, colour)])
+        .sum()
+}
+
+/// `colour`'s pawn structure: passed pawns by rank, less doubled and
+/// isolated pawns.
+fn structure(board: &Board, colour: Color) -> i64 {
+    let own = board.pieces(Piece::Pawn) & board.color_combined(colour);
+    let passed: i64 = passed_pawns(board, colour)
+        .map(|sq| PASSED_PAWN_BONUS[relative_rank(sq, colour)])
+        .sum();
+    let mut weaknesses = 0;
+    for &file in ALL_FILES.iter() {
+        let on_file = (own & get_file(file)).popcnt() as i64;
+        if on_file == 0 {
+            continue;
+        }
+        weaknesses += DOUBLED_PAWN_PENALTY * (on_file - 1);
+        if own & get_adjacent_files(file) == EMPTY {
+            weaknesses += ISOLATED_PAWN_PENALTY * on_file;
+        }
+    }
+    passed + connected_passers(board, colour) - weaknesses
+}
+
+/// Everything the pawns alone say about the position, in centipawns and
+/// positive when White's structure is better. Cached by pawn placement.
+pub fn evaluate(board: &Board) -> i64 {
+    let pawns = board.pieces(Piece::Pawn);
+    let white = pawns & board.color_combined(Color::White);
+    let black = pawns & board.color_combined(Color::Black);
+    let hash = (white.0 ^ black.0.rotate_left(29)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let idx = (hash >> 50) as usize & (PAWN_CACHE_ENTRIES - 1);
+    PAWN_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        match cache[idx] {
+            Some(entry) if entry.white == white && entry.black == black => entry.score,
+            _ => {
+                let score = structure(board, Color::White) - structure(board, Color::Black);
+                cache[idx] = Some(PawnEntry {
+                    white,
+                    black,
+                    score,
+                });
+                score
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn connected_passers_outscore_separated_ones() {
+        let connected = Board::from_str("4k3/8/3PP3/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let separated = Board::from_str("4k3/8/1P4P1/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(connected_passers(&connected, Color::White) > 0);
+        assert_eq!(connected_passers(&separated, Color::White), 0);
+        assert!(evaluate(&connected) > evaluate(&separated));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::chess960::Castling;
+use chess::{Board, ChessMove, MoveGen};
+
+/// The standard perft positions with their published leaf counts, from
+/// depth 1 on.
+pub const SUITE: &[(&str, &str, &[u64])] = &[
+    (
+        "start",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8902, 197281, 4865609, 119060324],
+    ),
+    (
+        "kiwipete",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        &[48, 2039, 97862, 4085603, 193690690],
+    ),
+    (
+        "position3",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        &[14, 191, 2812, 43238, 674624, 11030083],
+    ),
+    (
+        "position4",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        &[6, 264, 9467, 422333, 15833292],
+    ),
+    (
+        "position5",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        &[44, 1486, 62379, 2103487, 89941194],
+    ),
+    (
+        "position6",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        &[46, 2079, 89890, 3894594, 164075551],
+    ),
+];
+
+/// Counts the leaf nodes `depth` plies below `board`, with Chess960 rights
+/// `castling`, to check move generation against published totals.
+pub fn perft(board: &Board, castling: Castling, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = MoveGen::new_legal(board);
+    let castles = castling.moves(board);
+    if depth == 1 {
+        return (moves.len() + castles.len()) as u64;
+    }
+    moves
+        .chain(castles)
+        .map(|mv| {
+            let mut after = Board::default();
+            let left = castling.play(board, mv, &mut after);
+            perft(&after, left, depth - 1)
+        })
+        .sum()
+}
+
+/// The perft count below each legal move of `board`, so a wrong total can
+/// be traced to the move it comes from.
+pub fn divide(board: &Board, castling: Castling, depth: u32) -> Vec<(ChessMove, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    castling
+        .legal_moves(board)
+        .into_iter()
+        .map(|mv| {
+            let mut after = Board::default();
+            let left = castling.play(board, mv, &mut after);
+            (mv, perft(&after, left, depth - 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// Checks the suite position `name` against its published count at
+    /// `depth`.
+    fn check(name: &str, depth: u32) {
+        let &(_, fen, counts) = SUITE.iter().find(|&&(n, _, _)| n == name).unwrap();
+        let board = Board::from_str(fen).unwrap();
+        assert_eq!(
+            perft(&board, Castling::default(), depth),
+            counts[depth as usize - 1],
+            "{} at depth {}",
+            name,
+            depth
+        );
+    }
+
+    #[test]
+    fn start() {
+        check("start", 4);
+    }
+
+    #[test]
+    fn kiwipete() {
+        check("kiwipete", 3);
+    }
+
+    #[test]
+    fn position3() {
+        check("position3", 4);
+    }
+
+    #[test]
+    fn position4() {
+        check("position4", 3);
+    }
+
+    #[test]
+    fn position5() {
+        check("position5", 3);
+    }
+
+    #[test]
+    fn position6() {
+        check("position6", 3);
+    }
+
+    #[test]
+    fn divide_adds_up_to_perft() {
+        let board = Board::from_str(SUITE[1].1).unwrap();
+        let castling = Castling::default();
+        let total: u64 = divide(&board, castling, 3).iter().map(|&(_, c)| c).sum();
+        assert_eq!(total, perft(&board, castling, 3));
+    }
+
+    /// Chess960 positions with their published counts, castling the king
+    /// onto its own rook, from depth 1 on.
+    #[test]
+    fn chess960() {
+        let positions: [(&str, &[u64]); 3] = [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                &[21, 528, 12189, 326672],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                &[21, 807, 18002, 667366],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                &[20, 479, 10471, 273318],
+            ),
+        ];
+        for (fen, counts) in positions {
+            let (plain, castling) = crate::chess960::parse_fen(fen).unwrap();
+            let board = Board::from_str(&plain).unwrap();
+            for (depth, &count) in (1..).zip(counts) {
+                assert_eq!(
+                    perft(&board, castling, depth),
+                    count,
+                    "{} at {}",
+                    fen,
+                    depth
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use crate::chess960::{self, Castling};
+use crate::game_state::{Clocks, GameState};
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece, Square};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+
+/// Longest movetext line written, as the PGN standard asks.
+const LINE_WIDTH: usize = 79;
+
+/// Writes `mv`, a legal move on `board`, in standard algebraic notation.
+pub fn san(board: &Board, mv: ChessMove) -> String {
+    let source = mv.get_source();
+    let dest = mv.get_dest();
+    let piece = board.piece_on(source).unwrap_or(Piece::Pawn);
+    let capture = board.piece_on(dest).is_some()
+        || piece == Piece::Pawn && source.get_file() != dest.get_file();
+
+    let castle = match chess960::castle(board, mv) {
+        Some(castle) => Some(castle.side.san()),
+        None => castle_side(source, dest).filter(|_| piece == Piece::King),
+    };
+    let mut text = match castle {
+        Some(castle) => castle.to_string(),
+        None => {
+            let mut text = String::new();
+            if piece != Piece::Pawn {
+                text.push_str(&piece.to_string(Color::White));
+                text.push_str(&disambiguation(board, mv, piece));
+            } else if capture {
+                text.push(file_char(source));
+            }
+            if capture {
+                text.push('x');
+            }
+            text.push_str(&dest.to_string());
+            if let Some(promotion) = mv.get_promotion() {
+                text.push('=');
+                text.push_str(&promotion.to_string(Color::White));
+            }
+            text
+        }
+    };
+
+    let after = chess960::make_move_new(board, mv);
+    match after.status() {
+        BoardStatus::Checkmate => text.push('#'),
+        _ if *after.checkers() != chess::EMPTY => text.push('+'),
+        _ => {}
+    }
+    text
+}
+
+/// The legal move on `board` written as `text` in SAN, Chess960 castles
+/// under `castling` included. Check and annotation marks are optional, and
+/// castling may use zeros.
+pub fn parse_san(board: &Board, castling: Castling, text: &str) -> Option<ChessMove> {
+    let plain = |text: &str| {
+        text.trim_end_matches(['+', '#', '!', '?'])
+            .replace('0', "O")
+    };
+    let wanted = plain(text);
+    castling
+        .legal_moves(board)
+        .into_iter()
+        .find(|&mv| plain(&san(board, mv)) == wanted)
+}
+
+/// `O-O` or `O-O-O` if a king going from `source` to `dest` castles.
+fn castle_side(source: Square, dest: Square) -> Option<&'static str> {
+    match dest.get_file().to_index() as i64 - source.get_file().to_index() as i64 {
+        2 => Some("O-O"),
+        -2 => Some("O-O-O"),
+        _ => None,
+    }
+}
+
+fn file_char(square: Square) -> char {
+    (b'a' + square.get_file().to_index() as u8) as char
+}
+
+/// The file, rank or both of the source square, when another `piece` of
+/// the same side could also move to the destination.
+fn disambiguation(board: &Board, mv: ChessMove, piece: Piece) -> String {
+    let source = mv.get_source();
+    let rivals: Vec<Square> = MoveGen::new_legal(board)
+        .filter(|other| {
+            other.get_dest() == mv.get_dest()
+                && other.get_source() != source
+                && board.piece_on(other.get_source()) == Some(piece)
+        })
+        .map(|other| other.get_source())
+        .collect();
+    if rivals.is_empty() {
+        String::new()
+    } else if rivals.iter().all(|sq| sq.get_file() != source.get_file()) {
+        file_char(source).to_string()
+    } else if rivals.iter().all(|sq| sq.get_rank() != source.get_rank()) {
+        (source.get_rank().to_index() + 1).to_string()
+    } else {
+        source.to_string()
+    }
+}
+
+/// Whether `token` ends a game's movetext.
+fn is_result(token: &str) -> bool {
+    matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+/// Replays the first game in `text`, from its `FEN` tag if it has one.
+/// Comments, variations and annotation glyphs are skipped.
+pub fn read_game(text: &str) -> Result<GameState, String> {
+    let mut start = None;
+    let mut movetext = String::new();
+    for line in text.lines().map(str::trim) {
+        if let Some(tag) = line.strip_prefix('[') {
+            if !movetext.trim().is_empty() {
+                break;
+            }
+            if let Some(fen) = tag.strip_prefix("FEN \"") {
+                start = Some(fen.trim_end_matches(['"', ']']).to_string());
+            }
+        } else if !line.starts_with('%') {
+            // A `;` comment runs to the end of its line.
+            movetext.push_str(line.split(';').next().unwrap_or(""));
+            movetext.push(' ');
+        }
+    }
+
+    let mut game = match &start {
+        Some(fen) => crate::load_game(fen, chess960::names_rook_files(fen))
+            .map_err(|e| format!("bad FEN tag: {}", e))?,
+        None => GameState::new(Board::default(), Clocks::default()),
+    };
+    // Comments and variations nest no deeper than variations inside
+    // variations, so a depth count is enough to skip both.
+    let mut skipped = String::new();
+    let mut depth = 0;
+    let mut in_comment = false;
+    for c in movetext.chars() {
+        match c {
+            '{' => in_comment = true,
+            '}' => in_comment = false,
+            '(' if !in_comment => depth += 1,
+            ')' if !in_comment => depth -= 1,
+            _ if in_comment || depth > 0 => {}
+            _ => skipped.push(c),
+        }
+    }
+    for token in skipped.split_whitespace() {
+        if is_result(token) {
+            break;
+        }
+        // Move numbers may be glued to the move, as in `1.e4` or `3...Nf6`.
+        let token = token.rsplit('.').next().unwrap_or(token);
+        if token.is_empty() || token.starts_with('$') {
+            continue;
+        }
+        let board = game.board();
+        match parse_san(&board, game.castling(), token) {
+            Some(mv) => game.make_move(mv),
+            None => {
+                return Err(format!(
+                    "illegal move {} after {} plies",
+                    token,
+                    game.plies_played()
+                ))
+            }
+        }
+    }
+    Ok(game)
+}
+
+/// Reads the PGN file at `path` and replays its first game.
+pub fn load_game(path: &str) -> Result<GameState, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    read_game(&text)
+}
+
+/// Number of games already in the PGN file at `path`, counted by their
+/// `Event` tags. A missing file holds none.
+pub fn count_games(path: &str) -> io::Result<usize> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text.lines().filter(|l| l.starts_with("[Event ")).count()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes `game` as one PGN game: the seven tag roster, `SetUp` and `FEN`
+/// when it didn't start from the initial position, then the movetext.
+pub fn write_game(
+    game: &GameState,
+    event: &str,
+    round: usize,
+    players: (&str, &str),
+    result: &str,
+) -> String {
+    write_annotated_game(game, event, round, players, result, &[])
+}
+
+/// `write_game` with `annotations[i]` written after the `i`th move, e.g.
+/// a `$4` glyph and a `{comment}`. Moves past the end of
+/// `annotations` go without.
+pub fn write_annotated_game(
+    game: &GameState,
+    event: &str,
+    round: usize,
+    players: (&str, &str),
+    result: &str,
+    annotations: &[String],
+) -> String {
+    let (start, clocks) = game.start();
+    let mut out = String::new();
+    for (tag, value) in [
+        ("Event", event),
+        ("Site", "?"),
+        ("Date", "????.??.??"),
+        ("Round", &round.to_string()),
+        ("White", players.0),
+        ("Black", players.1),
+        ("Result", result),
+    ] {
+        out.push_str(&format!("[{} \"{}\"]\n", tag, value));
+    }
+    if start != Board::default() || clocks.fullmove != Clocks::default().fullmove {
+        out.push_str("[SetUp \"1\"]\n");
+        let fen = game.start_fen();
+        if chess960::names_rook_files(&fen) {
+            out.push_str("[Variant \"Chess960\"]\n");
+        }
+        out.push_str(&format!("[FEN \"{}\"]\n", fen));
+    }
+    out.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut board = start;
+    let mut fullmove = clocks.fullmove;
+    for (idx, &mv) in game.moves().iter().enumerate() {
+        match board.side_to_move() {
+            Color::White => tokens.push(format!("{}.", fullmove)),
+            Color::Black if idx == 0 => tokens.push(format!("{}...", fullmove)),
+            Color::Black => {}
+        }
+        tokens.push(san(&board, mv));
+        if let Some(annotation) = annotations.get(idx) {
+            tokens.extend(annotation.split_whitespace().map(str::to_string));
+        }
+        if board.side_to_move() == Color::Black {
+            fullmove += 1;
+        }
+        board = chess960::make_move_new(&board, mv);
+    }
+    tokens.push(result.to_string());
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    out.push_str(&line);
+    out.push_str("\n\n");
+    out
+}
+
+/// Appends `game` to the PGN file at `path`, numbered as the round after
+/// the games already there. Returns that round.
+pub fn append_game(
+    path: &str,
+    game: &GameState,
+    event: &str,
+    players: (&str, &str),
+    result: &str,
+) -> io::Result<usize> {
+    let round = count_games(path)? + 1;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(write_game(game, event, round, players, result).as_bytes())?;
+    Ok(round)
+}
+
+/// Writes `game` alone to the PGN file at `path`, replacing what was there.
+pub fn save_game(
+    path: &str,
+    game: &GameState,
+    event: &str,
+    players: (&str, &str),
+    result: &str,
+) -> io::Result<()> {
+    fs::write(path, write_game(game, event, 1, players, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn play(board: Board, clocks: Clocks, moves: &str) -> GameState {
+        let mut game = GameState::new(board, clocks);
+        for mv in moves.split_whitespace() {
+            game.make_move(ChessMove::from_str(mv).unwrap());
+        }
+        game
+    }
+
+    #[test]
+    fn scripted_game_round_trips_through_a_file() {
+        let game = play(
+            Board::default(),
+            Clocks::default(),
+            "e2e4 e7e5 d1h5 b8c6 f1c4 g8f6 h5f7",
+        );
+        let path = std::env::temp_dir().join(format!("pgn-round-trip-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        save_game(path, &game, "Test", ("White", "Black"), "1-0").unwrap();
+        let text = fs::read_to_string(path).unwrap();
+        let loaded = load_game(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(
+            text.contains("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0"),
+            "{}",
+            text
+        );
+        assert_eq!(loaded.moves(), game.moves());
+        assert_eq!(loaded.board(), game.board());
+    }
+
+    #[test]
+    fn black_to_move_start_numbers_from_its_fen() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let game = play(
+            Board::from_str(fen).unwrap(),
+            Clocks::from_fen(fen),
+            "e7e5 g1f3",
+        );
+        let text = write_game(&game, "Test", 1, ("White", "Black"), "*");
+        assert!(text.contains("[FEN \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq"));
+        assert!(text.contains("1... e5 2. Nf3 *"), "{}", text);
+        assert_eq!(read_game(&text).unwrap().moves(), game.moves());
+    }
+}
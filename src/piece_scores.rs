@@ -0,0 +1,158 @@
+use crate::chess960;
+use crate::eval_params::EvalParams;
+use crate::piece_values::{self, PIECES};
+use chess::{Board, ChessMove, Color, File, Piece, Square};
+
+/// Material and piece-square totals of a position, positive for White.
+/// The search keeps them up to date move by move instead of rescanning
+/// every piece at each leaf; only the game phase blending the two tables
+/// is worked out from the board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PieceScores {
+    material: i64,
+    middlegame: i64,
+    endgame: i64,
+}
+
+impl PieceScores {
+    /// The totals of `board`, counted from scratch.
+    pub fn of(board: &Board, params: &EvalParams) -> PieceScores {
+        let mut scores = PieceScores::default();
+        for &piece in &PIECES {
+            for square in *board.pieces(piece) {
+                if let Some(colour) = board.color_on(square) {
+                    scores.add(piece, square, colour, params, 1);
+                }
+            }
+        }
+        scores
+    }
+
+    /// Adds (`sign` 1) or takes away (`sign` -1) `colour`'s `piece` on
+    /// `square`.
+    fn add(&mut self, piece: Piece, square: Square, colour: Color, params: &EvalParams, sign: i64) {
+        let pc_idx = PIECES.iter().position(|&p| p == piece).unwrap_or(0);
+        // The tables are laid out for White; flip the rank for Black.
+        let (sq_idx, sign) = match colour {
+            Color::White => (square.to_index(), sign),
+            Color::Black => (square.to_index() ^ 56, -sign),
+        };
+        if params.material {
+            self.material += sign * params.piece_values[pc_idx];
+        }
+        self.middlegame += sign * params.piece_squares[pc_idx][sq_idx];
+        self.endgame += sign * params.endgame_piece_squares[pc_idx][sq_idx];
+    }
+
+    /// The totals once `mv` is played on `board`: captures, en passant,
+    /// promotions and the rook's half of castling included, Chess960 castles
+    /// too.
+    pub fn after_move(&self, board: &Board, mv: ChessMove, params: &EvalParams) -> PieceScores {
+        let mut scores = *self;
+        let (from, to) = (mv.get_source(), mv.get_dest());
+        let (Some(piece), us) = (board.piece_on(from), board.side_to_move()) else {
+            return scores;
+        };
+        let castle = match piece {
+            Piece::King => chess960::castle(board, mv),
+            _ => None,
+        };
+        if let Some(castle) = castle {
+            scores.add(Piece::King, castle.king.0, us, params, -1);
+            scores.add(Piece::Rook, castle.rook.0, us, params, -1);
+            scores.add(Piece::King, castle.king.1, us, params, 1);
+            scores.add(Piece::Rook, castle.rook.1, us, params, 1);
+            return scores;
+        }
+        if let Some(captured) = board.piece_on(to) {
+            scores.add(captured, to, !us, params, -1);
+        } else if piece == Piece::Pawn && from.get_file() != to.get_file() {
+            let passed = Square::make_square(from.get_rank(), to.get_file());
+            scores.add(Piece::Pawn, passed, !us, params, -1);
+        }
+        scores.add(piece, from, us, params, -1);
+        scores.add(mv.get_promotion().unwrap_or(piece), to, us, params, 1);
+        let files_crossed = from
+            .get_file()
+            .to_index()
+            .abs_diff(to.get_file().to_index());
+        if piece == Piece::King && files_crossed == 2 {
+            let (rook_from, rook_to) = if to.get_file() == File::G {
+                (File::H, File::F)
+            } else {
+                (File::A, File::D)
+            };
+            let rank = from.get_rank();
+            let rook = |file| Square::make_square(rank, file);
+            scores.add(Piece::Rook, rook(rook_from), us, params, -1);
+            scores.add(Piece::Rook, rook(rook_to), us, params, 1);
+        }
+        scores
+    }
+
+    /// Material plus the piece-square tables blended at `phase`.
+    pub fn value(&self, phase: i64) -> i64 {
+        self.material + self.piece_squares(phase)
+    }
+
+    pub fn material(&self) -> i64 {
+        self.material
+    }
+
+    /// The piece-square tables alone, blended at `phase`.
+    pub fn piece_squares(&self, phase: i64) -> i64 {
+        piece_values::tapered(self.middlegame, self.endgame, phase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// Plays `mv` on `fen` and checks the updated totals against a count
+    /// from scratch of the position it leads to.
+    fn check(fen: &str, mv: &str) {
+        let params = EvalParams::default();
+        let board = Board::from_str(fen).unwrap();
+        let mv = ChessMove::from_str(mv).unwrap();
+        assert!(board.legal(mv), "{} is not legal in {}", mv, fen);
+        let updated = PieceScores::of(&board, &params).after_move(&board, mv, &params);
+        let counted = PieceScores::of(&board.make_move_new(mv), &params);
+        assert_eq!(updated, counted, "{} in {}", mv, fen);
+    }
+
+    const CASTLING: &str = "r3k2r/pppq1ppp/2n2n2/2bpp3/4P3/2NP1N2/PPPBQPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn castling_king_side() {
+        check(CASTLING, "e1g1");
+        check(&CASTLING.replace(" w ", " b "), "e8g8");
+    }
+
+    #[test]
+    fn castling_queen_side() {
+        check(CASTLING, "e1c1");
+        check(&CASTLING.replace(" w ", " b "), "e8c8");
+    }
+
+    #[test]
+    fn en_passant() {
+        check("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6");
+        check("4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 1", "e4d3");
+    }
+
+    #[test]
+    fn under_promotion_captures() {
+        for piece in ["n", "b", "r"] {
+            check(
+                "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1",
+                &format!("a7b8{}", piece),
+            );
+            check(
+                "4k3/8/8/8/8/8/p7/1R2K3 b - - 0 1",
+                &format!("a2b1{}", piece),
+            );
+        }
+    }
+}
@@ -1,82 +1,107 @@
 use chess::Piece;
 
 pub const PIECES: [Piece; 6] = [
-    Piece::King, Piece::Queen, Piece::Bishop,
-    Piece::Rook, Piece::Knight, Piece::Pawn
+    Piece::King,
+    Piece::Queen,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Knight,
+    Piece::Pawn,
 ];
 
-pub const PIECE_SQUARES : [[i64; 64]; 6] = [
-    K_VALUES, Q_VALUES, B_VALUES,
-    R_VALUES, N_VALUES, P_VALUES
+/// Middlegame piece-square tables, in `PIECES` order.
+pub const PIECE_SQUARES: [[i64; 64]; 6] =
+    [K_VALUES, Q_VALUES, B_VALUES, R_VALUES, N_VALUES, P_VALUES];
+
+/// Endgame piece-square tables: the king heads for the centre and pawns
+/// are worth more the further they have run. The other pieces keep their
+/// middlegame tables.
+pub const ENDGAME_PIECE_SQUARES: [[i64; 64]; 6] = [
+    K_ENDGAME_VALUES,
+    Q_VALUES,
+    B_VALUES,
+    R_VALUES,
+    N_VALUES,
+    P_ENDGAME_VALUES,
 ];
 
+/// Value of one pawn. Every evaluation term is expressed relative to it, so
+/// scores are in true centipawns.
+pub const PAWN: i64 = 100;
+
 pub const PIECE_VALS: [i64; 6] = [
-    20_000, 900, 330,
-    500, 320, 100
+    200 * PAWN,
+    9 * PAWN,
+    33 * PAWN / 10,
+    5 * PAWN,
+    32 * PAWN / 10,
+    PAWN,
 ];
 
+/// Material value of `piece`, as listed in `PIECE_VALS`.
+pub fn piece_value(piece: Piece) -> i64 {
+    let idx = PIECES.iter().position(|&p| p == piece).unwrap();
+    PIECE_VALS[idx]
+}
+
+/// Contribution of each piece type to the game phase, in `PIECES` order.
+pub const PHASE_WEIGHTS: [i64; 6] = [0, 4, 1, 2, 1, 0];
+
+/// Phase of the starting position: every piece except kings and pawns.
+pub const MAX_PHASE: i64 = 24;
+
+/// Blend of a middlegame and an endgame value at `phase`, which runs from
+/// `MAX_PHASE` (all middlegame) down to 0 (all endgame).
+pub fn tapered(middlegame: i64, endgame: i64, phase: i64) -> i64 {
+    (middlegame * phase + endgame * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
 const P_VALUES: [i64; 64] = [
-      0,  0,  0,  0,  0,  0,  0,  0,
-      5, 10, 10,-20,-20, 10, 10,  5,
-      5, -5,-10,  0,  0,-10, -5,  5,
-      0,  0,  0, 20, 20,  0,  0,  0,
-      5,  5, 10, 25, 25, 10,  5,  5,
-     10, 10, 20, 30, 30, 20, 10, 10,
-     50, 50, 50, 50, 50, 50, 50, 50,
-      0,  0,  0,  0,  0,  0,  0,  0,
+    0, 0, 0, 0, 0, 0, 0, 0, 5, 10, 10, -20, -20, 10, 10, 5, 5, -5, -10, 0, 0, -10, -5, 5, 0, 0, 0,
+    20, 20, 0, 0, 0, 5, 5, 10, 25, 25, 10, 5, 5, 10, 10, 20, 30, 30, 20, 10, 10, 50, 50, 50, 50,
+    50, 50, 50, 50, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+const P_ENDGAME_VALUES: [i64; 64] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 5, 5, 5, 5, 5, 5, 5, 10, 10, 10, 10, 10, 10,
+    10, 10, 20, 20, 20, 20, 20, 20, 20, 20, 30, 30, 30, 30, 30, 30, 30, 30, 50, 50, 50, 50, 50, 50,
+    50, 50, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
 const N_VALUES: [i64; 64] = [
-    -50,-40,-30,-30,-30,-30,-40,-50,
-    -40,-20,  0,  5,  5,  0,-20,-40,
-    -30,  5, 10, 15, 15, 10,  5,-30,
-    -30,  0, 15, 20, 20, 15,  0,-30,
-    -30,  0, 15, 20, 20, 15,  0,-30,
-    -30,  0, 10, 15, 15, 10,  0,-30,
-    -40,-20,  0,  0,  0,  0,-20,-40,
-    -50,-40,-30,-30,-30,-30,-40,-50,
+    -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 5, 5, 0, -20, -40, -30, 5, 10, 15, 15, 10,
+    5, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 0, 10, 15, 15, 10,
+    0, -30, -40, -20, 0, 0, 0, 0, -20, -40, -50, -40, -30, -30, -30, -30, -40, -50,
 ];
 
 const R_VALUES: [i64; 64] = [
-      0,  0,  0,  5,  5,  0,  0,  0,
-     -5,  0,  0,  0,  0,  0,  0, -5,
-     -5,  0,  0,  0,  0,  0,  0, -5,
-     -5,  0,  0,  0,  0,  0,  0, -5,
-     -5,  0,  0,  0,  0,  0,  0, -5,
-     -5,  0,  0,  0,  0,  0,  0, -5,
-      5, 10, 10, 10, 10, 10, 10,  5,
-      0,  0,  0,  0,  0,  0,  0,  0,
+    0, 0, 0, 5, 5, 0, 0, 0, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0,
+    0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, 5, 10, 10, 10, 10, 10, 10, 5, 0, 0,
+    0, 0, 0, 0, 0, 0,
 ];
 
 const B_VALUES: [i64; 64] = [
-    -20,-10,-10,-10,-10,-10,-10,-20,
-    -10,  5,  0,  0,  0,  0,  5,-10,
-    -10, 10, 10, 10, 10, 10, 10,-10,
-    -10,  0, 10, 10, 10, 10,  0,-10,
-    -10,  5,  5, 10, 10,  5,  5,-10,
-    -10,  0,  5, 10, 10,  5,  0,-10,
-    -10,  0,  0,  0,  0,  0,  0,-10,
-    -20,-10,-10,-10,-10,-10,-10,-20,
+    -20, -10, -10, -10, -10, -10, -10, -20, -10, 5, 0, 0, 0, 0, 5, -10, -10, 10, 10, 10, 10, 10,
+    10, -10, -10, 0, 10, 10, 10, 10, 0, -10, -10, 5, 5, 10, 10, 5, 5, -10, -10, 0, 5, 10, 10, 5, 0,
+    -10, -10, 0, 0, 0, 0, 0, 0, -10, -20, -10, -10, -10, -10, -10, -10, -20,
 ];
 
 const Q_VALUES: [i64; 64] = [
-    -20,-10,-10, -5, -5,-10,-10,-20,
-    -10,  0,  5,  0,  0,  0,  0,-10,
-    -10,  5,  5,  5,  5,  5,  0,-10,
-      0,  0,  5,  5,  5,  5,  0,  0,
-     -5,  0,  5,  5,  5,  5,  0, -5,
-    -10,  0,  5,  5,  5,  5,  0,-10,
-    -10,  0,  0,  0,  0,  0,  0,-10,
-    -20,-10,-10, -5, -5,-10,-10,-20,
+    -20, -10, -10, -5, -5, -10, -10, -20, -10, 0, 5, 0, 0, 0, 0, -10, -10, 5, 5, 5, 5, 5, 0, -10,
+    0, 0, 5, 5, 5, 5, 0, 0, -5, 0, 5, 5, 5, 5, 0, -5, -10, 0, 5, 5, 5, 5, 0, -10, -10, 0, 0, 0, 0,
+    0, 0, -10, -20, -10, -10, -5, -5, -10, -10, -20,
 ];
 
 const K_VALUES: [i64; 64] = [
-     20, 30, 10,  0,  0, 10, 30, 20,
-     20, 20,  0,  0,  0,  0, 20, 20,
-    -10,-20,-20,-20,-20,-20,-20,-10,
-    -20,-30,-30,-40,-40,-30,-30,-20,
-    -30,-40,-40,-50,-50,-40,-40,-30,
-    -30,-40,-40,-50,-50,-40,-40,-30,
-    -30,-40,-40,-50,-50,-40,-40,-30,
-    -30,-40,-40,-50,-50,-40,-40,-30,
-];
\ No newline at end of file
+    20, 30, 10, 0, 0, 10, 30, 20, 20, 20, 0, 0, 0, 0, 20, 20, -10, -20, -20, -20, -20, -20, -20,
+    -10, -20, -30, -30, -40, -40, -30, -30, -20, -30, -40, -40, -50, -50, -40, -40, -30, -30, -40,
+    -40, -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -30, -40, -40, -50, -50,
+    -40, -40, -30,
+];
+
+const K_ENDGAME_VALUES: [i64; 64] = [
+    -50, -30, -30, -30, -30, -30, -30, -50, -30, -30, 0, 0, 0, 0, -30, -30, -30, -10, 20, 30, 30,
+    20, -10, -30, -30, -10, 30, 40, 40, 30, -10, -30, -30, -10, 30, 40, 40, 30, -10, -30, -30, -10,
+    20, 30, 30, 20, -10, -30, -30, -20, -10, 0, 0, -10, -20, -30, -50, -40, -30, -20, -20, -30,
+    -40, -50,
+];
@@ -0,0 +1,384 @@
+use crate::polyglot_random::RANDOM64;
+use crate::rng::Rng;
+use crate::{json, pgn};
+use chess::{get_adjacent_files, get_rank, BitBoard, Board, CastleRights, ChessMove, Color};
+use chess::{File, Piece, Rank, Square, ALL_PIECES, EMPTY};
+use std::fs;
+use std::io;
+
+/// Bytes in one book entry: key, move, weight and learn value, big-endian.
+const ENTRY_SIZE: usize = 16;
+
+const CASTLING_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+/// The key Polyglot books index `board` by. It is not the board's own
+/// Zobrist hash: the format fixes its own table, and counts the en passant
+/// file only when a pawn can actually take.
+pub fn key(board: &Board) -> u64 {
+    let mut key = 0;
+    for &piece in &ALL_PIECES {
+        for square in *board.pieces(piece) {
+            let colour = board.color_on(square).unwrap_or(Color::White);
+            // Black pawn, white pawn, black knight, ... white king.
+            let kind = 2 * piece.to_index() + (colour == Color::White) as usize;
+            key ^= RANDOM64[64 * kind + square.to_index()];
+        }
+    }
+    let rights = [
+        (Color::White, CastleRights::KingSide),
+        (Color::White, CastleRights::QueenSide),
+        (Color::Black, CastleRights::KingSide),
+        (Color::Black, CastleRights::QueenSide),
+    ];
+    for (idx, (colour, side)) in rights.into_iter().enumerate() {
+        let held = board.castle_rights(colour);
+        if held == side || held == CastleRights::Both {
+            key ^= RANDOM64[CASTLING_OFFSET + idx];
+        }
+    }
+    if let Some(file) = en_passant_file(board) {
+        key ^= RANDOM64[EN_PASSANT_OFFSET + file.to_index()];
+    }
+    if board.side_to_move() == Color::White {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+    key
+}
+
+/// The file of the pawn that just moved two squares, if one of the side to
+/// move's pawns stands beside it.
+fn en_passant_file(board: &Board) -> Option<File> {
+    let passed = board.en_passant()?;
+    let ours = board.pieces(Piece::Pawn) & board.color_combined(board.side_to_move());
+    let beside = get_adjacent_files(passed.get_file()) & get_rank(passed.get_rank());
+    ((ours & beside) != EMPTY).then_some(passed.get_file())
+}
+
+/// How a move is chosen among a position's book moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// Always the heaviest, so every game opens the same way.
+    Best,
+    /// Drawn with odds by the weights, for variety.
+    Weighted,
+}
+
+impl Selection {
+    pub fn from_name(name: &str) -> Option<Selection> {
+        match name.trim().to_lowercase().as_str() {
+            "best" => Some(Selection::Best),
+            "weighted" => Some(Selection::Weighted),
+            _ => None,
+        }
+    }
+}
+
+/// One move a book gives for a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookMove {
+    pub mv: ChessMove,
+    pub weight: u16,
+    /// Left to the program that wrote the book; most leave it zero.
+    pub learn: u32,
+}
+
+struct Entry {
+    key: u64,
+    mv: u16,
+    weight: u16,
+    learn: u32,
+}
+
+/// A Polyglot `.bin` opening book: entries sorted by key, so a position's
+/// moves sit together and are found by binary search.
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    pub fn load(path: &str) -> io::Result<Book> {
+        Book::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Book> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            let msg = format!("{} bytes is not a whole number of entries", bytes.len());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        }
+        let mut entries: Vec<Entry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| Entry {
+                key: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes(chunk[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(chunk[10..12].try_into().unwrap()),
+                learn: u32::from_be_bytes(chunk[12..16].try_into().unwrap()),
+            })
+            .collect();
+        // Books are written sorted, but a stable sort costs little and
+        // keeps each position's moves in the file's order.
+        entries.sort_by_key(|entry| entry.key);
+        Ok(Book { entries })
+    }
+
+    /// The book's moves for `board`, heaviest first. Entries that aren't
+    /// legal here, from a key collision or a broken book, are left out.
+    pub fn moves(&self, board: &Board) -> Vec<BookMove> {
+        let key = key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let mut moves: Vec<BookMove> = self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                let mv = decode_move(board, entry.mv)?;
+                board.legal(mv).then_some(BookMove {
+                    mv,
+                    weight: entry.weight,
+                    learn: entry.learn,
+                })
+            })
+            .collect();
+        moves.sort_by_key(|book_move| std::cmp::Reverse(book_move.weight));
+        moves
+    }
+
+    /// A move for `board` chosen by `selection`, or `None` when the
+    /// position isn't in the book or all its weights are zero.
+    pub fn pick(&self, board: &Board, selection: Selection, rng: &mut Rng) -> Option<ChessMove> {
+        let moves = self.moves(board);
+        let total: u64 = moves.iter().map(|m| m.weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        if selection == Selection::Best {
+            return moves.first().map(|m| m.mv);
+        }
+        let mut draw = rng.below(total);
+        for book_move in moves {
+            if draw < book_move.weight as u64 {
+                return Some(book_move.mv);
+            }
+            draw -= book_move.weight as u64;
+        }
+        None
+    }
+}
+
+/// The explorer's table of `book`'s moves for `board`: each with its
+/// weight, its share of the position's total weight and its learn value.
+pub fn explorer_table(book: &Book, board: &Board) -> Vec<String> {
+    let moves = book.moves(board);
+    if moves.is_empty() {
+        return vec!["Position not in book".to_string()];
+    }
+    let total: u64 = moves.iter().map(|m| m.weight as u64).sum();
+    let mut lines = vec![
+        format!("{} book moves, total weight {}", moves.len(), total),
+        format!("  {:<8}{:>7}{:>8}{:>8}", "move", "weight", "share", "learn"),
+    ];
+    for m in moves {
+        lines.push(format!(
+            "  {:<8}{:>7}{:>7.1}%{:>8}",
+            pgn::san(board, m.mv),
+            m.weight,
+            100.0 * crate::search::rate(m.weight as i64, total as i64),
+            m.learn
+        ));
+    }
+    lines
+}
+
+/// Prints `book`'s moves for `board`, as the explorer's table or one JSON
+/// object a move.
+pub fn print_explorer(book: &Book, board: &Board, json: bool) {
+    if !json {
+        for line in explorer_table(book, board) {
+            println!("{}", line);
+        }
+        return;
+    }
+    for m in book.moves(board) {
+        let line = json::Object::new()
+            .str("move", m.mv)
+            .str("san", pgn::san(board, m.mv))
+            .raw("weight", m.weight)
+            .raw("learn", m.learn);
+        println!("{}", line);
+    }
+}
+
+/// The move packed in `raw`: to file and row in the low six bits, then
+/// from file and row, then the promotion. Castling is the king taking its
+/// own rook, e1h1 for e1g1.
+fn decode_move(board: &Board, raw: u16) -> Option<ChessMove> {
+    let square = |bits: u16| {
+        let (file, rank) = ((bits & 7) as usize, ((bits >> 3) & 7) as usize);
+        Square::make_square(Rank::from_index(rank), File::from_index(file))
+    };
+    let (from, mut to) = (square(raw >> 6), square(raw));
+    let promotion = match (raw >> 12) & 7 {
+        0 => None,
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => return None,
+    };
+    let us = board.color_combined(board.side_to_move());
+    let takes_own_rook = (us & BitBoard::from_square(to)) != EMPTY;
+    if board.piece_on(from) == Some(Piece::King) && takes_own_rook {
+        let file = if to.get_file() > from.get_file() {
+            File::G
+        } else {
+            File::C
+        };
+        to = Square::make_square(from.get_rank(), file);
+    }
+    Some(ChessMove::new(from, to, promotion))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// The keys the format's documentation publishes.
+    #[test]
+    fn reference_keys() {
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                0x463b96181691fc9c,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                0x823c9b50fd114196,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+                0x0756b94461c50fb0,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+                0x662fafb965db29d4,
+            ),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                0x22a48b5a8e47ff78,
+            ),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+                0x652a607ca3f242c1,
+            ),
+            (
+                "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+                0x00fdd303c946bdd9,
+            ),
+            (
+                "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+                0x3c8123ea7b067637,
+            ),
+            (
+                "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+                0x5c3f9b829b279560,
+            ),
+        ];
+        for (fen, expected) in cases {
+            let board = Board::from_str(fen).unwrap();
+            assert_eq!(key(&board), expected, "{}", fen);
+        }
+    }
+
+    /// A book entry for `fen` as it is stored on disk.
+    fn entry(fen: &str, mv: u16, weight: u16) -> Vec<u8> {
+        let board = Board::from_str(fen).unwrap();
+        let mut bytes = key(&board).to_be_bytes().to_vec();
+        bytes.extend(mv.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes
+    }
+
+    /// Packs a move the way the format does.
+    fn raw(from: Square, to: Square) -> u16 {
+        let bits = |sq: Square| (sq.get_rank().to_index() * 8 + sq.get_file().to_index()) as u16;
+        (bits(from) << 6) | bits(to)
+    }
+
+    #[test]
+    fn castling_is_stored_as_king_takes_rook() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let bytes = [
+            entry(fen, raw(Square::E1, Square::H1), 2),
+            entry(fen, raw(Square::E1, Square::A1), 1),
+        ]
+        .concat();
+        let book = Book::from_bytes(&bytes).unwrap();
+        let moves: Vec<ChessMove> = book
+            .moves(&Board::from_str(fen).unwrap())
+            .iter()
+            .map(|m| m.mv)
+            .collect();
+        let castles = [
+            ChessMove::new(Square::E1, Square::G1, None),
+            ChessMove::new(Square::E1, Square::C1, None),
+        ];
+        assert_eq!(moves, castles);
+    }
+
+    #[test]
+    fn picks_only_book_moves_and_misses_cleanly() {
+        let start = Board::default();
+        let fen = start.to_string();
+        let bytes = [
+            entry(&fen, raw(Square::E2, Square::E4), 3),
+            entry(&fen, raw(Square::D2, Square::D4), 1),
+        ]
+        .concat();
+        let book = Book::from_bytes(&bytes).unwrap();
+        let book_moves = [
+            ChessMove::new(Square::E2, Square::E4, None),
+            ChessMove::new(Square::D2, Square::D4, None),
+        ];
+        let mut rng = Rng::new(1);
+        for _ in 0..20 {
+            let mv = book.pick(&start, Selection::Weighted, &mut rng).unwrap();
+            assert!(book_moves.contains(&mv), "{}", mv);
+        }
+        let best = book.pick(&start, Selection::Best, &mut rng);
+        assert_eq!(best, Some(book_moves[0]));
+        let out_of_book = start.make_move_new(book_moves[0]);
+        assert_eq!(book.pick(&out_of_book, Selection::Weighted, &mut rng), None);
+    }
+
+    #[test]
+    fn explorer_lists_the_start_position() {
+        let fen = Board::default().to_string();
+        let after_e4 = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let bytes = [
+            entry(&fen, raw(Square::D2, Square::D4), 10),
+            entry(&fen, raw(Square::G1, Square::F3), 0),
+            entry(after_e4, raw(Square::E7, Square::E5), 7),
+            entry(&fen, raw(Square::E2, Square::E4), 30),
+        ]
+        .concat();
+        let book = Book::from_bytes(&bytes).unwrap();
+        let table = explorer_table(&book, &Board::default());
+        assert_eq!(
+            table,
+            [
+                "3 book moves, total weight 40",
+                "  move     weight   share   learn",
+                "  e4           30   75.0%       0",
+                "  d4           10   25.0%       0",
+                "  Nf3           0    0.0%       0",
+            ]
+        );
+        let empty = Book::from_bytes(&[]).unwrap();
+        assert_eq!(
+            explorer_table(&empty, &Board::default()),
+            ["Position not in book"]
+        );
+    }
+}
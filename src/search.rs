@@ -0,0 +1,2472 @@
+use crate::display::eval_text;
+use crate::endgame::EndgameSource;
+use crate::eval::{evaluate, favour};
+use crate::eval_params::EvalParams;
+use crate::game_state::GameState;
+use crate::ordering::MoveOrdering;
+use crate::piece_scores::PieceScores;
+use crate::time_manager::Instant;
+use crate::{
+    chess960, endgame, game_state, interrupt, json, ordering, piece_values, rng, see, time_manager,
+    trace, tt, MAX_DEPTH,
+};
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece, Square, EMPTY};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default half-width of the first aspiration window around the previous
+/// score, in centipawns.
+pub const DEFAULT_ASPIRATION_WINDOW: i64 = piece_values::PAWN / 2;
+pub const DEFAULT_ASPIRATION_RESEARCHES: u32 = 3;
+
+/// Search depth and hash table size used by `--first-move-only`.
+pub(crate) const FIRST_MOVE_DEPTH: i8 = 1;
+pub(crate) const FIRST_MOVE_TT_ENTRIES: usize = 1 << 10;
+
+/// Plies taken off the search after a null move, and the least depth a
+/// node needs for one to be tried.
+const NULL_MOVE_REDUCTION: i8 = 2;
+const NULL_MOVE_MIN_DEPTH: i8 = 3;
+
+/// Late move reductions: moves from this index on in the ordering, at
+/// nodes with at least this depth, are searched this many plies shallower
+/// unless they are captures, promotions, checks or check evasions.
+const LMR_FIRST_MOVE: usize = 3;
+const LMR_MIN_DEPTH: i8 = 3;
+const LMR_REDUCTION: i8 = 1;
+
+/// What a capture in quiescence may win beyond the piece taken, for delta
+/// pruning: enough for a positional swing, short of a piece.
+const DELTA_MARGIN: i64 = 2 * piece_values::PAWN;
+
+/// Most search threads `--threads` and the UCI `Threads` option allow.
+pub const MAX_THREADS: usize = 64;
+
+/// Most lines `--multipv` and the UCI `MultiPV` option allow.
+pub const MAX_MULTIPV: usize = 32;
+
+/// Score of a checkmate, well clear of any material balance.
+pub const MATE_SCORE: i64 = 200 * piece_values::PAWN;
+
+/// Plies within which a score this close to `MATE_SCORE` still reads as a
+/// forced mate.
+pub const MAX_MATE_PLY: i64 = 256;
+
+pub fn is_mate_score(score: i64) -> bool {
+    score.abs() >= MATE_SCORE - MAX_MATE_PLY
+}
+
+/// Moves a mate score toward 0 by the `ply` plies it takes to reach, so
+/// the search prefers the quicker of two mates and the slower of two
+/// losses.
+fn mate_distance(score: i64, ply: usize) -> i64 {
+    if is_mate_score(score) {
+        score - score.signum() * ply as i64
+    } else {
+        score
+    }
+}
+
+/// Mate scores depend on the path to the node, so the table stores them
+/// relative to the node itself and converts them back on the way out.
+fn score_to_tt(score: i64, ply: usize) -> i64 {
+    if is_mate_score(score) {
+        score + score.signum() * ply as i64
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i64, ply: usize) -> i64 {
+    mate_distance(score, ply)
+}
+
+/// Strongest `--skill` level, which plays at full strength.
+pub const MAX_SKILL: i64 = 20;
+/// Skill levels per extra ply of search depth below full strength.
+pub const SKILL_PER_PLY: i64 = 4;
+/// Root move noise, in centipawns, added for each level below full strength.
+pub const SKILL_NOISE_STEP: i64 = piece_values::PAWN / 10;
+/// For each level below full strength, the chance in percent of playing a
+/// weaker move on purpose, and how far below the best it may score.
+pub const SKILL_SUBOPTIMAL_STEP: u64 = 3;
+pub const SKILL_MARGIN_STEP: i64 = piece_values::PAWN / 20;
+
+/// Weakens the engine to `skill`: lower levels search shallower, add more
+/// noise to the root move scores, and now and then settle for a move a
+/// little worse than the best, so they miss more and pick second best
+/// moves more often. Level `MAX_SKILL` leaves play untouched.
+pub fn weaken_to_skill(skill: i64, depth: &mut i8, config: &mut SearchConfig) {
+    if skill >= MAX_SKILL {
+        return;
+    }
+    *depth = std::cmp::min(*depth, 1 + (skill / SKILL_PER_PLY) as i8);
+    config.eval_noise = std::cmp::max(config.eval_noise, (MAX_SKILL - skill) * SKILL_NOISE_STEP);
+    config.suboptimal_percent = (MAX_SKILL - skill) as u64 * SKILL_SUBOPTIMAL_STEP;
+    config.suboptimal_margin = (MAX_SKILL - skill) * SKILL_MARGIN_STEP;
+    if config.seed.is_none() {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+        config.seed = Some(now.map(|d| d.as_nanos() as u64).unwrap_or(0));
+    }
+}
+
+/// Counters gathered while searching, used to judge move ordering.
+#[derive(Default)]
+pub(crate) struct SearchStats {
+    pub(crate) nodes: i64,
+    pub(crate) cutoffs: i64,
+    first_move_cutoffs: i64,
+    /// Null-window searches that had to be repeated with the full window.
+    pub(crate) researches: i64,
+    /// Nodes that hit the re-search cap and finished as plain alpha-beta.
+    pub(crate) pvs_fallbacks: i64,
+    /// Root searches repeated because the score left the aspiration window.
+    pub(crate) aspiration_researches: i64,
+    /// Transposition table lookups, lookups that found the position, and
+    /// lookups whose stored score ended the node.
+    pub(crate) tt_probes: i64,
+    pub(crate) tt_hits: i64,
+    pub(crate) tt_cutoffs: i64,
+    /// Nodes cut off by a null-move search.
+    pub(crate) null_cutoffs: i64,
+    /// Late moves searched at reduced depth, and those that had to be
+    /// searched again at full depth.
+    pub(crate) reductions: i64,
+    pub(crate) reduction_researches: i64,
+}
+
+/// `part / whole`, or 0 when there is nothing to divide.
+pub(crate) fn rate(part: i64, whole: i64) -> f64 {
+    if whole == 0 {
+        return 0.0;
+    }
+    part as f64 / whole as f64
+}
+
+impl SearchStats {
+    pub(crate) fn record_cutoff(&mut self, move_idx: usize) {
+        self.cutoffs += 1;
+        if move_idx == 0 {
+            self.first_move_cutoffs += 1;
+        }
+    }
+
+    pub(crate) fn add(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.cutoffs += other.cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.researches += other.researches;
+        self.pvs_fallbacks += other.pvs_fallbacks;
+        self.aspiration_researches += other.aspiration_researches;
+        self.tt_probes += other.tt_probes;
+        self.tt_hits += other.tt_hits;
+        self.tt_cutoffs += other.tt_cutoffs;
+        self.null_cutoffs += other.null_cutoffs;
+        self.reductions += other.reductions;
+        self.reduction_researches += other.reduction_researches;
+    }
+
+    /// Fraction of beta cutoffs produced by the first move tried.
+    pub(crate) fn first_move_cutoff_rate(&self) -> f64 {
+        rate(self.first_move_cutoffs, self.cutoffs)
+    }
+
+    pub(crate) fn tt_hit_rate(&self) -> f64 {
+        rate(self.tt_hits, self.tt_probes)
+    }
+
+    pub(crate) fn tt_cutoff_rate(&self) -> f64 {
+        rate(self.tt_cutoffs, self.tt_probes)
+    }
+}
+
+/// Search settings that stay fixed for a whole search.
+#[derive(Clone)]
+pub struct SearchConfig {
+    pub endgame_chain: Vec<EndgameSource>,
+    /// Time allowed for the move in milliseconds, if the search is timed.
+    pub movetime: Option<u64>,
+    /// Nodes the search may visit before it stops with the best move of
+    /// the deepest iteration it finished.
+    pub max_nodes: Option<u64>,
+    /// Lag allowance subtracted from `movetime`, in milliseconds.
+    pub move_overhead: u64,
+    /// Extra milliseconds a timed search may take once the score falls
+    /// sharply between iterations.
+    pub panic_time: u64,
+    pub eval: EvalParams,
+    /// Replay the principal variation after each search and report the
+    /// first illegal move, if any.
+    pub pv_verify: bool,
+    /// File that every root move's score is appended to after each
+    /// iteration, or `-` for stderr.
+    pub search_log: Option<String>,
+    /// Only take a score from the transposition table when it was searched
+    /// at least as deep as the current node.
+    pub tt_depth_check: bool,
+    pub tt_entries: usize,
+    /// Search some moves deeper than the nominal depth.
+    pub extensions: bool,
+    pub check_extension: CheckExtension,
+    /// Most plies that extensions may add along any one line.
+    pub max_extension: u32,
+    /// Search root moves after the first with a null window, re-searching
+    /// only those that beat the best so far.
+    pub scout: bool,
+    /// Null-move pruning: skip a node whose side to move is doing so well
+    /// that even passing fails high.
+    pub null_move: bool,
+    /// Late move reductions: search quiet moves late in the ordering
+    /// shallower, and again at full depth only if they beat the best.
+    pub late_move_reductions: bool,
+    /// Skip captures in quiescence that lose material by static exchange.
+    pub see_pruning: bool,
+    /// Skip quiet moves near the leaves when the static evaluation is too
+    /// far below the window for them to catch up, by the margins in
+    /// `eval.futility_margins`.
+    pub futility_pruning: bool,
+    /// Skip captures in quiescence that couldn't raise the score to the
+    /// window even winning the piece taken and `DELTA_MARGIN` besides.
+    pub delta_pruning: bool,
+    /// Score positions 100 plies after the last capture or pawn move as
+    /// draws, as the 50-move rule lets either side claim.
+    pub fifty_move_draw: bool,
+    /// Halfmove clock of the position being searched.
+    pub halfmove_clock: u32,
+    /// Hashes of the game's positions before the root, oldest first.
+    /// Getting back to one of them in the search counts as a draw.
+    pub game_history: Vec<u64>,
+    /// Chess960 castling rights at the root; empty in a classical game.
+    pub castling: chess960::Castling,
+    /// Which move ordering heuristics to use; each level adds to the last.
+    pub ordering: MoveOrdering,
+    /// Re-searches allowed at one node before it stops using null windows
+    /// for its remaining moves. `None` never gives up on them.
+    pub pvs_research_cap: Option<u32>,
+    /// Print `info` lines as the search progresses.
+    pub info: bool,
+    /// Print a readable summary of each finished iteration.
+    pub thinking: bool,
+    /// Minimum milliseconds between `info` lines within one iteration.
+    pub info_interval: u64,
+    /// Aspiration window failures allowed at one depth before searching
+    /// with the full window. 0 turns aspiration windows off.
+    pub aspiration_researches: u32,
+    /// Half-width of the first aspiration window, in centipawns. It doubles
+    /// after each failure.
+    pub aspiration_window: i64,
+    /// Analysis rather than play (UCI_AnalyseMode): report full PVs and
+    /// never offer a draw.
+    pub analyse_mode: bool,
+    /// Seed for random tiebreaks and root noise; `None` keeps the search
+    /// deterministic.
+    pub seed: Option<u64>,
+    /// Mix the position's hash into the seed, so choices stay the same for
+    /// a position across runs but vary between positions.
+    pub seed_from_fen: bool,
+    /// Largest random change to a root move's score, in centipawns.
+    pub eval_noise: i64,
+    /// With a seed, the chance in percent that the search plays a random
+    /// other root move scoring within `suboptimal_margin` centipawns of the
+    /// best, as a weaker player might.
+    pub suboptimal_percent: u64,
+    pub suboptimal_margin: i64,
+    /// Value of a draw for the side to move at the root, in centipawns:
+    /// `--draw-score` less `--contempt`. Above 0 the engine steers for
+    /// draws, below 0 it avoids them.
+    pub draw_score: i64,
+    /// Set from another thread to end the search early, as UCI `stop`
+    /// does; the best move so far is returned.
+    pub stop: Option<Arc<AtomicBool>>,
+    /// Search threads sharing the transposition table, at least 1.
+    pub threads: usize,
+    /// Have the threads split the root moves between them instead, each
+    /// with a table of its own.
+    pub parallel_root: bool,
+    /// Best moves `multipv_search` finds, each with its own line.
+    pub multipv: usize,
+    /// Root moves the search leaves out: the lines MultiPV has found, or
+    /// the book lines that keep losing.
+    pub excluded_moves: Vec<ChessMove>,
+    /// Keep a `trace::Iteration` of every pass over the root moves.
+    pub trace: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> SearchConfig {
+        SearchConfig {
+            endgame_chain: vec![EndgameSource::Builtin, EndgameSource::Search],
+            movetime: None,
+            max_nodes: None,
+            move_overhead: time_manager::DEFAULT_MOVE_OVERHEAD_MS,
+            panic_time: 0,
+            eval: EvalParams::default(),
+            pv_verify: cfg!(debug_assertions),
+            thinking: false,
+            search_log: None,
+            tt_depth_check: true,
+            tt_entries: tt::DEFAULT_ENTRIES,
+            extensions: true,
+            check_extension: CheckExtension::Off,
+            max_extension: DEFAULT_MAX_EXTENSION,
+            scout: true,
+            null_move: true,
+            late_move_reductions: true,
+            see_pruning: true,
+            futility_pruning: true,
+            delta_pruning: true,
+            fifty_move_draw: false,
+            halfmove_clock: 0,
+            game_history: Vec::new(),
+            castling: chess960::Castling::default(),
+            ordering: MoveOrdering::HashMove,
+            pvs_research_cap: None,
+            info: false,
+            info_interval: 0,
+            aspiration_researches: DEFAULT_ASPIRATION_RESEARCHES,
+            aspiration_window: DEFAULT_ASPIRATION_WINDOW,
+            analyse_mode: false,
+            seed: None,
+            seed_from_fen: false,
+            eval_noise: 0,
+            suboptimal_percent: 0,
+            suboptimal_margin: 0,
+            draw_score: 0,
+            stop: None,
+            threads: 1,
+            parallel_root: false,
+            multipv: 1,
+            excluded_moves: Vec::new(),
+            trace: false,
+        }
+    }
+}
+
+/// State threaded through the recursive search.
+pub(crate) struct SearchContext<'a> {
+    pub(crate) config: &'a SearchConfig,
+    pub(crate) stats: SearchStats,
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) aborted: bool,
+    pub(crate) tt: &'a tt::TranspositionTable,
+    pub(crate) start: Instant,
+    /// When the last `info` line was printed.
+    last_info: Option<Instant>,
+    /// Hashes of the positions from the root down to the current node.
+    pub(crate) path: Vec<u64>,
+    /// Source of root move noise and tiebreaks, when a seed is set.
+    pub(crate) rng: Option<rng::Rng>,
+    pub(crate) ordering: ordering::OrderingTables,
+    /// Halfmove clock of the node being searched.
+    pub(crate) halfmove: u32,
+    /// Chess960 castling rights of the node being searched.
+    pub(crate) castling: chess960::Castling,
+    /// Internal score of a drawn position, from `draw_score`.
+    pub(crate) draw: i64,
+    /// Draws scored so far that depend on the path to the node: repetitions
+    /// and the 50-move rule.
+    path_draws: u64,
+    /// Extensions used along the line to the current node, in half plies.
+    extension_units: u32,
+    /// Where the move into the current node captured, if it did, so a
+    /// recapture there can be extended.
+    capture_square: Option<Square>,
+    /// Material and piece-square totals of the current node.
+    pub(crate) scores: PieceScores,
+    /// The last two moves on the way to the current node, the latest
+    /// first, for move ordering. `None` stands for a null move.
+    pub(crate) previous: [Option<ordering::Played>; 2],
+    /// The node being entered was reached by a null move, so it mustn't
+    /// pass straight back.
+    after_null: bool,
+    /// Passes over the root moves so far, when the search is traced.
+    pub(crate) trace: Option<Vec<trace::Iteration>>,
+}
+
+impl<'a> SearchContext<'a> {
+    pub(crate) fn new(
+        config: &'a SearchConfig,
+        tt: &'a tt::TranspositionTable,
+    ) -> SearchContext<'a> {
+        SearchContext {
+            config,
+            stats: SearchStats::default(),
+            deadline: config
+                .movetime
+                .map(|ms| time_manager::deadline(ms, config.move_overhead)),
+            aborted: false,
+            tt,
+            start: Instant::now(),
+            last_info: None,
+            path: Vec::new(),
+            rng: None,
+            ordering: ordering::OrderingTables::new(),
+            halfmove: config.halfmove_clock,
+            castling: config.castling,
+            draw: 0,
+            path_draws: 0,
+            extension_units: 0,
+            capture_square: None,
+            scores: PieceScores::default(),
+            previous: [None; 2],
+            after_null: false,
+            trace: config.trace.then(Vec::new),
+        }
+    }
+
+    /// Static evaluation of `board`, the current node.
+    pub(crate) fn evaluate(&self, board: &Board) -> i64 {
+        evaluate(board, &self.config.eval, &self.scores, self.draw)
+    }
+
+    /// Extra depth for searching `mv`, played on `board` to reach
+    /// `result_board`, and the extension units the line will have used
+    /// below it. Half plies add up along the line and only count once they
+    /// make a whole one.
+    ///
+    /// Forcing moves get a whole ply: promotions, recaptures on the square
+    /// just captured on, and checks that leave a single reply. Other checks
+    /// get what `check_extension` says.
+    pub(crate) fn extension(
+        &self,
+        board: &Board,
+        mv: ChessMove,
+        result_board: &Board,
+    ) -> (i8, u32) {
+        let before = self.extension_units;
+        if !self.config.extensions {
+            return (0, before);
+        }
+        let in_check = *result_board.checkers() != EMPTY;
+        let recapture =
+            self.capture_square.is_some() && self.capture_square == captured_on(board, mv);
+        let forcing = mv.get_promotion().is_some()
+            || recapture
+            || (in_check && MoveGen::new_legal(result_board).len() == 1);
+        let units = if forcing {
+            2
+        } else if in_check {
+            self.config.check_extension.half_plies()
+        } else {
+            0
+        };
+        let after = std::cmp::min(before + units, 2 * self.config.max_extension);
+        ((after / 2 - before / 2) as i8, after)
+    }
+
+    /// Plies to take off the search of `mv`, the `idx`th move tried at a
+    /// node `depth` plies from the horizon, which led to `result_board`.
+    /// Only quiet moves that neither give nor escape check are reduced.
+    fn reduction(
+        &self,
+        board: &Board,
+        mv: ChessMove,
+        idx: usize,
+        depth: i8,
+        result_board: &Board,
+    ) -> i8 {
+        let late =
+            self.config.late_move_reductions && idx >= LMR_FIRST_MOVE && depth >= LMR_MIN_DEPTH;
+        if late
+            && ordering::is_quiet_move(board, mv)
+            && *board.checkers() == EMPTY
+            && *result_board.checkers() == EMPTY
+        {
+            LMR_REDUCTION
+        } else {
+            0
+        }
+    }
+
+    /// Counts a re-search at a node that has now done `researches` of them.
+    /// Returns false once the cap is reached and the node should stop
+    /// using null windows.
+    fn record_research(&mut self, researches: &mut u32) -> bool {
+        self.stats.researches += 1;
+        *researches += 1;
+        match self.config.pvs_research_cap {
+            Some(cap) if *researches >= cap => {
+                self.stats.pvs_fallbacks += 1;
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Random offset for a root move score, within the configured noise.
+    fn root_noise(&mut self) -> i64 {
+        match self.rng.as_mut() {
+            Some(rng) if self.config.eval_noise > 0 => rng.spread(self.config.eval_noise),
+            _ => 0,
+        }
+    }
+
+    /// Whether a root move tying with the `tied` best so far replaces them,
+    /// so each tied move is equally likely to end up chosen.
+    fn take_tie(&mut self, tied: &mut u64) -> bool {
+        match self.rng.as_mut() {
+            Some(rng) => {
+                *tied += 1;
+                rng.below(*tied) == 0
+            }
+            None => false,
+        }
+    }
+
+    /// Whether an `info` line may print at `now`, counting it as printed if
+    /// so: the `last` line of an iteration always may, others only once the
+    /// interval since the previous one has passed.
+    fn info_due(&mut self, now: Instant, last: bool) -> bool {
+        let interval = Duration::from_millis(self.config.info_interval);
+        if !last && self.last_info.is_some_and(|at| now - at < interval) {
+            return false;
+        }
+        self.last_info = Some(now);
+        true
+    }
+
+    /// Prints a UCI `info` line for a new best line at the root, if enabled.
+    /// Lines within an iteration are throttled to the configured interval;
+    /// the `last` line of each iteration always prints. With `thinking`
+    /// that last line is printed for people instead.
+    fn report_info(&mut self, board: &Board, depth: i8, score: i64, pv: &[ChessMove], last: bool) {
+        if !(self.config.info || self.config.thinking && last) {
+            return;
+        }
+        let now = Instant::now();
+        if !self.info_due(now, last) {
+            return;
+        }
+        let millis = (now - self.start).as_millis();
+        let nps = (self.stats.nodes as u128 * 1000)
+            .checked_div(millis)
+            .unwrap_or(0);
+        let pv: Vec<String> = pv.iter().map(|mv| mv.to_string()).collect();
+        if self.config.info {
+            let line = match self.config.multipv {
+                1 => String::new(),
+                _ => format!("multipv {} ", self.config.excluded_moves.len() + 1),
+            };
+            println!(
+                "info {}depth {} score {} nodes {} nps {} time {} pv {}",
+                line,
+                depth,
+                uci_score(score_to_cp(board, score)),
+                self.stats.nodes,
+                nps,
+                millis,
+                pv.join(" ")
+            );
+        } else {
+            println!(
+                "depth {}  score {}  nodes {}  nps {}  time {} ms  pv {}",
+                depth,
+                eval_text(score_to_white(score)),
+                self.stats.nodes,
+                nps,
+                millis,
+                pv.join(" ")
+            );
+        }
+    }
+
+    /// Checks the node limit, and every thousand nodes or so the clock, the
+    /// stop flag and Ctrl-C. Once this returns true the scores coming back
+    /// from the search are meaningless.
+    fn out_of_time(&mut self) -> bool {
+        let max_nodes = self.config.max_nodes;
+        if max_nodes.is_some_and(|max| self.stats.nodes as u64 >= max) {
+            self.aborted = true;
+        }
+        if !self.aborted && self.stats.nodes % time_manager::CHECK_INTERVAL == 0 {
+            let stop = self.config.stop.as_ref();
+            self.aborted = interrupt::requested()
+                || stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+                || self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline);
+        }
+        self.aborted
+    }
+}
+
+fn alpha_beta(
+    board: &Board,
+    depth: i8,
+    is_max: bool,
+    alpha: i64,
+    beta: i64,
+    ctx: &mut SearchContext,
+    pv: &mut Vec<ChessMove>,
+) -> i64 {
+    pv.clear();
+    let after_null = std::mem::take(&mut ctx.after_null);
+    if ctx.out_of_time() {
+        return 0;
+    }
+    // Repeating a position on the way here, or from earlier in the game,
+    // means the side that wants to can keep repeating it, so it's a draw,
+    // perpetual check included.
+    let hash = board.get_hash() ^ ctx.castling.key();
+    if ctx.path.contains(&hash) || ctx.config.game_history.contains(&hash) {
+        ctx.stats.nodes += 1;
+        ctx.path_draws += 1;
+        return ctx.draw;
+    }
+    let status = match board.status() {
+        BoardStatus::Stalemate if !ctx.castling.moves(board).is_empty() => BoardStatus::Ongoing,
+        status => status,
+    };
+    let ongoing = status == BoardStatus::Ongoing;
+    // Mate on the hundredth ply still counts, so only ongoing games are cut.
+    if ongoing && ctx.config.fifty_move_draw && ctx.halfmove >= 100 {
+        ctx.stats.nodes += 1;
+        ctx.path_draws += 1;
+        return ctx.draw;
+    }
+    // Neither side can mate, whatever is played.
+    if ongoing && endgame::is_insufficient_material(board) {
+        ctx.stats.nodes += 1;
+        return ctx.draw;
+    }
+    // A known draw ends the line anywhere, but a known win only scores the
+    // leaves: the mate still has to be found, and the progress terms of the
+    // score steer the search toward it.
+    if ongoing {
+        if let Some((strong, score)) = endgame::probe(board, &ctx.config.endgame_chain) {
+            if score == 0 {
+                ctx.stats.nodes += 1;
+                return ctx.draw;
+            }
+            if depth <= 0 {
+                ctx.stats.nodes += 1;
+                return favour(strong) * score;
+            }
+        }
+    }
+    if status == BoardStatus::Stalemate {
+        ctx.stats.nodes += 1;
+        return ctx.draw;
+    }
+    if !ongoing {
+        ctx.stats.nodes += 1;
+        return mate_distance(ctx.evaluate(board), ctx.path.len());
+    }
+    if depth <= 0 {
+        let ply = ctx.path.len();
+        return quiescence(board, ply, is_max, alpha, beta, ctx);
+    }
+
+    // Mate-distance pruning: nothing below can beat mating with the next
+    // move or be worse than being mated here, so once a shorter mate is
+    // known elsewhere the window closes and the node is settled.
+    let ply = ctx.path.len() as i64;
+    let (lowest, highest) = if is_max {
+        (-(MATE_SCORE - ply), MATE_SCORE - ply - 1)
+    } else {
+        (-(MATE_SCORE - ply - 1), MATE_SCORE - ply)
+    };
+    let (alpha, beta) = (alpha.max(lowest), beta.min(highest));
+    if alpha >= beta {
+        ctx.stats.nodes += 1;
+        return if is_max { alpha } else { beta };
+    }
+
+    let mut tt_move = None;
+    ctx.stats.tt_probes += 1;
+    if let Some(entry) = ctx.tt.probe(hash) {
+        ctx.stats.tt_hits += 1;
+        // The stored move is worth trying first whatever its depth, but a
+        // shallower score can't stand in for this search.
+        tt_move = entry.best_move;
+        if !entry.path_dependent && (!ctx.config.tt_depth_check || entry.depth >= depth) {
+            let score = score_from_tt(entry.score, ctx.path.len());
+            let usable = match entry.bound {
+                tt::Bound::Exact => true,
+                tt::Bound::Lower => score >= beta,
+                tt::Bound::Upper => score <= alpha,
+            };
+            if usable {
+                ctx.stats.tt_cutoffs += 1;
+                return score;
+            }
+        }
+    }
+
+    // Near the leaves, a static evaluation far enough outside the window
+    // settles the node (reverse futility) or lets quiet moves be skipped,
+    // since they're unlikely to win back the margin (futility).
+    let mut futile = None;
+    let margin = match ctx.config.futility_pruning {
+        true => ctx.config.eval.futility_margin(depth),
+        false => None,
+    };
+    if let Some(margin) = margin {
+        if *board.checkers() == EMPTY {
+            let static_eval = ctx.evaluate(board);
+            if is_max && static_eval - margin >= beta || !is_max && static_eval + margin <= alpha {
+                ctx.stats.nodes += 1;
+                return static_eval;
+            }
+            if is_max && static_eval + margin <= alpha {
+                futile = Some(static_eval + margin);
+            } else if !is_max && static_eval - margin >= beta {
+                futile = Some(static_eval - margin);
+            }
+        }
+    }
+
+    // If the side to move stands at or past the bound already, let it pass
+    // and search the rest shallower; failing high even after giving the
+    // opponent a free move means a real move would too.
+    if ctx.config.null_move && !after_null && null_move_allowed(board, depth) {
+        let static_eval = ctx.evaluate(board);
+        let ahead = if is_max {
+            static_eval >= beta
+        } else {
+            static_eval <= alpha
+        };
+        if let Some(passed) = board.null_move().filter(|_| ahead) {
+            let (null_alpha, null_beta) = if is_max {
+                (beta - 1, beta)
+            } else {
+                (alpha, alpha + 1)
+            };
+            let (halfmove, capture_square) = (ctx.halfmove, ctx.capture_square);
+            let previous = ctx.previous;
+            ctx.halfmove += 1;
+            ctx.capture_square = None;
+            ctx.previous = [None, previous[0]];
+            ctx.after_null = true;
+            ctx.path.push(hash);
+            let value = alpha_beta(
+                &passed,
+                depth - 1 - NULL_MOVE_REDUCTION,
+                !is_max,
+                null_alpha,
+                null_beta,
+                ctx,
+                pv,
+            );
+            ctx.path.pop();
+            ctx.halfmove = halfmove;
+            ctx.capture_square = capture_square;
+            ctx.previous = previous;
+            pv.clear();
+            if ctx.aborted {
+                return 0;
+            }
+            let passes = |value: i64| {
+                if is_max {
+                    value >= beta
+                } else {
+                    value <= alpha
+                }
+            };
+            // With next to no pieces left, passing may be better than any
+            // real move, so the cutoff has to survive a shallower search of
+            // this node that isn't allowed to pass.
+            let cutoff = passes(value)
+                && (!zugzwang_prone(board) || {
+                    ctx.after_null = true;
+                    let verified = alpha_beta(
+                        board,
+                        depth - NULL_MOVE_REDUCTION,
+                        is_max,
+                        null_alpha,
+                        null_beta,
+                        ctx,
+                        pv,
+                    );
+                    pv.clear();
+                    passes(verified)
+                });
+            if ctx.aborted {
+                return 0;
+            }
+            // A mate found after passing proves nothing, so the cutoff
+            // returns the bound rather than the score.
+            if cutoff {
+                ctx.stats.null_cutoffs += 1;
+                return if is_max { beta } else { alpha };
+            }
+        }
+    }
+
+    let mut moves: Vec<ChessMove> = MoveGen::new_legal(board).collect();
+    moves.extend(ctx.castling.moves(board));
+    let ply = ctx.path.len();
+    let halfmove = ctx.halfmove;
+    let castling = ctx.castling;
+    let previous = ctx.previous;
+    ctx.ordering.order(
+        board,
+        &mut moves,
+        ctx.config.ordering,
+        ply,
+        tt_move,
+        previous,
+    );
+
+    let (alpha_orig, beta_orig) = (alpha, beta);
+    let mut alpha = alpha;
+    let mut beta = beta;
+    let mut child_pv = Vec::new();
+    let mut best_move = None;
+    // Principal variation search: after the first move, prove the others
+    // worse with a null window and only re-search the ones that aren't.
+    let mut null_windows = true;
+    let mut researches = 0;
+    let path_draws = ctx.path_draws;
+    let (extension_units, capture_square) = (ctx.extension_units, ctx.capture_square);
+    let scores = ctx.scores;
+
+    ctx.path.push(hash);
+    let best_value = if is_max {
+        let mut best_value = i64::MIN;
+        let mut result_board = chess::Board::default();
+        for (idx, &mv) in moves.iter().enumerate() {
+            ctx.castling = castling.play(board, mv, &mut result_board);
+            if let Some(bound) = futile {
+                if idx > 0
+                    && ordering::is_quiet_move(board, mv)
+                    && *result_board.checkers() == EMPTY
+                {
+                    best_value = std::cmp::max(best_value, bound);
+                    continue;
+                }
+            }
+
+            ctx.halfmove = game_state::next_halfmove(board, mv, halfmove);
+            let (extension, units) = ctx.extension(board, mv, &result_board);
+            ctx.extension_units = units;
+            ctx.capture_square = captured_on(board, mv);
+            ctx.scores = scores.after_move(board, mv, &ctx.config.eval);
+            ctx.previous = [Some(ordering::Played::of(board, mv)), previous[0]];
+            let child_depth = depth - 1 + extension;
+            let mut value;
+            if idx == 0 || !null_windows {
+                value = alpha_beta(
+                    &result_board,
+                    child_depth,
+                    false,
+                    alpha,
+                    beta,
+                    ctx,
+                    &mut child_pv,
+                );
+            } else {
+                let reduction = ctx.reduction(board, mv, idx, depth, &result_board);
+                value = alpha_beta(
+                    &result_board,
+                    child_depth - reduction,
+                    false,
+                    alpha,
+                    alpha + 1,
+                    ctx,
+                    &mut child_pv,
+                );
+                if reduction > 0 {
+                    ctx.stats.reductions += 1;
+                    if value > alpha && !ctx.aborted {
+                        ctx.stats.reduction_researches += 1;
+                        value = alpha_beta(
+                            &result_board,
+                            child_depth,
+                            false,
+                            alpha,
+                            alpha + 1,
+                            ctx,
+                            &mut child_pv,
+                        );
+                    }
+                }
+                if value > alpha && value < beta && !ctx.aborted {
+                    null_windows = ctx.record_research(&mut researches);
+                    value = alpha_beta(
+                        &result_board,
+                        child_depth,
+                        false,
+                        alpha,
+                        beta,
+                        ctx,
+                        &mut child_pv,
+                    );
+                }
+            }
+            if ctx.aborted {
+                break;
+            }
+            if value > best_value {
+                best_value = value;
+                best_move = Some(mv);
+                update_pv(pv, mv, &child_pv);
+            }
+
+            alpha = std::cmp::max(alpha, best_value);
+            if beta <= alpha {
+                ctx.stats.record_cutoff(idx);
+                ctx.ordering
+                    .record_cutoff(board, mv, ply, depth, previous, &moves[..idx]);
+                break;
+            }
+        }
+        best_value
+    } else {
+        let mut best_value = i64::MAX;
+        let mut result_board = chess::Board::default();
+        for (idx, &mv) in moves.iter().enumerate() {
+            ctx.castling = castling.play(board, mv, &mut result_board);
+            if let Some(bound) = futile {
+                if idx > 0
+                    && ordering::is_quiet_move(board, mv)
+                    && *result_board.checkers() == EMPTY
+                {
+                    best_value = std::cmp::min(best_value, bound);
+                    continue;
+                }
+            }
+
+            ctx.halfmove = game_state::next_halfmove(board, mv, halfmove);
+            let (extension, units) = ctx.extension(board, mv, &result_board);
+            ctx.extension_units = units;
+            ctx.capture_square = captured_on(board, mv);
+            ctx.scores = scores.after_move(board, mv, &ctx.config.eval);
+            ctx.previous = [Some(ordering::Played::of(board, mv)), previous[0]];
+            let child_depth = depth - 1 + extension;
+            let mut value;
+            if idx == 0 || !null_windows {
+                value = alpha_beta(
+                    &result_board,
+                    child_depth,
+                    true,
+                    alpha,
+                    beta,
+                    ctx,
+                    &mut child_pv,
+                );
+            } else {
+                let reduction = ctx.reduction(board, mv, idx, depth, &result_board);
+                value = alpha_beta(
+                    &result_board,
+                    child_depth - reduction,
+                    true,
+                    beta - 1,
+                    beta,
+                    ctx,
+                    &mut child_pv,
+                );
+                if reduction > 0 {
+                    ctx.stats.reductions += 1;
+                    if value < beta && !ctx.aborted {
+                        ctx.stats.reduction_researches += 1;
+                        value = alpha_beta(
+                            &result_board,
+                            child_depth,
+                            true,
+                            beta - 1,
+                            beta,
+                            ctx,
+                            &mut child_pv,
+                        );
+                    }
+                }
+                if value > alpha && value < beta && !ctx.aborted {
+                    null_windows = ctx.record_research(&mut researches);
+                    value = alpha_beta(
+                        &result_board,
+                        child_depth,
+                        true,
+                        alpha,
+                        beta,
+                        ctx,
+                        &mut child_pv,
+                    );
+                }
+            }
+            if ctx.aborted {
+                break;
+            }
+            if value < best_value {
+                best_value = value;
+                best_move = Some(mv);
+                update_pv(pv, mv, &child_pv);
+            }
+
+            beta = std::cmp::min(beta, best_value);
+            if beta <= alpha {
+                ctx.stats.record_cutoff(idx);
+                ctx.ordering
+                    .record_cutoff(board, mv, ply, depth, previous, &moves[..idx]);
+                break;
+            }
+        }
+        best_value
+    };
+    ctx.path.pop();
+    ctx.halfmove = halfmove;
+    ctx.castling = castling;
+    ctx.extension_units = extension_units;
+    ctx.capture_square = capture_square;
+    ctx.scores = scores;
+    ctx.previous = previous;
+    if ctx.aborted {
+        return 0;
+    }
+
+    let bound = if best_value <= alpha_orig {
+        tt::Bound::Upper
+    } else if best_value >= beta_orig {
+        tt::Bound::Lower
+    } else {
+        tt::Bound::Exact
+    };
+    ctx.tt.store(tt::TtEntry {
+        hash,
+        depth,
+        score: score_to_tt(best_value, ply),
+        bound,
+        best_move,
+        path_dependent: ctx.path_draws != path_draws,
+    });
+    best_value
+}
+
+/// Resolves the captures left hanging at the horizon, so a leaf isn't
+/// scored in the middle of an exchange. The side to move can stand pat on
+/// the static evaluation or try a capture; in check every evasion is
+/// searched instead, since standing pat could be mate.
+pub(crate) fn quiescence(
+    board: &Board,
+    ply: usize,
+    is_max: bool,
+    alpha: i64,
+    beta: i64,
+    ctx: &mut SearchContext,
+) -> i64 {
+    ctx.stats.nodes += 1;
+    if ctx.out_of_time() {
+        return 0;
+    }
+    let stand_pat = ctx.evaluate(board);
+    match board.status() {
+        BoardStatus::Ongoing => {}
+        BoardStatus::Stalemate => return ctx.draw,
+        BoardStatus::Checkmate => return mate_distance(stand_pat, ply),
+    }
+    let in_check = *board.checkers() != EMPTY;
+    let (mut alpha, mut beta) = (alpha, beta);
+    let mut best_value = if in_check {
+        if is_max {
+            i64::MIN
+        } else {
+            i64::MAX
+        }
+    } else {
+        stand_pat
+    };
+    if is_max {
+        alpha = std::cmp::max(alpha, best_value);
+    } else {
+        beta = std::cmp::min(beta, best_value);
+    }
+    if alpha >= beta {
+        return best_value;
+    }
+
+    let mut gen = MoveGen::new_legal(board);
+    if !in_check {
+        gen.set_iterator_mask(*board.color_combined(!board.side_to_move()));
+    }
+    let mut moves: Vec<ChessMove> = gen.collect();
+    ctx.ordering
+        .order(board, &mut moves, MoveOrdering::MvvLva, 0, None, [None; 2]);
+    let scores = ctx.scores;
+    for mv in moves {
+        // A capture that loses material once the exchange plays out can't
+        // raise the score above standing pat.
+        if !in_check && ctx.config.see_pruning && see::see(board, mv) < 0 {
+            continue;
+        }
+        // Nor can one that wouldn't reach the window even winning the
+        // piece outright and a margin more for the position.
+        if !in_check && ctx.config.delta_pruning && mv.get_promotion().is_none() {
+            let taken = board
+                .piece_on(mv.get_dest())
+                .map_or(piece_values::PAWN, piece_values::piece_value);
+            let gain = taken + DELTA_MARGIN;
+            // The bound goes into the score, which fails low soft.
+            if is_max && stand_pat + gain <= alpha {
+                best_value = std::cmp::max(best_value, stand_pat + gain);
+                continue;
+            }
+            if !is_max && stand_pat - gain >= beta {
+                best_value = std::cmp::min(best_value, stand_pat - gain);
+                continue;
+            }
+        }
+        ctx.scores = scores.after_move(board, mv, &ctx.config.eval);
+        let value = quiescence(&board.make_move_new(mv), ply + 1, !is_max, alpha, beta, ctx);
+        ctx.scores = scores;
+        if ctx.aborted {
+            return 0;
+        }
+        if is_max {
+            best_value = std::cmp::max(best_value, value);
+            alpha = std::cmp::max(alpha, best_value);
+        } else {
+            best_value = std::cmp::min(best_value, value);
+            beta = std::cmp::min(beta, best_value);
+        }
+        if alpha >= beta {
+            break;
+        }
+    }
+    best_value
+}
+
+/// How much deeper a move that gives check is searched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckExtension {
+    Off,
+    /// Half a ply: two checks along a line add one ply between them.
+    Half,
+    Full,
+}
+
+impl CheckExtension {
+    pub fn from_name(name: &str) -> Option<CheckExtension> {
+        match name.trim().to_lowercase().as_str() {
+            "off" => Some(CheckExtension::Off),
+            "half" => Some(CheckExtension::Half),
+            "full" => Some(CheckExtension::Full),
+            _ => None,
+        }
+    }
+
+    /// The extension in half plies.
+    fn half_plies(self) -> u32 {
+        match self {
+            CheckExtension::Off => 0,
+            CheckExtension::Half => 1,
+            CheckExtension::Full => 2,
+        }
+    }
+}
+
+/// Default cap on the plies extensions may add along one line.
+pub const DEFAULT_MAX_EXTENSION: u32 = 4;
+
+/// The square `mv` captures on, if it captures. En passant is left out,
+/// since the pawn lands on an empty square.
+fn captured_on(board: &Board, mv: ChessMove) -> Option<Square> {
+    ordering::captured_piece(board, mv)
+        .is_some()
+        .then_some(mv.get_dest())
+}
+
+/// Replaces `pv` with `mv` followed by the line found below it.
+fn update_pv(pv: &mut Vec<ChessMove>, mv: ChessMove, child_pv: &[ChessMove]) {
+    pv.clear();
+    pv.push(mv);
+    pv.extend_from_slice(child_pv);
+}
+
+/// Lengthens a PV cut short by transposition table cutoffs, following the
+/// stored best moves for as long as they are legal and don't repeat a
+/// position, up to `MAX_DEPTH` plies in all. `castling` holds the root's
+/// Chess960 rights.
+fn extend_pv_from_tt(
+    board: &Board,
+    castling: chess960::Castling,
+    pv: &mut Vec<ChessMove>,
+    tt: &tt::TranspositionTable,
+) {
+    let (mut board, mut castling) = (*board, castling);
+    let mut seen = vec![board.get_hash() ^ castling.key()];
+    for &mv in pv.iter() {
+        let before = board;
+        castling = castling.play(&before, mv, &mut board);
+        seen.push(board.get_hash() ^ castling.key());
+    }
+    while pv.len() < MAX_DEPTH as usize {
+        let hash = board.get_hash() ^ castling.key();
+        let mv = match tt.probe(hash).and_then(|entry| entry.best_move) {
+            Some(mv) if castling.is_legal(&board, mv) => mv,
+            _ => break,
+        };
+        let before = board;
+        castling = castling.play(&before, mv, &mut board);
+        let hash = board.get_hash() ^ castling.key();
+        if seen.contains(&hash) {
+            break;
+        }
+        seen.push(hash);
+        pv.push(mv);
+    }
+}
+
+pub struct SearchResult {
+    pub best_move: Option<ChessMove>,
+    /// Centipawns, positive when White is better; mates are scored from
+    /// `MATE_SCORE` down by the plies to go.
+    pub score: i64,
+    /// Deepest iteration that finished.
+    pub depth: i8,
+    pub nodes: i64,
+    /// Principal variation, starting with `best_move`.
+    pub pv: Vec<ChessMove>,
+    pub(crate) stats: SearchStats,
+    /// Every pass over the root moves, for a traced search.
+    pub(crate) trace: Vec<trace::Iteration>,
+}
+
+/// Outcome of searching every root move to one depth.
+pub(crate) struct RootIteration {
+    pub(crate) best_move: Option<ChessMove>,
+    pub(crate) score: i64,
+    pub(crate) pv: Vec<ChessMove>,
+    /// Score of each root move that finished, in search order.
+    pub(crate) move_scores: Vec<(ChessMove, i64)>,
+}
+
+/// Searches every root move inside the window `alpha..beta`, starting with
+/// `first`, usually the previous iteration's best move, then captures by
+/// MVV-LVA. Killers and history say little at the root, where every move
+/// gets searched anyway. A score at or beyond either edge is only a bound.
+fn search_root(
+    board: &Board,
+    depth: i8,
+    ctx: &mut SearchContext,
+    alpha: i64,
+    beta: i64,
+    first: Option<ChessMove>,
+) -> RootIteration {
+    let white_move = board.side_to_move() == Color::White;
+    let counters = trace::Iteration::counters(&ctx.stats);
+    ctx.scores = PieceScores::of(board, &ctx.config.eval);
+    let mut moves: Vec<ChessMove> = ctx.castling.legal_moves(board);
+    moves.retain(|mv| !ctx.config.excluded_moves.contains(mv));
+    ctx.ordering.order(
+        board,
+        &mut moves,
+        ctx.config.ordering.min(MoveOrdering::MvvLva),
+        0,
+        None,
+        [None; 2],
+    );
+    if let Some(idx) = first.and_then(|f| moves.iter().position(|&mv| mv == f)) {
+        moves[..=idx].rotate_right(1);
+    }
+
+    let mut best_value;
+    let mut best_move = None;
+    let mut pv = Vec::new();
+    let mut move_scores = Vec::new();
+
+    let is_better = {
+        if white_move {
+            best_value = i64::MIN;
+            |x: i64, y: i64| -> bool { x > y }
+        } else {
+            best_value = i64::MAX;
+            |x: i64, y: i64| -> bool { x < y }
+        }
+    };
+
+    // With a seed, moves are compared on a noisy score and ties are broken
+    // at random; otherwise the first of equal moves wins.
+    let mut best_key = best_value;
+    let mut tied = 0;
+
+    // Once a move has a score, the rest only need to be shown no better,
+    // with a null window, unless every root score has to be exact for the
+    // noise, the random tiebreaks, the search log or a trace.
+    let scout = ctx.config.scout
+        && ctx.rng.is_none()
+        && ctx.config.search_log.is_none()
+        && !ctx.config.trace;
+
+    let mut child_pv = Vec::new();
+    let castling = ctx.castling;
+    ctx.path.push(board.get_hash() ^ castling.key());
+    let halfmove = ctx.halfmove;
+    let (extension_units, capture_square) = (ctx.extension_units, ctx.capture_square);
+    let scores = ctx.scores;
+    for mv in moves {
+        let mut new_board = Board::default();
+        ctx.castling = castling.play(board, mv, &mut new_board);
+        ctx.halfmove = game_state::next_halfmove(board, mv, halfmove);
+        let (extension, units) = ctx.extension(board, mv, &new_board);
+        ctx.extension_units = units;
+        ctx.capture_square = captured_on(board, mv);
+        ctx.scores = scores.after_move(board, mv, &ctx.config.eval);
+        ctx.previous = [Some(ordering::Played::of(board, mv)), None];
+        let child_depth = depth + extension;
+        let window = match (scout && best_move.is_some(), white_move) {
+            (false, _) => None,
+            (true, true) => Some((best_value.max(alpha), best_value.max(alpha) + 1)),
+            (true, false) => Some((best_value.min(beta) - 1, best_value.min(beta))),
+        };
+        let mut value;
+        match window {
+            Some((null_alpha, null_beta)) if null_alpha >= alpha && null_beta <= beta => {
+                value = alpha_beta(
+                    &new_board,
+                    child_depth,
+                    !white_move,
+                    null_alpha,
+                    null_beta,
+                    ctx,
+                    &mut child_pv,
+                );
+                let improves = if white_move {
+                    value > null_alpha && value < beta
+                } else {
+                    value < null_beta && value > alpha
+                };
+                if improves && !ctx.aborted {
+                    ctx.stats.researches += 1;
+                    let (alpha, beta) = if white_move {
+                        (null_alpha, beta)
+                    } else {
+                        (alpha, null_beta)
+                    };
+                    value = alpha_beta(
+                        &new_board,
+                        child_depth,
+                        !white_move,
+                        alpha,
+                        beta,
+                        ctx,
+                        &mut child_pv,
+                    );
+                }
+            }
+            _ => {
+                value = alpha_beta(
+                    &new_board,
+                    child_depth,
+                    !white_move,
+                    alpha,
+                    beta,
+                    ctx,
+                    &mut child_pv,
+                );
+            }
+        }
+        if ctx.aborted {
+            break;
+        }
+        move_scores.push((mv, value));
+        let key = value + ctx.root_noise();
+        let better = is_better(key, best_key);
+        if better || key == best_key && ctx.take_tie(&mut tied) {
+            if better {
+                tied = 1;
+            }
+            best_key = key;
+            best_value = value;
+            best_move = Some(mv);
+            update_pv(&mut pv, mv, &child_pv);
+            ctx.report_info(board, depth, best_value, &pv, false);
+        }
+        // Nothing beats mate on the move, so the other moves can wait.
+        if score_to_cp(board, value) == MATE_SCORE - 1 {
+            break;
+        }
+    }
+    ctx.path.pop();
+    ctx.halfmove = halfmove;
+    ctx.castling = castling;
+    ctx.extension_units = extension_units;
+    ctx.capture_square = capture_square;
+    ctx.scores = scores;
+    ctx.previous = [None; 2];
+
+    let iteration = RootIteration {
+        best_move,
+        score: best_value,
+        pv,
+        move_scores,
+    };
+    if let Some(passes) = &mut ctx.trace {
+        passes.push(trace::Iteration::new(
+            board,
+            depth,
+            (alpha, beta),
+            &iteration,
+            counters,
+            &ctx.stats,
+            ctx.aborted,
+        ));
+    }
+    iteration
+}
+
+/// Searches the root in a narrow window around the previous iteration's
+/// score, trying its best move first, and widening the window each time
+/// the score falls outside. After the configured number of failures the
+/// window is dropped altogether, so a score swinging back and forth can't
+/// keep the search re-searching.
+fn aspiration_search(
+    board: &Board,
+    depth: i8,
+    previous: &RootIteration,
+    ctx: &mut SearchContext,
+) -> RootIteration {
+    let guess = previous.score;
+    let mut delta = ctx.config.aspiration_window;
+    let mut fails = 0;
+    loop {
+        let (alpha, beta) = if fails >= ctx.config.aspiration_researches {
+            (i64::MIN, i64::MAX)
+        } else {
+            (guess.saturating_sub(delta), guess.saturating_add(delta))
+        };
+        let iteration = search_root(board, depth, ctx, alpha, beta, previous.best_move);
+        let full_width = alpha == i64::MIN && beta == i64::MAX;
+        if ctx.aborted || full_width || (iteration.score > alpha && iteration.score < beta) {
+            return iteration;
+        }
+        ctx.stats.aspiration_researches += 1;
+        fails += 1;
+        delta = delta.saturating_mul(2);
+    }
+}
+
+/// Iterative deepening: searches to depth 1, 2, ... up to `depth`, keeping
+/// the result of the deepest iteration that finished before the deadline.
+/// With more than one thread the others help by filling the table; on
+/// wasm32, which has no threads, it always searches alone.
+pub fn search(board: &Board, depth: i8, config: &SearchConfig) -> SearchResult {
+    #[cfg(not(target_arch = "wasm32"))]
+    if config.threads > 1 {
+        return parallel_search(board, depth, config);
+    }
+    let tt = tt::TranspositionTable::new(config.tt_entries);
+    search_with_table(board, depth, config, &tt)
+}
+
+/// `search` on `config.threads` threads: root move splitting with
+/// `parallel_root`, else Lazy SMP.
+#[cfg(not(target_arch = "wasm32"))]
+fn parallel_search(board: &Board, depth: i8, config: &SearchConfig) -> SearchResult {
+    if config.parallel_root {
+        return root_split_search(board, depth, config);
+    }
+    let tt = tt::TranspositionTable::new(config.tt_entries);
+    // Lazy SMP: helper threads search the same position into the shared
+    // table, and the main thread picks up what they found as it goes.
+    let done = Arc::new(AtomicBool::new(false));
+    let helper_config = SearchConfig {
+        movetime: None,
+        max_nodes: None,
+        info: false,
+        search_log: None,
+        pv_verify: false,
+        thinking: false,
+        stop: Some(Arc::clone(&done)),
+        ..config.clone()
+    };
+    std::thread::scope(|scope| {
+        let helpers: Vec<_> = (1..config.threads)
+            .map(|id| {
+                let (config, tt) = (&helper_config, &tt);
+                scope.spawn(move || helper_search(board, depth, config, tt, id))
+            })
+            .collect();
+        let mut result = search_with_table(board, depth, config, &tt);
+        done.store(true, Ordering::Relaxed);
+        for helper in helpers {
+            result.nodes += helper.join().unwrap_or(0);
+        }
+        result
+    })
+}
+
+/// The `config.multipv` best moves, best first: each search leaves out the
+/// moves found before it. A timed search splits its time evenly between
+/// the lines. Stops early when the legal moves run out or the search is
+/// stopped, but always holds the first search, even without a move.
+pub fn multipv_search(board: &Board, depth: i8, config: &SearchConfig) -> Vec<SearchResult> {
+    let legal = config.castling.legal_moves(board).len();
+    let mut config = SearchConfig {
+        movetime: config.movetime.map(|ms| ms / config.multipv as u64),
+        excluded_moves: Vec::new(),
+        ..config.clone()
+    };
+    let mut lines = Vec::new();
+    loop {
+        let result = search(board, depth, &config);
+        let best_move = result.best_move;
+        lines.push(result);
+        let stopped = config
+            .stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed));
+        let done = stopped || lines.len() >= config.multipv.min(legal);
+        match best_move {
+            Some(mv) if !done => config.excluded_moves.push(mv),
+            _ => break,
+        }
+    }
+    lines
+}
+
+/// Swaps the best move of `iteration` for a random other root move scoring
+/// within `margin` centipawns of it, if there is one. Its line is cut to the
+/// move itself, since the search only followed the best one.
+fn settle_for_less(board: &Board, iteration: &mut RootIteration, margin: i64, rng: &mut rng::Rng) {
+    let best = score_to_cp(board, iteration.score);
+    let close: Vec<(ChessMove, i64)> = iteration
+        .move_scores
+        .iter()
+        .copied()
+        .filter(|&(mv, score)| {
+            Some(mv) != iteration.best_move && best - score_to_cp(board, score) <= margin
+        })
+        .collect();
+    if close.is_empty() {
+        return;
+    }
+    let (mv, score) = close[rng.below(close.len() as u64) as usize];
+    iteration.best_move = Some(mv);
+    iteration.score = score;
+    iteration.pv = vec![mv];
+}
+
+/// One Lazy SMP helper: plain iterative deepening into the shared table
+/// until the main thread is done. Odd helpers start a ply deeper, so the
+/// threads don't all search the same tree at the same time. Returns the
+/// nodes searched.
+#[cfg(not(target_arch = "wasm32"))]
+fn helper_search(
+    board: &Board,
+    depth: i8,
+    config: &SearchConfig,
+    tt: &tt::TranspositionTable,
+    id: usize,
+) -> i64 {
+    let mut ctx = SearchContext::new(config, tt);
+    ctx.draw = favour(board.side_to_move()) * config.draw_score;
+    let mut best_move = None;
+    for iteration_depth in (1 + (id % 2) as i8).min(depth)..=depth {
+        let iteration = search_root(
+            board,
+            iteration_depth,
+            &mut ctx,
+            i64::MIN,
+            i64::MAX,
+            best_move,
+        );
+        if ctx.aborted {
+            break;
+        }
+        best_move = iteration.best_move;
+    }
+    ctx.stats.nodes
+}
+
+/// The single-threaded search, using `tt` for its transpositions.
+fn search_with_table(
+    board: &Board,
+    depth: i8,
+    config: &SearchConfig,
+    tt: &tt::TranspositionTable,
+) -> SearchResult {
+    interrupt::set_searching(true);
+    let result = iterative_deepening(board, depth, config, tt);
+    interrupt::set_searching(false);
+    if interrupt::requested() {
+        report_interrupt(result.best_move, &result.pv, config.info);
+    }
+    result
+}
+
+/// Root move splitting: the threads take the root moves one at a time and
+/// search each on its own, with a table per thread, and the best of them
+/// is played. Nothing is shared but the moves left to take, so it scales
+/// with the threads, though each move is searched with a full window and
+/// without the others' transpositions. A time or node limit is shared out
+/// evenly between the moves.
+#[cfg(not(target_arch = "wasm32"))]
+fn root_split_search(board: &Board, depth: i8, config: &SearchConfig) -> SearchResult {
+    interrupt::set_searching(true);
+    let mut moves: Vec<ChessMove> = config.castling.legal_moves(board);
+    moves.retain(|mv| !config.excluded_moves.contains(mv));
+    let started = Instant::now();
+    let workers = config.threads.min(moves.len()).max(1);
+    let share = |limit: u64| (limit * workers as u64 / moves.len().max(1) as u64).max(1);
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results: Vec<(usize, SearchResult)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..workers)
+            .map(|_| {
+                let (moves, next) = (&moves, &next);
+                scope.spawn(move || {
+                    let tt = tt::TranspositionTable::new(config.tt_entries);
+                    let mut results = Vec::new();
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&mv) = moves.get(idx) else {
+                            break;
+                        };
+                        let left = config
+                            .movetime
+                            .map(|ms| ms.saturating_sub(started.elapsed().as_millis() as u64));
+                        if left == Some(0) || interrupt::requested() {
+                            break;
+                        }
+                        let move_config = SearchConfig {
+                            movetime: left
+                                .map(|left| share(config.movetime.unwrap_or(0)).min(left)),
+                            max_nodes: config.max_nodes.map(share),
+                            info: false,
+                            search_log: None,
+                            pv_verify: false,
+                            thinking: false,
+                            threads: 1,
+                            excluded_moves: moves.iter().copied().filter(|&m| m != mv).collect(),
+                            ..config.clone()
+                        };
+                        results.push((idx, iterative_deepening(board, depth, &move_config, &tt)));
+                    }
+                    results
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_default())
+            .collect()
+    });
+    let mut stats = SearchStats::default();
+    for (_, result) in &results {
+        stats.add(&result.stats);
+    }
+    let nodes = stats.nodes;
+    let side = favour(board.side_to_move());
+    // The first move in generator order wins a tie, as in the plain search.
+    let best = results
+        .into_iter()
+        .filter(|(_, result)| result.best_move.is_some())
+        .min_by_key(|(idx, result)| (-side * result.score, *idx));
+    let result = match best {
+        Some((_, result)) => SearchResult {
+            nodes,
+            stats,
+            ..result
+        },
+        None => SearchResult {
+            best_move: moves.first().copied(),
+            score: 0,
+            depth: 0,
+            nodes,
+            pv: moves.first().copied().into_iter().collect(),
+            stats,
+            trace: Vec::new(),
+        },
+    };
+    interrupt::set_searching(false);
+    if interrupt::requested() {
+        report_interrupt(result.best_move, &result.pv, config.info);
+    }
+    result
+}
+
+/// Searches to depth 1, 2, ... up to `depth` in one thread, using `tt` for
+/// its transpositions.
+fn iterative_deepening(
+    board: &Board,
+    depth: i8,
+    config: &SearchConfig,
+    tt: &tt::TranspositionTable,
+) -> SearchResult {
+    let mut ctx = SearchContext::new(config, tt);
+    ctx.draw = favour(board.side_to_move()) * config.draw_score;
+    ctx.rng = config.seed.map(|seed| {
+        if config.seed_from_fen {
+            rng::Rng::new(seed ^ board.get_hash())
+        } else {
+            rng::Rng::new(seed)
+        }
+    });
+    let mut completed: Option<(i8, RootIteration)> = None;
+    for iteration_depth in 1.min(depth)..=depth {
+        let iteration = match &completed {
+            Some((_, previous)) if config.aspiration_researches > 0 => {
+                aspiration_search(board, iteration_depth, previous, &mut ctx)
+            }
+            Some((_, previous)) => search_root(
+                board,
+                iteration_depth,
+                &mut ctx,
+                i64::MIN,
+                i64::MAX,
+                previous.best_move,
+            ),
+            None => search_root(board, iteration_depth, &mut ctx, i64::MIN, i64::MAX, None),
+        };
+        if let Some(path) = &config.search_log {
+            if let Err(e) = log_root_scores(path, board, iteration_depth, &iteration) {
+                println!("Cannot write search log: {}", e);
+            }
+        }
+        if ctx.aborted {
+            // A partial iteration is only better than nothing at all.
+            if completed.is_none() && iteration.best_move.is_some() {
+                completed = Some((iteration_depth, iteration));
+            }
+            break;
+        }
+        let mut iteration = iteration;
+        if config.analyse_mode {
+            extend_pv_from_tt(board, config.castling, &mut iteration.pv, ctx.tt);
+        }
+        ctx.report_info(board, iteration_depth, iteration.score, &iteration.pv, true);
+        if let (Some((_, previous)), Some(movetime)) = (&completed, config.movetime) {
+            let was = score_to_cp(board, previous.score);
+            let now = score_to_cp(board, iteration.score);
+            if config.panic_time > 0 && time_manager::is_panic(was, now) {
+                ctx.deadline = Some(time_manager::panic_deadline(
+                    ctx.start,
+                    movetime,
+                    config.panic_time,
+                    config.move_overhead,
+                ));
+            }
+        }
+        // The shortest forced mate shows up at the first depth that sees
+        // any mate, so searching deeper can't improve on it.
+        let mate_found = is_mate_score(iteration.score) && score_to_cp(board, iteration.score) > 0;
+        completed = Some((iteration_depth, iteration));
+        if mate_found {
+            break;
+        }
+    }
+
+    if let (Some((_, iteration)), Some(rng)) = (completed.as_mut(), ctx.rng.as_mut()) {
+        if rng.below(100) < config.suboptimal_percent {
+            settle_for_less(board, iteration, config.suboptimal_margin, rng);
+        }
+    }
+
+    let (depth, best_move, score, pv) = match completed {
+        Some((depth, it)) => (depth, it.best_move, it.score, it.pv),
+        None => {
+            // Out of time before a single root move was searched: any legal
+            // move beats forfeiting on time.
+            let mv = config
+                .castling
+                .legal_moves(board)
+                .into_iter()
+                .find(|mv| !config.excluded_moves.contains(mv));
+            (0, mv, 0, mv.into_iter().collect())
+        }
+    };
+
+    if config.info {
+        println!(
+            "info string tt probes {} hit rate {:.3} cutoff rate {:.3}",
+            ctx.stats.tt_probes,
+            ctx.stats.tt_hit_rate(),
+            ctx.stats.tt_cutoff_rate()
+        );
+    }
+
+    if config.pv_verify {
+        if let Err((ply, mv)) = verify_pv(board, config.castling, &pv) {
+            println!("info string illegal PV move {} at ply {}", mv, ply + 1);
+        }
+    }
+
+    SearchResult {
+        best_move,
+        score,
+        depth,
+        nodes: ctx.stats.nodes,
+        pv,
+        stats: ctx.stats,
+        trace: ctx.trace.unwrap_or_default(),
+    }
+}
+
+/// Ends the program after Ctrl-C stopped a search, printing the move it had
+/// settled on and its line, as `bestmove` for a GUI.
+fn report_interrupt(best_move: Option<ChessMove>, pv: &[ChessMove], uci: bool) -> ! {
+    match (best_move, uci) {
+        (Some(mv), true) => match pv.get(1) {
+            Some(reply) => println!("bestmove {} ponder {}", mv, reply),
+            None => println!("bestmove {}", mv),
+        },
+        (None, true) => println!("bestmove 0000"),
+        (Some(mv), false) => {
+            println!("Interrupted, best move so far: {}", mv);
+            let line: Vec<String> = pv.iter().map(|mv| mv.to_string()).collect();
+            println!("Line: {}", line.join(" "));
+        }
+        (None, false) => println!("Interrupted, no legal move"),
+    }
+    interrupt::exit();
+}
+
+/// Appends one `depth move score` line per finished root move, scores in
+/// centipawns for the side to move. A path of `-` logs to stderr.
+fn log_root_scores(
+    path: &str,
+    board: &Board,
+    depth: i8,
+    iteration: &RootIteration,
+) -> std::io::Result<()> {
+    let mut out: Box<dyn Write> = if path == "-" {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(OpenOptions::new().create(true).append(true).open(path)?)
+    };
+    for &(mv, score) in &iteration.move_scores {
+        writeln!(out, "depth {} {} {}", depth, mv, score_to_cp(board, score))?;
+    }
+    Ok(())
+}
+
+/// Plays `pv` out on a copy of `board`, with Chess960 rights `castling`,
+/// returning the index and move of the first one that isn't legal in the
+/// position it is played from.
+fn verify_pv(
+    board: &Board,
+    castling: chess960::Castling,
+    pv: &[ChessMove],
+) -> Result<(), (usize, ChessMove)> {
+    let (mut board, mut castling) = (*board, castling);
+    for (ply, &mv) in pv.iter().enumerate() {
+        if !castling.is_legal(&board, mv) {
+            return Err((ply, mv));
+        }
+        let before = board;
+        castling = castling.play(&before, mv, &mut board);
+    }
+    Ok(())
+}
+
+/// The principal variation: the best move followed by the replies the
+/// search expects. It ends early at a mate or stalemate.
+fn find_best_line(board: &Board, depth: i8) -> Vec<ChessMove> {
+    search(board, depth, &SearchConfig::default()).pv
+}
+
+pub(crate) fn find_best_move(board: &Board, depth: i8) -> Option<ChessMove> {
+    find_best_line(board, depth).first().copied()
+}
+
+/// Converts an internal score (positive favours White) into centipawns
+/// from the point of view of the side to move.
+pub fn score_to_cp(board: &Board, score: i64) -> i64 {
+    favour(board.side_to_move()) * score
+}
+
+/// Converts an internal score into centipawns from White's point of view.
+pub fn score_to_white(score: i64) -> i64 {
+    favour(Color::White) * score
+}
+
+/// A score for the side to move as UCI writes it: `cp 35`, or `mate 3`
+/// and `mate -3` counting in moves rather than plies.
+fn uci_score(cp: i64) -> String {
+    match mate_in(cp) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", cp),
+    }
+}
+
+/// Moves to mate for a mate score, negative when the side the score is
+/// for is the one being mated.
+fn mate_in(score: i64) -> Option<i64> {
+    is_mate_score(score).then(|| score.signum() * ((MATE_SCORE - score.abs() + 1) / 2))
+}
+
+pub fn search_json(board: &Board, result: &SearchResult, time_ms: u128) -> json::Object {
+    let nps = (result.nodes as u128 * 1000)
+        .checked_div(time_ms)
+        .unwrap_or(0);
+    json::Object::new()
+        .opt_str("bestmove", result.best_move)
+        .raw("score_cp", score_to_cp(board, result.score))
+        .raw(
+            "mate",
+            mate_in(score_to_cp(board, result.score)).map_or("null".to_string(), |m| m.to_string()),
+        )
+        .raw("depth", result.depth)
+        .raw("nodes", result.nodes)
+        .raw("nps", nps)
+        .raw("time_ms", time_ms)
+        .strs("pv", &result.pv)
+}
+
+/// Whether a node `depth` plies from the horizon may try a null move. With
+/// only king and pawns left the side to move is often in zugzwang, where
+/// passing would be its best move if it were allowed, so those nodes never
+/// do.
+fn null_move_allowed(board: &Board, depth: i8) -> bool {
+    if depth < NULL_MOVE_MIN_DEPTH || *board.checkers() != EMPTY {
+        return false;
+    }
+    let own = board.color_combined(board.side_to_move());
+    let pawns_and_king = board.pieces(Piece::Pawn) | board.pieces(Piece::King);
+    (own & !pawns_and_king) != EMPTY
+}
+
+/// Whether the side to move is down to at most one minor piece besides
+/// king and pawns, where zugzwang is common enough that a null-move
+/// cutoff needs checking.
+fn zugzwang_prone(board: &Board) -> bool {
+    let own = board.color_combined(board.side_to_move());
+    let heavy = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let minor = board.pieces(Piece::Knight) | board.pieces(Piece::Bishop);
+    own & heavy == EMPTY && (own & minor).popcnt() <= 1
+}
+
+/// `config` for a search of the current position of `game`. The game
+/// ends on repetitions and the 50-move rule, so the search has to see
+/// them coming.
+pub(crate) fn game_search_config(game: &GameState, config: &SearchConfig) -> SearchConfig {
+    SearchConfig {
+        halfmove_clock: game.clocks().halfmove,
+        fifty_move_draw: true,
+        game_history: game.earlier_hashes(),
+        castling: game.castling(),
+        ..config.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks;
+    use crate::eval::calc_board_value;
+    use crate::eval_params::{EvalParams, DEFAULT_FUTILITY_MARGINS};
+    use std::str::FromStr;
+
+    #[test]
+    fn search_json_has_legal_bestmove_and_pv() {
+        let board = Board::default();
+        let result = search(&board, 3, &SearchConfig::default());
+        let value = json::parse(&search_json(&board, &result, 10).to_string()).unwrap();
+        let best = ChessMove::from_str(value.get("bestmove").as_str().unwrap()).unwrap();
+        assert!(board.legal(best), "{}", best);
+        match value.get("pv") {
+            json::Value::Array(pv) => assert!(!pv.is_empty()),
+            pv => panic!("pv is {:?}", pv),
+        }
+        assert_eq!(value.get("nodes").as_u64(), Some(result.nodes as u64));
+    }
+
+    #[test]
+    fn finds_mate_by_knight_underpromotion() {
+        // f8=N is mate; f8=Q doesn't even give check.
+        let board = Board::from_str("6nb/5Ppk/6pp/8/8/8/8/K7 w - - 0 1").unwrap();
+        let result = search(&board, 3, &SearchConfig::default());
+        assert_eq!(result.best_move.unwrap().to_string(), "f7f8n");
+        assert_eq!(result.score, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn most_cutoffs_come_from_the_first_move() {
+        for (name, fen) in benchmarks::CASES {
+            let board = Board::from_str(fen).unwrap();
+            let stats = search(&board, 5, &SearchConfig::default()).stats;
+            assert!(stats.cutoffs > 0, "{}", name);
+            let rate = stats.first_move_cutoff_rate();
+            assert!(rate > 0.85, "{}: first move cutoff rate {:.3}", name, rate);
+        }
+    }
+
+    #[test]
+    fn move_overhead_ends_the_search_early() {
+        let config = SearchConfig {
+            movetime: Some(600),
+            move_overhead: 500,
+            ..SearchConfig::default()
+        };
+        let started = Instant::now();
+        let result = search(&Board::default(), MAX_DEPTH, &config);
+        let elapsed = started.elapsed().as_millis();
+        assert!(result.best_move.is_some());
+        assert!(elapsed < 400, "took {} ms of a 600 ms budget", elapsed);
+    }
+
+    #[test]
+    fn deep_pv_is_legal() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let board = Board::from_str(fen).unwrap();
+        let result = search(&board, 6, &SearchConfig::default());
+        assert!(result.pv.len() >= 4, "{:?}", result.pv);
+        assert_eq!(
+            verify_pv(&board, chess960::Castling::default(), &result.pv),
+            Ok(())
+        );
+        let illegal = [result.pv[0], result.pv[0]];
+        assert_eq!(
+            verify_pv(&board, chess960::Castling::default(), &illegal),
+            Err((1, result.pv[0]))
+        );
+    }
+
+    #[test]
+    fn search_log_has_every_root_move_at_every_depth() {
+        let path = std::env::temp_dir().join(format!("search-log-{}", std::process::id()));
+        let config = SearchConfig {
+            search_log: Some(path.to_str().unwrap().to_string()),
+            ..SearchConfig::default()
+        };
+        search(&Board::default(), 4, &config);
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for depth in 1..=4 {
+            let mut moves: Vec<&str> = log
+                .lines()
+                .filter_map(|line| line.strip_prefix(&format!("depth {} ", depth)))
+                .filter_map(|rest| rest.split(' ').next())
+                .collect();
+            moves.sort_unstable();
+            moves.dedup();
+            assert_eq!(moves.len(), 20, "depth {}: {:?}", depth, moves);
+        }
+    }
+
+    #[test]
+    fn tt_depth_check_changes_nodes_not_moves() {
+        for (name, fen) in benchmarks::CASES {
+            let board = Board::from_str(fen).unwrap();
+            let checked = search(&board, 5, &SearchConfig::default());
+            let config = SearchConfig {
+                tt_depth_check: false,
+                ..SearchConfig::default()
+            };
+            let unchecked = search(&board, 5, &config);
+            assert_ne!(checked.nodes, unchecked.nodes, "{}", name);
+            assert!(board.legal(unchecked.best_move.unwrap()), "{}", name);
+            let loss = (checked.score - unchecked.score).abs();
+            assert!(loss <= piece_values::PAWN / 2, "{}: {}", name, loss);
+        }
+    }
+
+    #[test]
+    fn pvs_fallback_keeps_the_best_move() {
+        let capped = SearchConfig {
+            pvs_research_cap: Some(1),
+            ..SearchConfig::default()
+        };
+        for (name, fen) in benchmarks::CASES {
+            let board = Board::from_str(fen).unwrap();
+            let plain = search(&board, 5, &SearchConfig::default());
+            let fallback = search(&board, 5, &capped);
+            assert_eq!(plain.best_move, fallback.best_move, "{}", name);
+        }
+        // Kiwipete's tactics make later moves beat the first one often
+        // enough to hit a cap of one re-search.
+        let board =
+            Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let result = search(&board, 4, &capped);
+        assert!(result.stats.pvs_fallbacks > 0);
+        assert_eq!(
+            search(&board, 4, &SearchConfig::default()).best_move,
+            result.best_move
+        );
+    }
+
+    #[test]
+    fn info_lines_are_throttled() {
+        let config = SearchConfig {
+            info_interval: 20,
+            ..SearchConfig::default()
+        };
+        let tt = tt::TranspositionTable::new(config.tt_entries);
+        let mut ctx = SearchContext::new(&config, &tt);
+        let start = Instant::now();
+        let mut lines = 0;
+        while start.elapsed() < Duration::from_millis(100) {
+            if ctx.info_due(Instant::now(), false) {
+                lines += 1;
+            }
+        }
+        assert!((1..=6).contains(&lines), "{}", lines);
+        assert!(ctx.info_due(Instant::now(), true));
+    }
+
+    #[test]
+    fn perpetual_check_saves_the_lost_position() {
+        // With a queen against three rooks, White checks from g5 and f6 for ever.
+        let board = Board::from_str("5rk1/5p1p/8/8/8/8/rr1Q2PP/7K w - - 0 1").unwrap();
+        let result = search(&board, 6, &SearchConfig::default());
+        assert_eq!(result.best_move, Some(ChessMove::from_str("d2g5").unwrap()));
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn wider_futility_margins_prune_less() {
+        let with_margins = |scale: i64| {
+            let mut config = SearchConfig::default();
+            config.eval.futility_margins = DEFAULT_FUTILITY_MARGINS
+                .iter()
+                .map(|margin| margin * scale / 2)
+                .collect();
+            config
+        };
+        for (name, fen) in benchmarks::CASES {
+            let board = Board::from_str(fen).unwrap();
+            let results: Vec<SearchResult> = [1, 2, 4]
+                .iter()
+                .map(|&scale| search(&board, 5, &with_margins(scale)))
+                .collect();
+            assert!(results[0].nodes < results[1].nodes, "{}", name);
+            assert!(results[1].nodes < results[2].nodes, "{}", name);
+            for result in &results[1..] {
+                assert_eq!(result.best_move, results[0].best_move, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn fen_seeded_choices_repeat_per_position_and_vary_between_them() {
+        // The same pieces, but Black has lost its castling rights, so only
+        // the hash tells the two apart at shallow depth.
+        let start = Board::default();
+        let uncastled =
+            Board::from_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1").unwrap();
+        let choice = |board: &Board, seed: u64, seed_from_fen: bool| {
+            let config = SearchConfig {
+                seed: Some(seed),
+                seed_from_fen,
+                suboptimal_percent: 100,
+                suboptimal_margin: piece_values::PAWN / 2,
+                ..SearchConfig::default()
+            };
+            search(board, 2, &config).best_move
+        };
+        let mut differed = false;
+        for seed in 0..20 {
+            assert_eq!(choice(&start, seed, true), choice(&start, seed, true));
+            assert_eq!(choice(&start, seed, false), choice(&uncastled, seed, false));
+            differed |= choice(&start, seed, true) != choice(&uncastled, seed, true);
+        }
+        assert!(differed);
+    }
+
+    #[test]
+    fn score_drop_spends_panic_time() {
+        // From the start the score falls from 0.64 at depth 2 to 0 at depth
+        // 3, well inside the move time.
+        let config = SearchConfig {
+            movetime: Some(100),
+            panic_time: 300,
+            ..SearchConfig::default()
+        };
+        let started = Instant::now();
+        search(&Board::default(), MAX_DEPTH, &config);
+        let elapsed = started.elapsed().as_millis();
+        assert!((300..600).contains(&elapsed), "{}", elapsed);
+    }
+
+    #[test]
+    fn aspiration_fallback_bounds_the_researches() {
+        // Kiwipete's score falls by a pawn over the first five depths, so
+        // a narrow window fails over and over.
+        let board =
+            Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let depth = 5;
+        let full_width = SearchConfig {
+            aspiration_researches: 0,
+            ..SearchConfig::default()
+        };
+        let narrow = SearchConfig {
+            aspiration_window: 5,
+            aspiration_researches: 2,
+            ..SearchConfig::default()
+        };
+        let expected = search(&board, depth, &full_width);
+        let result = search(&board, depth, &narrow);
+        assert_eq!(result.best_move, expected.best_move);
+        assert_eq!(result.score, expected.score);
+        let researches = result.stats.aspiration_researches;
+        assert!(
+            (1..=2 * (depth as i64 - 1)).contains(&researches),
+            "{}",
+            researches
+        );
+    }
+
+    #[test]
+    fn fifty_move_rule_draws_a_line_that_makes_no_progress() {
+        // A rook up, but no capture or pawn move is on the board, and
+        // there is no mate within the two plies left on the clock.
+        let board = Board::from_str("8/8/3k4/8/8/8/8/R3K3 w - - 98 100").unwrap();
+        let config = SearchConfig {
+            fifty_move_draw: true,
+            halfmove_clock: 98,
+            ..SearchConfig::default()
+        };
+        assert_eq!(search(&board, 4, &config).score, 0);
+        let unclaimed = SearchConfig {
+            fifty_move_draw: false,
+            ..config
+        };
+        assert!(search(&board, 4, &unclaimed).score > 3 * piece_values::PAWN);
+    }
+
+    #[test]
+    fn second_search_with_the_same_table_is_cheaper() {
+        let board =
+            Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let config = SearchConfig::default();
+        let tt = tt::TranspositionTable::new(config.tt_entries);
+        let first = search_with_table(&board, 5, &config, &tt);
+        let second = search_with_table(&board, 5, &config, &tt);
+        assert!(second.stats.tt_hits > 0);
+        assert!(
+            second.nodes * 4 < first.nodes,
+            "{} vs {}",
+            second.nodes,
+            first.nodes
+        );
+        assert_eq!(second.best_move, first.best_move);
+    }
+
+    #[test]
+    fn tt_hit_rate_is_plausible() {
+        let board =
+            Board::from_str("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/P4PPP/R1BQKB1R w KQ - 0 8")
+                .unwrap();
+        let stats = search(&board, 6, &SearchConfig::default()).stats;
+        assert!(stats.tt_probes > 0);
+        let rate = stats.tt_hit_rate();
+        assert!((0.01..0.9).contains(&rate), "{}", rate);
+    }
+
+    #[test]
+    fn mvv_lva_shrinks_a_tactical_tree() {
+        let board =
+            Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let nodes = |ordering| {
+            let config = SearchConfig {
+                ordering,
+                ..SearchConfig::default()
+            };
+            search(&board, 4, &config).nodes
+        };
+        let (unordered, ordered) = (nodes(MoveOrdering::None), nodes(MoveOrdering::MvvLva));
+        assert!(ordered * 4 < unordered, "{} vs {}", ordered, unordered);
+    }
+
+    #[test]
+    fn quiescence_takes_the_hanging_queen() {
+        // Promoting on h8 wins a rook and a new queen by the count after
+        // one ply, but the queen on d4 takes it back and is out of danger;
+        // Nxd4 wins her for nothing.
+        let board = Board::from_str("7r/6Pk/8/8/3q4/8/2N5/1K6 w - - 0 1").unwrap();
+        let params = EvalParams::default();
+        let one_ply = MoveGen::new_legal(&board)
+            .max_by_key(|&mv| calc_board_value(&board.make_move_new(mv), &params))
+            .unwrap();
+        assert_eq!(one_ply.to_string(), "g7h8q");
+        let result = search(&board, 1, &SearchConfig::default());
+        assert_eq!(result.best_move.unwrap().to_string(), "c2d4");
+    }
+
+    #[test]
+    fn draw_score_makes_the_engine_repeat() {
+        let mut game = GameState::new(Board::default(), game_state::Clocks::default());
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6"] {
+            game.make_move(ChessMove::from_str(mv).unwrap());
+        }
+        let best = |draw_score| {
+            let config = SearchConfig {
+                draw_score,
+                ..SearchConfig::default()
+            };
+            let config = game_search_config(&game, &config);
+            search(&game.board(), 4, &config)
+                .best_move
+                .unwrap()
+                .to_string()
+        };
+        // Going back to g1 repeats the position after 2. Ng1.
+        assert_ne!(best(0), "f3g1");
+        assert_eq!(best(piece_values::PAWN), "f3g1");
+    }
+
+    #[test]
+    fn mate_in_one_ends_the_search_at_once() {
+        let board =
+            Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .unwrap();
+        let result = search(&board, 6, &SearchConfig::default());
+        assert_eq!(result.best_move.unwrap().to_string(), "h5f7");
+        assert_eq!(result.depth, 1);
+        let moves = MoveGen::new_legal(&board).len() as i64;
+        assert!(result.nodes < 10 * moves, "{} nodes", result.nodes);
+        assert_eq!(find_best_move(&board, 6), result.best_move);
+    }
+
+    #[test]
+    fn mates_are_scored_by_their_distance() {
+        let config = SearchConfig::default();
+        let white_mates = Board::from_str("k7/8/1K6/8/8/8/8/7R w - - 0 1").unwrap();
+        let result = search(&white_mates, 4, &config);
+        assert_eq!(result.best_move.unwrap().to_string(), "h1h8");
+        assert_eq!(result.score, MATE_SCORE - 1);
+        let black_mates = Board::from_str("7r/8/8/8/8/1k6/8/K7 b - - 0 1").unwrap();
+        let result = search(&black_mates, 4, &config);
+        assert_eq!(result.best_move.unwrap().to_string(), "h8h1");
+        assert_eq!(result.score, -(MATE_SCORE - 1));
+        // Mate in two is three plies away.
+        let slower = Board::from_str("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        assert_eq!(search(&slower, 4, &config).score, MATE_SCORE - 3);
+    }
+
+    #[test]
+    fn repetition_draws_are_not_reused_from_the_table() {
+        // Two rooks down, White's only hope is 1. Qc3+ Kg8, which repeats
+        // an earlier position in the first game but not in the second.
+        let board = Board::from_str("7k/7p/8/p7/p7/8/rr4PP/2Q4K w - - 0 1").unwrap();
+        let repeated = ["c1c3", "h8g8"].iter().fold(board, |board, mv| {
+            board.make_move_new(ChessMove::from_str(mv).unwrap())
+        });
+        let config = SearchConfig {
+            game_history: vec![repeated.get_hash()],
+            ..SearchConfig::default()
+        };
+        let tt = tt::TranspositionTable::new(config.tt_entries);
+        let first = search_with_table(&board, 5, &config, &tt);
+        assert_eq!(first.best_move.unwrap().to_string(), "c1c3");
+        assert_eq!(first.score, 0);
+        let fresh = search(&board, 5, &SearchConfig::default());
+        assert!(fresh.score < 0);
+        let second = search_with_table(&board, 5, &SearchConfig::default(), &tt);
+        assert_eq!(second.score, fresh.score);
+    }
+
+    #[test]
+    fn null_move_prunes_without_changing_the_move() {
+        let board =
+            Board::from_str("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/P4PPP/R1BQKB1R w KQ - 0 8")
+                .unwrap();
+        let without = SearchConfig {
+            null_move: false,
+            ..SearchConfig::default()
+        };
+        let pruned = search(&board, 6, &SearchConfig::default());
+        let full = search(&board, 6, &without);
+        assert_eq!(pruned.best_move, full.best_move);
+        assert!(
+            pruned.nodes < full.nodes,
+            "{} vs {}",
+            pruned.nodes,
+            full.nodes
+        );
+        // King and pawns only: the side to move never passes.
+        let ending = Board::from_str("8/5k2/3p4/3P4/4K3/8/8/8 w - - 0 1").unwrap();
+        assert!(!null_move_allowed(&ending, 6));
+        assert_eq!(
+            search(&ending, 6, &SearchConfig::default()).nodes,
+            search(&ending, 6, &without).nodes
+        );
+    }
+
+    #[test]
+    fn scout_saves_nodes_and_keeps_the_best_moves() {
+        let full_window = SearchConfig {
+            scout: false,
+            ..SearchConfig::default()
+        };
+        let (mut scouted, mut full) = (0, 0);
+        for (name, fen) in benchmarks::CASES {
+            let board = Board::from_str(fen).unwrap();
+            let with = search(&board, 5, &SearchConfig::default());
+            let without = search(&board, 5, &full_window);
+            assert_eq!(with.best_move, without.best_move, "{}", name);
+            scouted += with.nodes;
+            full += without.nodes;
+        }
+        assert!(scouted < full, "{} vs {}", scouted, full);
+    }
+
+    #[test]
+    fn check_extensions_reshape_the_tree_within_bounds() {
+        let board =
+            Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let nodes = |check_extension, max_extension| {
+            let config = SearchConfig {
+                check_extension,
+                max_extension,
+                ..SearchConfig::default()
+            };
+            search(&board, 5, &config).nodes
+        };
+        let cap = DEFAULT_MAX_EXTENSION;
+        let (off, half, full) = (
+            nodes(CheckExtension::Off, cap),
+            nodes(CheckExtension::Half, cap),
+            nodes(CheckExtension::Full, cap),
+        );
+        assert_ne!(half, off);
+        assert!(full < 2 * off, "{} vs {}", full, off);
+        // With no room to extend every setting searches the same tree.
+        assert_eq!(
+            nodes(CheckExtension::Full, 0),
+            nodes(CheckExtension::Off, 0)
+        );
+        assert!(nodes(CheckExtension::Full, 0) < full);
+    }
+
+    #[test]
+    fn stop_flag_ends_the_search_with_a_move() {
+        let board =
+            Board::from_str("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/P4PPP/R1BQKB1R w KQ - 0 8")
+                .unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let config = SearchConfig {
+            stop: Some(Arc::clone(&stop)),
+            ..SearchConfig::default()
+        };
+        let flag = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            stop.store(true, Ordering::Relaxed);
+        });
+        let start = Instant::now();
+        let result = search(&board, crate::MAX_DEPTH, &config);
+        flag.join().unwrap();
+        assert!(start.elapsed().as_secs() < 5);
+        assert!(result.depth >= 1 && result.depth < crate::MAX_DEPTH);
+        assert!(board.legal(result.best_move.unwrap()));
+        assert_eq!(result.pv.first(), result.best_move.as_ref());
+    }
+
+    #[test]
+    fn aspiration_windows_match_the_full_window_for_fewer_nodes() {
+        let board =
+            Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 4 4")
+                .unwrap();
+        let full_window = SearchConfig {
+            aspiration_researches: 0,
+            ..SearchConfig::default()
+        };
+        let windowed = search(&board, 5, &SearchConfig::default());
+        let full = search(&board, 5, &full_window);
+        assert_eq!(windowed.best_move, full.best_move);
+        assert_eq!(windowed.score, full.score);
+        assert!(
+            windowed.nodes < full.nodes,
+            "{} vs {}",
+            windowed.nodes,
+            full.nodes
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::attacks;
+use crate::piece_values::{piece_value, PAWN};
+use chess::{BitBoard, Board, ChessMove, Piece};
+
+/// Capturing pieces tried cheapest first.
+const CAPTURE_ORDER: [Piece; 6] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+    Piece::King,
+];
+
+/// Static exchange evaluation: the material the side to move gains, in
+/// centipawns, if `mv` starts a sequence of recaptures on its destination
+/// square where each side always recaptures with its cheapest piece and may
+/// stop whenever carrying on would lose material.
+pub fn see(board: &Board, mv: ChessMove) -> i64 {
+    let target = mv.get_dest();
+    let mut mover = match board.piece_on(mv.get_source()) {
+        Some(piece) => piece,
+        None => return 0,
+    };
+    let captured = match board.piece_on(target) {
+        Some(piece) => piece_value(piece),
+        // A pawn moving diagonally onto an empty square is en passant.
+        None if mover == Piece::Pawn && mv.get_source().get_file() != target.get_file() => PAWN,
+        None => 0,
+    };
+
+    let mut occupied = *board.combined() ^ BitBoard::from_square(mv.get_source());
+    let mut side = !board.side_to_move();
+    let mut gains = vec![captured];
+    loop {
+        let attackers = attacks::attackers_to(board, target, occupied) & board.color_combined(side);
+        let next = CAPTURE_ORDER
+            .iter()
+            .map(|&piece| (piece, attackers & board.pieces(piece)))
+            .find(|(_, squares)| squares.popcnt() > 0);
+        let (piece, squares) = match next {
+            Some(found) => found,
+            None => break,
+        };
+        gains.push(piece_value(mover) - gains[gains.len() - 1]);
+        mover = piece;
+        occupied ^= BitBoard::from_square(squares.to_square());
+        side = !side;
+    }
+
+    // Each side may decline to recapture, so unwind from the end keeping
+    // whichever is better for the side to move at that point.
+    for i in (1..gains.len()).rev() {
+        gains[i - 1] = -std::cmp::max(-gains[i - 1], gains[i]);
+    }
+    gains[0]
+}
@@ -0,0 +1,107 @@
+use crate::game_state::GameState;
+use crate::time_manager::GameClock;
+use crate::{chess960, load_game, MAX_DEPTH};
+use chess::{ChessMove, Color};
+use std::str::FromStr;
+
+/// An interactive game as `save` writes it and `load` and `--resume` read
+/// it back. Each setting takes a line of its own:
+///
+/// ```text
+/// start rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
+/// moves e2e4 e7e5 g1f3
+/// engine black
+/// depth 6
+/// clock 287400 291100 2000
+/// ```
+///
+/// The moves are replayed from the start, so repetitions from before the
+/// save still count. `clock` holds White's and Black's time left and the
+/// increment, in milliseconds, and is left out of untimed games.
+pub struct Session {
+    pub game: GameState,
+    /// The side the engine plays.
+    pub engine: Color,
+    pub depth: i8,
+    pub clock: Option<GameClock>,
+}
+
+fn colour_name(colour: Color) -> &'static str {
+    match colour {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+impl Session {
+    /// The session file text.
+    fn to_text(&self) -> String {
+        let moves: Vec<String> = self.game.moves().iter().map(|mv| mv.to_string()).collect();
+        let mut text = format!(
+            "start {}\nmoves {}\nengine {}\ndepth {}\n",
+            self.game.start_fen(),
+            moves.join(" "),
+            colour_name(self.engine),
+            self.depth
+        );
+        if let Some(clock) = &self.clock {
+            let [white, black] = clock.left();
+            text += &format!("clock {} {} {}\n", white, black, clock.increment());
+        }
+        text
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+
+    /// Reads the session saved in `path`.
+    pub fn load(path: &str) -> Result<Session, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let (mut start, mut moves, mut engine, mut depth, mut clock) = (None, "", None, None, None);
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "start" => start = Some(value),
+                "moves" => moves = value,
+                "engine" => {
+                    engine = match value {
+                        "white" => Some(Color::White),
+                        "black" => Some(Color::Black),
+                        _ => return Err(format!("bad engine side '{}'", value)),
+                    }
+                }
+                "depth" => match value.parse::<i8>() {
+                    Ok(d) if (1..=MAX_DEPTH).contains(&d) => depth = Some(d),
+                    _ => return Err(format!("bad depth '{}'", value)),
+                },
+                "clock" => {
+                    let fields: Result<Vec<u64>, _> =
+                        value.split_whitespace().map(str::parse).collect();
+                    match fields.as_deref() {
+                        Ok(&[white, black, increment]) => {
+                            clock = Some(GameClock::new(0, increment).with_left([white, black]))
+                        }
+                        _ => return Err(format!("bad clock '{}'", value)),
+                    }
+                }
+                _ => return Err(format!("unknown setting '{}'", key)),
+            }
+        }
+        let start = start.ok_or("no start position")?;
+        let mut game = load_game(start, chess960::names_rook_files(start))?;
+        for token in moves.split_whitespace() {
+            match ChessMove::from_str(token) {
+                Ok(mv) if game.is_legal(mv) => game.make_move(mv),
+                _ => return Err(format!("illegal move {}", token)),
+            }
+        }
+        Ok(Session {
+            game,
+            engine: engine.ok_or("no engine side")?,
+            depth: depth.ok_or("no depth")?,
+            clock,
+        })
+    }
+}
@@ -0,0 +1,68 @@
+use crate::attacks;
+use crate::see;
+use chess::{Board, ChessMove, Color, MoveGen, Piece, Square, ALL_COLORS, EMPTY};
+
+/// Immediate tactics in a position, for the `--show-threats` listing.
+pub struct Threats {
+    /// Attacked pieces with no defender, of either colour.
+    pub hanging: Vec<(Color, Piece, Square)>,
+    /// Captures for the side to move that win material, with the SEE gain.
+    pub winning_captures: Vec<(ChessMove, i64)>,
+    /// Moves for the side to move that give check.
+    pub checks: Vec<ChessMove>,
+}
+
+pub fn find_threats(board: &Board) -> Threats {
+    let mut hanging = Vec::new();
+    for colour in ALL_COLORS {
+        let attacked = attacks::attacked_squares(board, !colour);
+        let defended = attacks::attacked_squares(board, colour);
+        for sq in *board.color_combined(colour) & attacked & !defended {
+            match board.piece_on(sq) {
+                Some(Piece::King) | None => {}
+                Some(piece) => hanging.push((colour, piece, sq)),
+            }
+        }
+    }
+
+    let mut winning_captures = Vec::new();
+    let mut checks = Vec::new();
+    for mv in MoveGen::new_legal(board) {
+        if board.piece_on(mv.get_dest()).is_some() {
+            let gain = see::see(board, mv);
+            if gain > 0 {
+                winning_captures.push((mv, gain));
+            }
+        }
+        if *board.make_move_new(mv).checkers() != EMPTY {
+            checks.push(mv);
+        }
+    }
+    winning_captures.sort_by_key(|&(_, gain)| -gain);
+
+    Threats {
+        hanging,
+        winning_captures,
+        checks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece_values::piece_value;
+    use std::str::FromStr;
+
+    #[test]
+    fn hanging_queen_is_listed_and_taken_first() {
+        // 1. e4 e5 2. Qh5 Nc6 3. Qxe5+?? leaves the queen to the knight.
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4Q3/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 0 3";
+        let threats = find_threats(&Board::from_str(fen).unwrap());
+        assert!(threats
+            .hanging
+            .contains(&(Color::White, Piece::Queen, Square::E5)));
+        let (best, gain) = threats.winning_captures[0];
+        assert_eq!(best.to_string(), "c6e5");
+        assert_eq!(gain, piece_value(Piece::Queen));
+    }
+}
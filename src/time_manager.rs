@@ -0,0 +1,215 @@
+use crate::piece_values::PAWN;
+use chess::Color;
+use std::time::Duration;
+
+#[cfg(target_arch = "wasm32")]
+pub use host_clock::Instant;
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+
+/// `wasm32-unknown-unknown` has no clock of its own, and `std`'s `Instant`
+/// panics there, so the search reads the host's instead: `performance.now()`
+/// through the `wasm` bindings, or else an `env.now_ms` the host supplies,
+/// in milliseconds from any fixed point.
+#[cfg(target_arch = "wasm32")]
+mod host_clock {
+    use std::ops::{Add, Sub};
+    use std::time::Duration;
+
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen::prelude::wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = performance, js_name = now)]
+        fn performance_now() -> f64;
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    extern "C" {
+        fn now_ms() -> f64;
+    }
+
+    #[cfg(feature = "wasm")]
+    fn host_now() -> f64 {
+        performance_now()
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn host_now() -> f64 {
+        unsafe { now_ms() }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+    pub struct Instant(f64);
+
+    impl Instant {
+        pub fn now() -> Instant {
+            Instant(host_now())
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            Instant::now() - *self
+        }
+    }
+
+    impl Add<Duration> for Instant {
+        type Output = Instant;
+
+        fn add(self, duration: Duration) -> Instant {
+            Instant(self.0 + duration.as_secs_f64() * 1000.0)
+        }
+    }
+
+    impl Sub for Instant {
+        type Output = Duration;
+
+        /// Zero when `earlier` is later, as for `std`'s `Instant`.
+        fn sub(self, earlier: Instant) -> Duration {
+            Duration::from_secs_f64((self.0 - earlier.0).max(0.0) / 1000.0)
+        }
+    }
+}
+
+/// Default allowance for GUI and communication lag, in milliseconds.
+pub const DEFAULT_MOVE_OVERHEAD_MS: u64 = 30;
+
+/// Nodes searched between clock checks.
+pub const CHECK_INTERVAL: i64 = 1024;
+
+/// Time the search may actually spend on a move: the requested move time
+/// less the overhead, but always at least a millisecond.
+pub fn move_budget(movetime_ms: u64, overhead_ms: u64) -> Duration {
+    Duration::from_millis(std::cmp::max(movetime_ms.saturating_sub(overhead_ms), 1))
+}
+
+/// Instant by which a search started now has to finish.
+pub fn deadline(movetime_ms: u64, overhead_ms: u64) -> Instant {
+    Instant::now() + move_budget(movetime_ms, overhead_ms)
+}
+
+/// Moves the remaining clock time has to last when the GUI doesn't say.
+pub const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+/// Move time for a side with `time_left_ms` on its clock, gaining
+/// `increment_ms` per move: an even share over the moves still to play,
+/// plus most of the increment, but never more than half the clock.
+pub fn clock_budget(time_left_ms: u64, increment_ms: u64, moves_to_go: Option<u64>) -> u64 {
+    let moves = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+    let share = time_left_ms / moves + increment_ms * 3 / 4;
+    std::cmp::min(share, time_left_ms / 2)
+}
+
+/// Drop in the score, in centipawns for the side to move, from one
+/// iteration to the next that marks the position as critical.
+pub const PANIC_SCORE_DROP: i64 = PAWN / 2;
+
+/// Whether a score going from `previous` to `current` (both for the side
+/// to move) is a big enough fall to spend panic time on.
+pub fn is_panic(previous: i64, current: i64) -> bool {
+    previous - current >= PANIC_SCORE_DROP
+}
+
+/// Deadline for a search that started at `start` and may now use `panic_ms`
+/// on top of its move time, still keeping the overhead in reserve.
+pub fn panic_deadline(
+    start: Instant,
+    movetime_ms: u64,
+    panic_ms: u64,
+    overhead_ms: u64,
+) -> Instant {
+    start + move_budget(movetime_ms + panic_ms, overhead_ms)
+}
+
+/// How long a self-play move should visibly take when pacing by position
+/// complexity: `per_move_ms` for each legal move available.
+pub fn pacing_delay(per_move_ms: u64, legal_moves: usize) -> Duration {
+    Duration::from_millis(per_move_ms.saturating_mul(legal_moves as u64))
+}
+
+/// Both sides' clocks in a timed game, in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct GameClock {
+    /// Added to a side's clock after each of its moves.
+    increment: u64,
+    /// Time left, indexed by colour.
+    left: [u64; 2],
+}
+
+impl GameClock {
+    /// Clocks for a game of `base` per side plus `increment` a move.
+    pub fn new(base: u64, increment: u64) -> GameClock {
+        GameClock {
+            increment,
+            left: [base; 2],
+        }
+    }
+
+    /// The same clocks with `left` on them, White's first, as when a saved
+    /// game resumes.
+    pub fn with_left(self, left: [u64; 2]) -> GameClock {
+        GameClock { left, ..self }
+    }
+
+    pub fn increment(&self) -> u64 {
+        self.increment
+    }
+
+    /// Time both sides have left, indexed by colour.
+    pub fn left(&self) -> [u64; 2] {
+        self.left
+    }
+
+    /// Move time for `side` going by its clock.
+    pub fn budget(&self, side: Color) -> u64 {
+        clock_budget(self.left[side.to_index()], self.increment, None)
+    }
+
+    /// Takes the `used` milliseconds of a move off `side`'s clock and adds
+    /// the increment. Returns false, leaving the clock at zero, if the
+    /// flag fell first.
+    pub fn charge(&mut self, side: Color, used: u64) -> bool {
+        let left = &mut self.left[side.to_index()];
+        if used > *left {
+            *left = 0;
+            return false;
+        }
+        *left = *left - used + self.increment;
+        true
+    }
+}
+
+/// Time on a clock as `m:ss.s`, e.g. `4:57.3`.
+fn clock_text(ms: u64) -> String {
+    format!("{}:{:02}.{}", ms / 60_000, ms / 1000 % 60, ms / 100 % 10)
+}
+
+impl std::fmt::Display for GameClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "White {}  Black {}",
+            clock_text(self.left[Color::White.to_index()]),
+            clock_text(self.left[Color::Black.to_index()])
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{Board, MoveGen};
+    use std::str::FromStr;
+
+    #[test]
+    fn busier_positions_pause_longer() {
+        let pause = |fen: &str| {
+            let board = Board::from_str(fen).unwrap();
+            pacing_delay(10, MoveGen::new_legal(&board).len())
+        };
+        // Twenty moves from the start, three for a king in its corner.
+        let busy = pause("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let quiet = pause("7k/8/8/8/8/8/8/K7 w - - 0 1");
+        assert_eq!(busy, Duration::from_millis(200));
+        assert!(quiet < busy);
+        assert_eq!(pacing_delay(0, 20), Duration::ZERO);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::eval::{
+    calc_attack_map, calc_board_value, calc_colour_complex, calc_king_safety,
+    calc_minor_piece_bonus, calc_mobility, calc_pawn_structure, calc_pins, calc_stalemate_risk,
+    game_phase,
+};
+use crate::eval_params::EvalParams;
+use crate::piece_scores::PieceScores;
+use crate::search::{score_to_cp, RootIteration, SearchStats};
+use crate::{endgame, json};
+use chess::{Board, BoardStatus, ChessMove};
+
+/// The terms of the static evaluation of `board`, in the order `evaluate`
+/// adds them up, in centipawns from White's point of view. Finished and
+/// dead drawn positions have no terms.
+pub fn eval_terms(board: &Board, params: &EvalParams) -> Vec<(&'static str, i64)> {
+    if board.status() != BoardStatus::Ongoing || endgame::is_insufficient_material(board) {
+        return Vec::new();
+    }
+    let scores = PieceScores::of(board, params);
+    vec![
+        ("material", scores.material()),
+        ("piece squares", scores.piece_squares(game_phase(board))),
+        ("king safety", calc_king_safety(board)),
+        ("minor pieces", calc_minor_piece_bonus(board, params)),
+        ("stalemate risk", calc_stalemate_risk(board)),
+        ("colour complex", calc_colour_complex(board)),
+        ("pins", calc_pins(board)),
+        ("attack map", calc_attack_map(board, params)),
+        ("pawns", calc_pawn_structure(board)),
+        ("mobility", calc_mobility(board, params)),
+    ]
+}
+
+/// How a root score stands to the window it was searched in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// At or below alpha: the move is at most this good.
+    FailLow,
+    /// At or above beta: the move is at least this good.
+    FailHigh,
+}
+
+impl Bound {
+    fn of(score: i64, window: Option<(i64, i64)>) -> Bound {
+        match window {
+            Some((alpha, _)) if score <= alpha => Bound::FailLow,
+            Some((_, beta)) if score >= beta => Bound::FailHigh,
+            _ => Bound::Exact,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Bound::Exact => "exact",
+            Bound::FailLow => "fail low",
+            Bound::FailHigh => "fail high",
+        }
+    }
+}
+
+/// One pass over the root moves of a traced search, an aspiration re-search
+/// of a depth included. Scores and the window are in centipawns for the side
+/// to move; counters cover this pass alone.
+pub struct Iteration {
+    pub depth: i8,
+    /// `None` for a full-width search.
+    pub window: Option<(i64, i64)>,
+    pub score: i64,
+    pub best_move: Option<ChessMove>,
+    pub move_scores: Vec<(ChessMove, i64)>,
+    /// The time or node limit stopped the pass before every move was done.
+    pub aborted: bool,
+    pub nodes: i64,
+    pub tt_probes: i64,
+    pub tt_hits: i64,
+    pub tt_cutoffs: i64,
+}
+
+impl Iteration {
+    /// The trace of `iteration`, searched in `alpha..beta`, where `before`
+    /// holds the counters from when it started.
+    pub(crate) fn new(
+        board: &Board,
+        depth: i8,
+        (alpha, beta): (i64, i64),
+        iteration: &RootIteration,
+        before: [i64; 4],
+        stats: &SearchStats,
+        aborted: bool,
+    ) -> Iteration {
+        let window = if alpha == i64::MIN && beta == i64::MAX {
+            None
+        } else {
+            let (low, high) = (score_to_cp(board, alpha), score_to_cp(board, beta));
+            Some((low.min(high), low.max(high)))
+        };
+        Iteration {
+            depth,
+            window,
+            score: score_to_cp(board, iteration.score),
+            best_move: iteration.best_move,
+            move_scores: iteration
+                .move_scores
+                .iter()
+                .map(|&(mv, score)| (mv, score_to_cp(board, score)))
+                .collect(),
+            aborted,
+            nodes: stats.nodes - before[0],
+            tt_probes: stats.tt_probes - before[1],
+            tt_hits: stats.tt_hits - before[2],
+            tt_cutoffs: stats.tt_cutoffs - before[3],
+        }
+    }
+
+    /// The counters `new` takes as `before`.
+    pub(crate) fn counters(stats: &SearchStats) -> [i64; 4] {
+        [
+            stats.nodes,
+            stats.tt_probes,
+            stats.tt_hits,
+            stats.tt_cutoffs,
+        ]
+    }
+
+    pub fn bound(&self) -> Bound {
+        Bound::of(self.score, self.window)
+    }
+
+    fn window_text(&self) -> String {
+        match self.window {
+            Some((alpha, beta)) => format!("{:+}..{:+}", alpha, beta),
+            None => "full".to_string(),
+        }
+    }
+}
+
+fn percent(part: i64, whole: i64) -> f64 {
+    100.0 * crate::search::rate(part, whole)
+}
+
+/// Prints the evaluation terms of `board` and their total, as a table or
+/// one JSON object.
+pub fn print_eval(board: &Board, params: &EvalParams, json: bool) {
+    let terms = eval_terms(board, params);
+    let total = calc_board_value(board, params);
+    if json {
+        let object = terms.iter().fold(
+            json::Object::new().str("type", "eval"),
+            |object, &(name, cp)| object.raw(&name.replace(' ', "_"), cp),
+        );
+        println!("{}", object.raw("total", total));
+        return;
+    }
+    println!("Evaluation, centipawns for White");
+    for (name, cp) in &terms {
+        println!("  {:<16}{:>+7}", name, cp);
+    }
+    let sum: i64 = terms.iter().map(|&(_, cp)| cp).sum();
+    if !terms.is_empty() && sum != total {
+        println!("  {:<16}{:>+7}", "clamp", total - sum);
+    }
+    println!("  {:<16}{:>+7}", "total", total);
+}
+
+/// Prints each pass of a traced search: its window and how the score came
+/// out against it, with the table's hits, then the root moves of the
+/// deepest pass that finished.
+pub fn print_search(iterations: &[Iteration], json: bool) {
+    if json {
+        for it in iterations {
+            let moves: Vec<String> = it
+                .move_scores
+                .iter()
+                .map(|&(mv, score)| {
+                    json::Object::new()
+                        .str("move", mv)
+                        .raw("score_cp", score)
+                        .str("bound", Bound::of(score, it.window).name())
+                        .to_string()
+                })
+                .collect();
+            let (alpha, beta) = match it.window {
+                Some((alpha, beta)) => (alpha.to_string(), beta.to_string()),
+                None => ("null".to_string(), "null".to_string()),
+            };
+            let line = json::Object::new()
+                .str("type", "iteration")
+                .raw("depth", it.depth)
+                .raw("alpha", alpha)
+                .raw("beta", beta)
+                .raw("score_cp", it.score)
+                .str("bound", it.bound().name())
+                .raw("aborted", it.aborted)
+                .opt_str("bestmove", it.best_move)
+                .raw("nodes", it.nodes)
+                .raw("tt_probes", it.tt_probes)
+                .raw("tt_hits", it.tt_hits)
+                .raw("tt_cutoffs", it.tt_cutoffs)
+                .raw("moves", format!("[{}]", moves.join(",")));
+            println!("{}", line);
+        }
+        return;
+    }
+    println!("Search, centipawns for the side to move");
+    println!(
+        "  {:<6}{:<14}{:<11}{:>7}  {:<7}{:>10}{:>9}{:>9}",
+        "depth", "window", "result", "score", "best", "nodes", "tt hits", "tt cuts"
+    );
+    for it in iterations {
+        let result = if it.aborted {
+            "stopped"
+        } else {
+            it.bound().name()
+        };
+        let best = it.best_move.map_or("-".to_string(), |mv| mv.to_string());
+        println!(
+            "  {:<6}{:<14}{:<11}{:>+7}  {:<7}{:>10}{:>8.1}%{:>8.1}%",
+            it.depth,
+            it.window_text(),
+            result,
+            it.score,
+            best,
+            it.nodes,
+            percent(it.tt_hits, it.tt_probes),
+            percent(it.tt_cutoffs, it.tt_probes)
+        );
+    }
+    let Some(deepest) = iterations.iter().rev().find(|it| !it.aborted) else {
+        return;
+    };
+    println!("Root moves at depth {}", deepest.depth);
+    let mut moves = deepest.move_scores.clone();
+    moves.sort_by_key(|&(_, score)| -score);
+    for (mv, score) in moves {
+        let bound = Bound::of(score, deepest.window);
+        let note = if bound == Bound::Exact {
+            ""
+        } else {
+            bound.name()
+        };
+        println!("  {:<7}{:>+7}  {}", mv.to_string(), score, note);
+    }
+}
@@ -0,0 +1,161 @@
+use chess::{ChessMove, ALL_PIECES, ALL_SQUARES};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of entries in a table; a power of two so the hash can be masked.
+pub const DEFAULT_ENTRIES: usize = 1 << 18;
+
+/// Largest table `--hash` or a GUI's `Hash` option may ask for, in
+/// megabytes.
+pub const MAX_HASH_MB: usize = 4096;
+
+/// Size in bytes of one slot: the packed entry and its checked key.
+const SLOT_BYTES: usize = std::mem::size_of::<Slot>();
+
+/// Number of entries that fit in `megabytes`, rounded down to a power of
+/// two so the table never grows past the budget.
+pub fn entries_for_megabytes(megabytes: usize) -> usize {
+    let fit = megabytes * 1024 * 1024 / SLOT_BYTES;
+    if fit == 0 {
+        1
+    } else {
+        1 << fit.ilog2()
+    }
+}
+
+/// Size of a table with `entries` entries, in whole megabytes.
+pub fn megabytes_for_entries(entries: usize) -> usize {
+    entries * SLOT_BYTES / (1024 * 1024)
+}
+
+/// How a stored score relates to the true value of the position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// The search failed high: the true value is at least `score`.
+    Lower,
+    /// The search failed low: the true value is at most `score`.
+    Upper,
+}
+
+#[derive(Clone, Copy)]
+pub struct TtEntry {
+    pub hash: u64,
+    pub depth: i8,
+    pub score: i64,
+    pub bound: Bound,
+    pub best_move: Option<ChessMove>,
+    /// The score hangs on a draw by repetition or the 50-move rule below
+    /// this node, which depends on how the search got here, so only the
+    /// move is worth reusing.
+    pub path_dependent: bool,
+}
+
+impl TtEntry {
+    /// Everything but the hash in one word: score in the low 32 bits, then
+    /// depth, bound, the path flag, the move and a top bit that tells a
+    /// used slot from an empty one. Scores past the range of an `i32` are
+    /// window sentinels and keep their sign.
+    fn pack(&self) -> u64 {
+        let score = self.score.clamp(i32::MIN as i64, i32::MAX as i64) as i32 as u32 as u64;
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        let mv = match self.best_move {
+            Some(mv) => {
+                let promotion = mv.get_promotion().map_or(0, |p| p.to_index() as u64 + 1);
+                1 << 15
+                    | promotion << 12
+                    | (mv.get_source().to_index() as u64) << 6
+                    | mv.get_dest().to_index() as u64
+            }
+            None => 0,
+        };
+        score
+            | (self.depth as u8 as u64) << 32
+            | bound << 40
+            | u64::from(self.path_dependent) << 42
+            | mv << 43
+            | OCCUPIED
+    }
+
+    fn unpack(hash: u64, data: u64) -> TtEntry {
+        let mv = (data >> 43) & 0xFFFF;
+        let best_move = (mv >> 15 == 1).then(|| {
+            let promotion = ((mv >> 12) & 7) as usize;
+            ChessMove::new(
+                ALL_SQUARES[((mv >> 6) & 63) as usize],
+                ALL_SQUARES[(mv & 63) as usize],
+                promotion.checked_sub(1).map(|p| ALL_PIECES[p]),
+            )
+        });
+        TtEntry {
+            hash,
+            depth: (data >> 32) as u8 as i8,
+            score: data as u32 as i32 as i64,
+            bound: match (data >> 40) & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+            best_move,
+            path_dependent: (data >> 42) & 1 == 1,
+        }
+    }
+}
+
+/// Set in the data of every stored entry; empty slots are all zeros.
+const OCCUPIED: u64 = 1 << 63;
+
+/// One table slot. The key is the hash XORed with the data, so an entry
+/// torn by two threads writing at once fails the check on probe instead of
+/// handing back half of each.
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Results of earlier searches, indexed by Zobrist hash, so transpositions
+/// and deeper iterations can reuse them. Lock-free, so search threads can
+/// share one table.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+    pub fn new(size: usize) -> TranspositionTable {
+        TranspositionTable {
+            slots: (0..size.next_power_of_two())
+                .map(|_| Slot {
+                    key: AtomicU64::new(0),
+                    data: AtomicU64::new(0),
+                })
+                .collect(),
+        }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        (hash as usize) & (self.slots.len() - 1)
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TtEntry> {
+        let slot = &self.slots[self.index(hash)];
+        let data = slot.data.load(Ordering::Relaxed);
+        let key = slot.key.load(Ordering::Relaxed);
+        (key ^ data == hash && data & OCCUPIED != 0).then(|| TtEntry::unpack(hash, data))
+    }
+
+    /// Stores `entry`, keeping a deeper result for the same position.
+    pub fn store(&self, entry: TtEntry) {
+        if let Some(old) = self.probe(entry.hash) {
+            if old.depth > entry.depth {
+                return;
+            }
+        }
+        let slot = &self.slots[self.index(entry.hash)];
+        let data = entry.pack();
+        slot.data.store(data, Ordering::Relaxed);
+        slot.key.store(entry.hash ^ data, Ordering::Relaxed);
+    }
+}
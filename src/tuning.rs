@@ -0,0 +1,353 @@
+use crate::display::WIN_PROB_SCALE;
+use crate::eval::{calc_board_value, game_phase};
+use crate::eval_params::{EvalParams, PIECE_NAMES};
+use crate::game_state::Clocks;
+use crate::{fen, piece_values};
+use chess::{Board, BoardStatus, Color, MoveGen, EMPTY};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// A position worth tuning on: not in check and with no capture available,
+/// so the static evaluation isn't distorted by a pending exchange.
+pub fn is_quiet(board: &Board) -> bool {
+    if *board.checkers() != EMPTY {
+        return false;
+    }
+    let mut moves = MoveGen::new_legal(board);
+    moves.set_iterator_mask(*board.color_combined(!board.side_to_move()));
+    moves.next().is_none()
+}
+
+/// PGN-style result of a finished game, or "1/2-1/2" if it was abandoned.
+pub fn game_result(board: &Board) -> &'static str {
+    match (board.status(), board.side_to_move()) {
+        (BoardStatus::Checkmate, Color::White) => "0-1",
+        (BoardStatus::Checkmate, Color::Black) => "1-0",
+        _ => "1/2-1/2",
+    }
+}
+
+/// Appends one `fen<TAB>eval<TAB>result` line per quiet position. `eval`
+/// maps a position to its score from White's point of view.
+pub fn append_records(
+    path: &str,
+    positions: &[Board],
+    result: &str,
+    eval: impl Fn(&Board) -> i64,
+) -> io::Result<usize> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut written = 0;
+    for board in positions.iter().filter(|b| is_quiet(b)) {
+        let fen = fen::to_fen(board, Clocks::default());
+        writeln!(file, "{}\t{}\t{}", fen, eval(board), result)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Material values tuned, in `PIECES` order; the king's is left alone.
+const VALUE_PIECES: std::ops::Range<usize> = 1..6;
+/// Where the tables start in the parameters: the middlegame table of each
+/// piece, in `PIECES` order, then the king's and pawns' endgame tables.
+const MIDDLEGAME_TABLES: usize = 5;
+const ENDGAME_TABLES: usize = MIDDLEGAME_TABLES + 6 * 64;
+const PARAMS: usize = ENDGAME_TABLES + 2 * 64;
+/// Names of the tables in `piece_values.rs`, in parameter order.
+const TABLE_NAMES: [&str; 8] = [
+    "K_VALUES",
+    "Q_VALUES",
+    "B_VALUES",
+    "R_VALUES",
+    "N_VALUES",
+    "P_VALUES",
+    "K_ENDGAME_VALUES",
+    "P_ENDGAME_VALUES",
+];
+
+/// Adam step size, in centipawns, and its moment decay rates.
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+/// A training position, reduced to what tuning needs: its weight on each
+/// parameter, the rest of the evaluation, and the result for White.
+pub struct Sample {
+    features: Vec<(usize, f64)>,
+    rest: f64,
+    result: f64,
+}
+
+/// Material values and piece-square tables, flattened into one vector in
+/// the order above.
+pub struct Params(Vec<f64>);
+
+impl Params {
+    /// The values `eval` uses. Only the king and pawns have endgame
+    /// tables of their own; the other pieces are tuned on their
+    /// middlegame tables.
+    pub fn from_eval(eval: &EvalParams) -> Params {
+        let mut values = vec![0.0; PARAMS];
+        for pc_idx in VALUE_PIECES {
+            values[pc_idx - VALUE_PIECES.start] = eval.piece_values[pc_idx] as f64;
+        }
+        for pc_idx in 0..6 {
+            for sq in 0..64 {
+                values[MIDDLEGAME_TABLES + pc_idx * 64 + sq] =
+                    eval.piece_squares[pc_idx][sq] as f64;
+            }
+        }
+        for (table, pc_idx) in [0, 5].into_iter().enumerate() {
+            for sq in 0..64 {
+                values[ENDGAME_TABLES + table * 64 + sq] =
+                    eval.endgame_piece_squares[pc_idx][sq] as f64;
+            }
+        }
+        Params(values)
+    }
+
+    fn eval(&self, sample: &Sample) -> f64 {
+        sample.rest
+            + sample
+                .features
+                .iter()
+                .map(|&(idx, weight)| self.0[idx] * weight)
+                .sum::<f64>()
+    }
+
+    fn rounded(&self, idx: usize) -> i64 {
+        self.0[idx].round() as i64
+    }
+
+    /// Rows of the table numbered `table` in `TABLE_NAMES`, rank 1 first.
+    fn table_rows(&self, table: usize) -> Vec<String> {
+        (0..8)
+            .map(|rank| {
+                let row: Vec<String> = (0..8)
+                    .map(|file| {
+                        let idx = MIDDLEGAME_TABLES + table * 64 + rank * 8 + file;
+                        format!("{:3}", self.rounded(idx))
+                    })
+                    .collect();
+                row.join(",")
+            })
+            .collect()
+    }
+
+    /// Writes the parameters as a file for `--eval-params`. Pieces without
+    /// an endgame table of their own get their middlegame one for both.
+    pub fn write_params(&self, out: &mut impl Write) -> io::Result<()> {
+        for pc_idx in VALUE_PIECES {
+            let value = self.rounded(pc_idx - VALUE_PIECES.start);
+            writeln!(out, "{}_value = {}", PIECE_NAMES[pc_idx], value)?;
+        }
+        for (pc_idx, name) in PIECE_NAMES.iter().enumerate() {
+            let endgame = match pc_idx {
+                0 => 6,
+                5 => 7,
+                _ => pc_idx,
+            };
+            for (suffix, table) in [("squares", pc_idx), ("endgame_squares", endgame)] {
+                writeln!(out)?;
+                writeln!(out, "{}_{} = [", name, suffix)?;
+                for row in self.table_rows(table) {
+                    writeln!(out, "    {},", row)?;
+                }
+                writeln!(out, "]")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the parameters as the constants of `piece_values.rs`, ready
+    /// to paste over the old ones.
+    pub fn write_rust(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "pub const PIECE_VALS: [i64; 6] = [")?;
+        let values: Vec<String> = VALUE_PIECES
+            .map(|pc_idx| self.rounded(pc_idx - VALUE_PIECES.start).to_string())
+            .collect();
+        writeln!(out, "    200 * PAWN, {}", values.join(", "))?;
+        writeln!(out, "];")?;
+        for (table, name) in TABLE_NAMES.iter().enumerate() {
+            writeln!(out)?;
+            writeln!(out, "const {}: [i64; 64] = [", name)?;
+            for row in self.table_rows(table) {
+                writeln!(out, "    {},", row)?;
+            }
+            writeln!(out, "];")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a game result: `1-0`, `0-1` or `1/2-1/2`, or White's score as
+/// `1.0`, `0.5` or `0.0`, optionally in brackets or quotes as EPD files
+/// write it.
+fn parse_result(text: &str) -> Option<f64> {
+    match text.trim_matches(|c| matches!(c, '[' | ']' | '"' | ';')) {
+        "1-0" | "1.0" | "1" => Some(1.0),
+        "0-1" | "0.0" | "0" => Some(0.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        _ => None,
+    }
+}
+
+/// Splits `board`'s evaluation under `params` into the tuned material and
+/// table terms, by weight, and everything else. King and pawn tables are
+/// blended by phase, the others are shared by both phases.
+fn sample(board: &Board, params: &EvalParams, result: f64) -> Sample {
+    let phase = game_phase(board) as f64 / piece_values::MAX_PHASE as f64;
+    let mut features = Vec::new();
+    for (pc_idx, &piece) in piece_values::PIECES.iter().enumerate() {
+        for square in *board.pieces(piece) {
+            let (sign, sq) = match board.color_on(square) {
+                Some(Color::White) => (1.0, square.to_index()),
+                _ => (-1.0, square.to_index() ^ 56),
+            };
+            if params.material && VALUE_PIECES.contains(&pc_idx) {
+                features.push((pc_idx - VALUE_PIECES.start, sign));
+            }
+            let middlegame = MIDDLEGAME_TABLES + pc_idx * 64 + sq;
+            match pc_idx {
+                0 | 5 => {
+                    let table = if pc_idx == 0 { 0 } else { 1 };
+                    features.push((middlegame, sign * phase));
+                    features.push((ENDGAME_TABLES + table * 64 + sq, sign * (1.0 - phase)));
+                }
+                _ => features.push((middlegame, sign)),
+            }
+        }
+    }
+    let mut sample = Sample {
+        features,
+        rest: 0.0,
+        result,
+    };
+    let tuned = Params::from_eval(params).eval(&sample);
+    sample.rest = calc_board_value(board, params) as f64 - tuned;
+    sample
+}
+
+/// Reads training positions from `path`, one per line: a FEN and the game
+/// result last, tab-separated as `--eval-tune-dump` writes them or spaced
+/// as in an EPD file. Lines that don't parse, and finished games, are
+/// skipped. Returns the samples and the number skipped.
+pub fn load_samples(path: &str, params: &EvalParams) -> io::Result<(Vec<Sample>, usize)> {
+    let text = std::fs::read_to_string(path)?;
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let parsed = if line.contains('\t') {
+            let fields: Vec<&str> = line.split('\t').collect();
+            Some((fields[0].to_string(), fields[fields.len() - 1]))
+        } else {
+            line.rsplit_once(char::is_whitespace)
+                .map(|(fen, result)| (fen.trim().to_string(), result))
+        };
+        let sample = parsed.and_then(|(fen, result)| {
+            let board = Board::from_str(&fen).ok()?;
+            let result = parse_result(result)?;
+            let ongoing = board.status() == BoardStatus::Ongoing
+                && !crate::endgame::is_insufficient_material(&board);
+            ongoing.then(|| sample(&board, params, result))
+        });
+        match sample {
+            Some(sample) => samples.push(sample),
+            None => skipped += 1,
+        }
+    }
+    Ok((samples, skipped))
+}
+
+/// Predicted score for White of a position evaluated at `eval`.
+fn sigmoid(eval: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / WIN_PROB_SCALE))
+}
+
+/// Mean squared difference between the results and their predictions.
+pub fn error(samples: &[Sample], params: &Params, k: f64) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .map(|s| (s.result - sigmoid(params.eval(s), k)).powi(2))
+        .sum();
+    total / samples.len() as f64
+}
+
+/// The scaling of the evaluation that best predicts the results as it
+/// stands, so tuning changes the parameters and not just their scale.
+pub fn fit_k(samples: &[Sample], params: &Params) -> f64 {
+    let (mut low, mut high) = (0.05, 5.0);
+    for _ in 0..60 {
+        let third = (high - low) / 3.0;
+        if error(samples, params, low + third) < error(samples, params, high - third) {
+            high -= third;
+        } else {
+            low += third;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Texel tuning: lowers the prediction error of `params` over `samples`
+/// by gradient descent with Adam, for `iterations` passes. Prints the
+/// error every tenth of the way.
+pub fn tune(samples: &[Sample], params: &mut Params, k: f64, iterations: u32) {
+    let mut m = vec![0.0; PARAMS];
+    let mut v = vec![0.0; PARAMS];
+    let scale = k * std::f64::consts::LN_10 / WIN_PROB_SCALE;
+    for iteration in 1..=iterations {
+        let mut gradient = vec![0.0; PARAMS];
+        for sample in samples {
+            let p = sigmoid(params.eval(sample), k);
+            let slope = (p - sample.result) * p * (1.0 - p) * scale;
+            for &(idx, weight) in &sample.features {
+                gradient[idx] += slope * weight;
+            }
+        }
+        for idx in 0..PARAMS {
+            let g = gradient[idx] / samples.len() as f64;
+            m[idx] = BETA1 * m[idx] + (1.0 - BETA1) * g;
+            v[idx] = BETA2 * v[idx] + (1.0 - BETA2) * g * g;
+            let m_hat = m[idx] / (1.0 - BETA1.powi(iteration as i32));
+            let v_hat = v[idx] / (1.0 - BETA2.powi(iteration as i32));
+            params.0[idx] -= LEARNING_RATE * m_hat / (v_hat.sqrt() + 1e-8);
+        }
+        if iteration % (iterations / 10).max(1) == 0 {
+            println!(
+                "Iteration {}: error {:.6}",
+                iteration,
+                error(samples, params, k)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::ChessMove;
+    use std::{env, fs};
+
+    #[test]
+    fn only_quiet_positions_are_recorded() {
+        let mut positions = vec![Board::default()];
+        for mv in ["e2e4", "d7d5", "e4d5"] {
+            let board = positions[positions.len() - 1];
+            positions.push(board.make_move_new(ChessMove::from_str(mv).unwrap()));
+        }
+        let path = env::temp_dir().join(format!("tune-dump-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        // After 1. e4 d5 and 2. exd5 a pawn can be taken.
+        assert_eq!(
+            append_records(path, &positions, "1/2-1/2", |_| 0).unwrap(),
+            2
+        );
+        let text = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines
+            .iter()
+            .all(|l| l.split('\t').count() == 3 && l.ends_with("\t0\t1/2-1/2")));
+    }
+}
@@ -0,0 +1,430 @@
+use crate::chess960::{self, Castling};
+use crate::game_state::{self, Clocks};
+use crate::input::CommandInput;
+use crate::search::{multipv_search, SearchConfig, MAX_MULTIPV, MAX_THREADS};
+use crate::{time_manager, tt, SearchLimits, MAX_DEPTH, STARTING_FEN};
+use chess::{Board, ChessMove, Color};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Largest `Contempt` either way, in centipawns.
+const MAX_CONTEMPT: i64 = 1000;
+
+/// What the engine calls itself in reply to `uci`.
+pub struct EngineId {
+    pub name: String,
+    pub author: String,
+}
+
+/// The game a GUI has set up with `position`.
+struct Position {
+    board: Board,
+    /// Chess960 castling rights, when `UCI_Chess960` is on.
+    castling: Castling,
+    halfmove: u32,
+    /// Hashes of the positions the moves passed through, for repetitions.
+    history: Vec<u64>,
+}
+
+impl Position {
+    fn start() -> Position {
+        Position {
+            board: Board::default(),
+            castling: Castling::default(),
+            halfmove: 0,
+            history: Vec::new(),
+        }
+    }
+}
+
+/// Parses the arguments of `position`: `startpos` or `fen <fen>`,
+/// optionally followed by `moves` and a list of coordinate moves. With
+/// `chess960` the FEN may be X-FEN or Shredder-FEN, and a castle is the
+/// king taking its own rook.
+fn parse_position(args: &str, chess960: bool) -> Result<Position, String> {
+    let (setup, moves) = match args.split_once("moves") {
+        Some((setup, moves)) => (setup.trim(), moves),
+        None => (args.trim(), ""),
+    };
+    let fen = match setup.strip_prefix("fen") {
+        Some(fen) => fen.trim(),
+        None if setup == "startpos" => STARTING_FEN,
+        None => return Err(format!("unknown position {}", setup)),
+    };
+    let (plain, castling) = match chess960 {
+        true => chess960::parse_fen(fen)?,
+        false => (fen.to_string(), Castling::default()),
+    };
+    let mut position = Position {
+        board: Board::from_str(&plain).map_err(|e| e.to_string())?,
+        castling,
+        halfmove: Clocks::from_fen(fen).halfmove,
+        history: Vec::new(),
+    };
+    for token in moves.split_whitespace() {
+        let mv = ChessMove::from_str(token).map_err(|e| e.to_string())?;
+        let board = position.board;
+        if !position.castling.is_legal(&board, mv) {
+            return Err(format!("illegal move {}", token));
+        }
+        position.halfmove = game_state::next_halfmove(&board, mv, position.halfmove);
+        position
+            .history
+            .push(board.get_hash() ^ position.castling.key());
+        position.castling = position.castling.play(&board, mv, &mut position.board);
+    }
+    Ok(position)
+}
+
+/// Limits of `go`: `depth`, `nodes` and `movetime`. A clock (`wtime` and
+/// `btime`, with `winc`, `binc` and `movestogo`) is turned into a move
+/// time for `side`. `infinite` searches until `stop`.
+fn parse_go(args: &str, side: Color) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut infinite = false;
+    let (mut time_left, mut increment, mut moves_to_go) = (None, 0, None);
+    let mut words = args.split_whitespace();
+    while let Some(word) = words.next() {
+        let mut number = || words.next().and_then(|n| n.parse::<u64>().ok());
+        match (word, side) {
+            ("depth", _) => limits.depth = number().map(|d| d.min(MAX_DEPTH as u64) as i8),
+            ("nodes", _) => limits.nodes = number(),
+            ("movetime", _) => limits.movetime = number(),
+            ("wtime", Color::White) | ("btime", Color::Black) => time_left = number(),
+            ("winc", Color::White) | ("binc", Color::Black) => increment = number().unwrap_or(0),
+            ("movestogo", _) => moves_to_go = number(),
+            ("infinite", _) => infinite = true,
+            _ => {}
+        }
+    }
+    limits.movetime = limits
+        .movetime
+        .or_else(|| time_left.map(|left| time_manager::clock_budget(left, increment, moves_to_go)));
+    if infinite && limits.depth.is_none() {
+        limits.depth = Some(MAX_DEPTH);
+    }
+    limits
+}
+
+/// A `go` running on its own thread, so commands keep being read.
+struct RunningSearch {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    /// While pondering: wakes the thread to print its move, which it holds
+    /// back until `ponderhit` or `stop`.
+    ponder: Option<Sender<()>>,
+    /// Time for the move once the ponder move has been played.
+    movetime: Option<u64>,
+}
+
+impl RunningSearch {
+    /// Starts searching `board`; the thread hands the `bestmove` line to
+    /// `report` when done, with the reply it expects as the move to ponder
+    /// on. With `ponder` the search has no time limit until `ponderhit`
+    /// gives it `movetime`.
+    fn start<F>(
+        board: Board,
+        depth: i8,
+        mut config: SearchConfig,
+        ponder: bool,
+        report: F,
+    ) -> RunningSearch
+    where
+        F: FnOnce(String) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        config.stop = Some(Arc::clone(&stop));
+        let movetime = config.movetime;
+        let (sender, receiver) = if ponder {
+            config.movetime = None;
+            let (sender, receiver) = mpsc::channel();
+            (Some(sender), Some(receiver))
+        } else {
+            (None, None)
+        };
+        // Moves print in coordinate notation, promotions included as e.g.
+        // e7e8q.
+        let handle = thread::spawn(move || {
+            // With MultiPV the first line is the best, and the one played.
+            let result = multipv_search(&board, depth, &config).remove(0);
+            // The protocol forbids a bestmove while pondering, even when
+            // the search runs out of depth first.
+            if let Some(receiver) = receiver {
+                let _ = receiver.recv();
+            }
+            report(match (result.best_move, result.pv.get(1)) {
+                (Some(mv), Some(reply)) => format!("bestmove {} ponder {}", mv, reply),
+                (Some(mv), None) => format!("bestmove {}", mv),
+                (None, _) => "bestmove 0000".to_string(),
+            });
+        });
+        RunningSearch {
+            stop,
+            handle,
+            ponder: sender,
+            movetime,
+        }
+    }
+
+    /// The opponent played the move being pondered: the search goes on as
+    /// a normal one, stopping once its move time has passed.
+    fn ponderhit(&mut self) {
+        let Some(ponder) = self.ponder.take() else {
+            return;
+        };
+        let _ = ponder.send(());
+        if let Some(ms) = self.movetime {
+            let stop = Arc::clone(&self.stop);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(ms));
+                stop.store(true, Ordering::Relaxed);
+            });
+        }
+    }
+
+    /// Waits for the search to finish, telling it to stop first if `stop`.
+    fn finish(self, stop: bool) {
+        if stop {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        if let Some(ponder) = &self.ponder {
+            let _ = ponder.send(());
+        }
+        if self.handle.join().is_err() {
+            println!("info string search thread panicked");
+        }
+    }
+}
+
+/// What the engine answers to `uci`: its id and options, then `uciok`.
+fn handshake(config: &SearchConfig, id: &EngineId) -> Vec<String> {
+    let mut lines = Vec::new();
+    lines.push(format!("id name {} {}", id.name, env!("CARGO_PKG_VERSION")));
+    lines.push(format!("id author {}", id.author));
+    lines.push(format!(
+        "option name Hash type spin default {} min 1 max {}",
+        tt::megabytes_for_entries(tt::DEFAULT_ENTRIES),
+        tt::MAX_HASH_MB
+    ));
+    lines.push(format!(
+        "option name Threads type spin default 1 min 1 max {}",
+        MAX_THREADS
+    ));
+    lines.push(format!(
+        "option name MultiPV type spin default 1 min 1 max {}",
+        MAX_MULTIPV
+    ));
+    lines.push(format!(
+        "option name Contempt type spin default {} min -{} max {}",
+        -config.draw_score, MAX_CONTEMPT, MAX_CONTEMPT
+    ));
+    lines.push("option name Ponder type check default false".to_string());
+    lines.push("option name NullMove type check default true".to_string());
+    lines.push("option name LateMoveReductions type check default true".to_string());
+    lines.push("option name UCI_AnalyseMode type check default false".to_string());
+    lines.push("option name UCI_Chess960 type check default false".to_string());
+    lines.push("uciok".to_string());
+    lines
+}
+
+/// Applies `setoption name <name> value <value>` to `config`, or to
+/// `chess960` for `UCI_Chess960`.
+fn set_option(args: &str, config: &mut SearchConfig, chess960: &mut bool) {
+    let Some((name, value)) = args
+        .strip_prefix("name")
+        .and_then(|rest| rest.split_once("value"))
+    else {
+        return;
+    };
+    match name.trim() {
+        "UCI_AnalyseMode" => config.analyse_mode = value.trim() == "true",
+        "UCI_Chess960" => *chess960 = value.trim() == "true",
+        "NullMove" => config.null_move = value.trim() == "true",
+        "LateMoveReductions" => config.late_move_reductions = value.trim() == "true",
+        "Hash" => match value.trim().parse() {
+            Ok(megabytes) if (1..=tt::MAX_HASH_MB).contains(&megabytes) => {
+                config.tt_entries = tt::entries_for_megabytes(megabytes)
+            }
+            _ => println!("info string bad Hash value {}", value.trim()),
+        },
+        // Pondering is driven by `go ponder`, so there is nothing to set.
+        "Ponder" => {}
+        "MultiPV" => match value.trim().parse() {
+            Ok(lines) if (1..=MAX_MULTIPV).contains(&lines) => config.multipv = lines,
+            _ => println!("info string bad MultiPV value {}", value.trim()),
+        },
+        "Contempt" => match value.trim().parse::<i64>() {
+            Ok(cp) if cp.abs() <= MAX_CONTEMPT => config.draw_score = -cp,
+            _ => println!("info string bad Contempt value {}", value.trim()),
+        },
+        "Threads" => match value.trim().parse() {
+            Ok(threads) if (1..=MAX_THREADS).contains(&threads) => config.threads = threads,
+            _ => println!("info string bad Threads value {}", value.trim()),
+        },
+        name => println!("info string unknown option {}", name),
+    }
+}
+
+/// Speaks the UCI protocol on stdin and stdout until `quit` or end of
+/// input. Searches start from `config`, which `setoption` can change.
+/// The version is added to `id`'s name, so logs show which build played.
+pub fn run(config: &SearchConfig, default_depth: i8, id: &EngineId) {
+    let mut config = SearchConfig {
+        info: true,
+        ..config.clone()
+    };
+    let mut position = Position::start();
+    let mut chess960 = false;
+    let mut running: Option<RunningSearch> = None;
+    let mut input = CommandInput::stdin();
+    while let Some(line) = input.next_line() {
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        // Only these may arrive while a search runs; anything else waits
+        // for it to end, as the protocol has the GUI wait for bestmove.
+        if !matches!(command, "isready" | "stop" | "ponderhit" | "") {
+            if let Some(search) = running.take() {
+                search.finish(command == "quit");
+            }
+        }
+        match command {
+            "uci" => {
+                for line in handshake(&config, id) {
+                    println!("{}", line);
+                }
+            }
+            "isready" => println!("readyok"),
+            "ucinewgame" => position = Position::start(),
+            "setoption" => set_option(args.trim(), &mut config, &mut chess960),
+            "position" => match parse_position(args, chess960) {
+                Ok(parsed) => position = parsed,
+                Err(e) => println!("info string bad position: {}", e),
+            },
+            "go" => {
+                let limits = parse_go(args, position.board.side_to_move());
+                let config = SearchConfig {
+                    halfmove_clock: position.halfmove,
+                    game_history: position.history.clone(),
+                    castling: position.castling,
+                    ..limits.apply(&config)
+                };
+                let depth = limits.depth_or(default_depth);
+                let ponder = args.split_whitespace().any(|word| word == "ponder");
+                let report = |line| println!("{}", line);
+                running = Some(RunningSearch::start(
+                    position.board,
+                    depth,
+                    config,
+                    ponder,
+                    report,
+                ));
+            }
+            "ponderhit" => {
+                if let Some(search) = running.as_mut() {
+                    search.ponderhit();
+                }
+            }
+            "stop" => {
+                if let Some(search) = running.take() {
+                    search.finish(true);
+                }
+            }
+            "quit" => return,
+            "" => {}
+            _ => println!("info string unknown command {}", command),
+        }
+    }
+    if let Some(search) = running {
+        search.finish(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{Piece, Square};
+
+    #[test]
+    fn ponder_search_done_before_ponderhit_moves_once() {
+        let (sender, receiver) = mpsc::channel();
+        let report = move |line| sender.send(line).unwrap();
+        let config = SearchConfig {
+            movetime: Some(50),
+            ..SearchConfig::default()
+        };
+        let mut search = RunningSearch::start(Board::default(), 1, config, true, report);
+        // Depth 1 is over long before this, but the move waits.
+        let early = receiver.recv_timeout(Duration::from_millis(300));
+        assert!(early.is_err(), "bestmove while pondering: {:?}", early);
+        search.ponderhit();
+        search.finish(false);
+        let lines: Vec<String> = receiver.try_iter().collect();
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert!(lines[0].starts_with("bestmove "), "{:?}", lines);
+    }
+
+    #[test]
+    fn chess960_castle_is_king_takes_rook() {
+        let fen = "fen nrkbbqrn/pppppppp/8/8/8/8/PPPPPPPP/NRK2QRN w GBgb - 0 1";
+        let args = format!("{} moves c1b1", fen);
+        assert!(parse_position(&args, false).is_err());
+        let position = parse_position(&args, true).unwrap();
+        let board = position.board;
+        // Queen side: the king lands on c1 and the rook on d1.
+        assert_eq!(board.piece_on(Square::C1), Some(Piece::King));
+        assert_eq!(board.piece_on(Square::D1), Some(Piece::Rook));
+        assert_eq!(board.piece_on(Square::B1), None);
+        assert_eq!(position.castling.field(), "gb");
+    }
+
+    #[test]
+    fn analyse_mode_plays_on_in_a_lost_position() {
+        let mut config = SearchConfig::default();
+        let mut chess960 = false;
+        set_option(
+            "name UCI_AnalyseMode value true",
+            &mut config,
+            &mut chess960,
+        );
+        assert!(config.analyse_mode);
+        // White has a bare king against two queens.
+        let board = Board::from_str("k7/8/8/3qq3/8/8/8/7K w - - 0 1").unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let report = move |line| sender.send(line).unwrap();
+        RunningSearch::start(board, 4, config, false, report).finish(false);
+        let lines: Vec<String> = receiver.try_iter().collect();
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        let mv = lines[0].split_whitespace().nth(1).unwrap();
+        assert!(board.legal(ChessMove::from_str(mv).unwrap()), "{:?}", lines);
+    }
+
+    #[test]
+    fn uci_reply_opens_with_the_id_and_closes_with_uciok() {
+        let id = EngineId {
+            name: "Scacchi".to_string(),
+            author: "Someone".to_string(),
+        };
+        let lines = handshake(&SearchConfig::default(), &id);
+        assert!(lines[0].starts_with("id name Scacchi "));
+        assert_eq!(lines[1], "id author Someone");
+        assert_eq!(lines.last().unwrap(), "uciok");
+        for line in &lines[2..lines.len() - 1] {
+            assert!(line.starts_with("option name "), "{}", line);
+        }
+    }
+
+    #[test]
+    fn id_line_names_the_configured_engine_and_version() {
+        let id = EngineId {
+            name: "My Engine".to_string(),
+            author: "Someone".to_string(),
+        };
+        let lines = handshake(&SearchConfig::default(), &id);
+        let expected = format!("id name My Engine {}", env!("CARGO_PKG_VERSION"));
+        assert_eq!(lines.iter().filter(|line| **line == expected).count(), 1);
+    }
+}
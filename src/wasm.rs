@@ -0,0 +1,50 @@
+use crate::engine::{self, SearchLimits};
+use wasm_bindgen::prelude::*;
+
+/// The engine as JavaScript sees it: `new Engine()`, `setPosition(fen)`,
+/// then `bestMove(depth, ms)` for a move in UCI notation.
+#[wasm_bindgen]
+pub struct Engine {
+    engine: engine::Engine,
+}
+
+impl Default for Engine {
+    fn default() -> Engine {
+        Engine::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Engine {
+    /// An engine at the starting position with the default settings.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Engine {
+        Engine {
+            engine: engine::Engine::new(),
+        }
+    }
+
+    /// Sets up the position in `fen`; a FEN that can't be read throws.
+    #[wasm_bindgen(js_name = setPosition)]
+    pub fn set_position(&mut self, fen: &str) -> Result<(), JsValue> {
+        self.engine
+            .set_position(fen)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Searches to `depth` plies or for `ms` milliseconds, whichever ends
+    /// it first; with neither it searches to the default depth. Gives
+    /// `0000` when there is no legal move.
+    #[wasm_bindgen(js_name = bestMove)]
+    pub fn best_move(&self, depth: Option<i8>, ms: Option<u32>) -> String {
+        let limits = SearchLimits {
+            depth,
+            movetime: ms.map(u64::from),
+            ..SearchLimits::default()
+        };
+        match self.engine.search(&limits).best_move {
+            Some(mv) => mv.to_string(),
+            None => "0000".to_string(),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::game_state::GameState;
+use crate::input::CommandInput;
+use crate::search::{game_search_config, search, SearchConfig};
+use crate::uci::EngineId;
+use crate::{load_game, time_manager, SearchLimits, MAX_DEPTH, STARTING_FEN};
+use chess::{BoardStatus, ChessMove, Color};
+use std::str::FromStr;
+
+/// How long the engine may think, as set by `level`, `st`, `sd`, `time`
+/// and `otim`.
+#[derive(Default)]
+struct Clock {
+    /// Moves per time control, or `None` for the whole game.
+    moves_per_session: Option<u64>,
+    /// Added to the clock after each move, in milliseconds.
+    increment: u64,
+    /// Fixed time per move from `st`, in milliseconds.
+    movetime: Option<u64>,
+    /// Deepest iteration from `sd`.
+    depth: Option<i8>,
+    /// The engine's time left from `time`, in milliseconds.
+    time_left: Option<u64>,
+}
+
+impl Clock {
+    /// Limits for the engine's next move, `moves_played` moves into the
+    /// game for its side.
+    fn limits(&self, moves_played: u64) -> SearchLimits {
+        let moves_to_go = self.moves_per_session.map(|mps| mps - moves_played % mps);
+        SearchLimits {
+            depth: self.depth,
+            movetime: self.movetime.or_else(|| {
+                self.time_left
+                    .map(|left| time_manager::clock_budget(left, self.increment, moves_to_go))
+            }),
+            ..SearchLimits::default()
+        }
+    }
+}
+
+/// Parses `level MPS BASE INC`, where the base time may be minutes or
+/// `minutes:seconds` and the increment is in seconds. Only the moves per
+/// session and the increment matter, since `time` keeps the clock.
+fn parse_level(args: &str, clock: &mut Clock) {
+    let fields: Vec<&str> = args.split_whitespace().collect();
+    if let [mps, _base, inc] = fields.as_slice() {
+        clock.moves_per_session = mps.parse().ok().filter(|&mps| mps > 0);
+        clock.increment = inc.parse::<f64>().map_or(0, |s| (s * 1000.0) as u64);
+        clock.movetime = None;
+    }
+}
+
+/// Centiseconds, as `time` and `otim` send them, in milliseconds.
+fn centiseconds(args: &str) -> Option<u64> {
+    args.trim().parse::<u64>().ok().map(|cs| cs * 10)
+}
+
+/// The result to announce if the game in `game` is over, with the reason.
+fn game_over(game: &GameState) -> Option<String> {
+    let board = game.board();
+    match (board.status(), board.side_to_move()) {
+        (BoardStatus::Checkmate, Color::White) => Some("0-1 {Black mates}".to_string()),
+        (BoardStatus::Checkmate, Color::Black) => Some("1-0 {White mates}".to_string()),
+        (BoardStatus::Stalemate, _) => Some("1/2-1/2 {Stalemate}".to_string()),
+        (BoardStatus::Ongoing, _) => game
+            .draw_by_rule()
+            .map(|rule| format!("1/2-1/2 {{Draw by {}}}", rule)),
+    }
+}
+
+/// Starts a game from `fen`.
+fn new_game(fen: &str) -> Result<GameState, String> {
+    load_game(fen, false)
+}
+
+/// Searches the current position and plays the move found, printing it,
+/// or announces the result if the game is already over.
+fn play_engine_move(game: &mut GameState, clock: &Clock, config: &SearchConfig, default_depth: i8) {
+    if let Some(result) = game_over(game) {
+        println!("{}", result);
+        return;
+    }
+    let limits = clock.limits(game.plies_played() as u64 / 2);
+    let config = game_search_config(game, &limits.apply(config));
+    let depth = limits.depth_or(default_depth).min(MAX_DEPTH);
+    match search(&game.board(), depth, &config).best_move {
+        Some(mv) => {
+            game.make_move(mv);
+            println!("move {}", mv);
+            if let Some(result) = game_over(game) {
+                println!("{}", result);
+            }
+        }
+        None => println!("resign"),
+    }
+}
+
+/// Speaks the Chess Engine Communication Protocol, as XBoard and WinBoard
+/// do, on stdin and stdout until `quit` or end of input. The engine
+/// thinks between commands, so a move can't be interrupted with `?`.
+pub fn run(config: &SearchConfig, default_depth: i8, id: &EngineId) {
+    let mut game = new_game(STARTING_FEN).expect("starting position");
+    let mut clock = Clock::default();
+    // The side the engine plays, or `None` in force mode.
+    let mut engine_side = Some(Color::Black);
+    let mut input = CommandInput::stdin();
+    while let Some(line) = input.next_line() {
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "xboard" | "accepted" | "rejected" | "post" | "nopost" | "hard" | "easy" | "random"
+            | "computer" | "white" | "black" | "name" | "rating" | "" => {}
+            "protover" => println!(
+                "feature myname=\"{} {}\" usermove=1 setboard=1 ping=1 colors=0 \
+                 sigint=0 sigterm=0 analyze=0 done=1",
+                id.name,
+                env!("CARGO_PKG_VERSION")
+            ),
+            "new" => {
+                game = new_game(STARTING_FEN).expect("starting position");
+                clock = Clock::default();
+                engine_side = Some(Color::Black);
+            }
+            "setboard" => match new_game(args) {
+                Ok(started) => game = started,
+                Err(e) => println!("tellusererror Illegal position: {}", e),
+            },
+            "force" | "result" => engine_side = None,
+            "go" => {
+                engine_side = Some(game.board().side_to_move());
+                play_engine_move(&mut game, &clock, config, default_depth);
+            }
+            "level" => parse_level(args, &mut clock),
+            "st" => clock.movetime = args.trim().parse::<u64>().ok().map(|s| s * 1000),
+            "sd" => clock.depth = args.trim().parse::<i8>().ok(),
+            "time" => clock.time_left = centiseconds(args),
+            // The engine budgets only its own time.
+            "otim" => {}
+            "ping" => println!("pong {}", args.trim()),
+            "undo" => {
+                game.takeback(1);
+            }
+            "remove" => {
+                game.takeback(2);
+            }
+            "quit" => return,
+            _ => {
+                // Protocol 1 sends moves bare, without `usermove`.
+                let text = if command == "usermove" { args } else { command };
+                match ChessMove::from_str(text.trim()) {
+                    Ok(mv) if game.board().legal(mv) => {
+                        game.make_move(mv);
+                        if engine_side == Some(game.board().side_to_move()) {
+                            play_engine_move(&mut game, &clock, config, default_depth);
+                        }
+                    }
+                    Ok(_) => println!("Illegal move: {}", text.trim()),
+                    Err(_) if command == "usermove" => println!("Illegal move: {}", text.trim()),
+                    Err(_) => println!("Error (unknown command): {}", command),
+                }
+            }
+        }
+    }
+}